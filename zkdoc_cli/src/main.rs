@@ -5,18 +5,25 @@ use colored::*;
 use serde::{Deserialize, Serialize};
 use spinners::{Spinner, Spinners};
 use std::io::prelude::*;
+use std::path::Path;
 use std::time::{Duration, Instant};
-use std::{fs, fs::File, process, str, str::FromStr};
+use std::{fs, fs::File, process, str};
+use zkdoc_sdk::holder::HolderKey;
 use zkdoc_sdk::services::{
-    generate_proof, get_file_commitment_and_selected_row, get_selected_row, verify_correct_selector,
+    generate_proof_cancellable, get_file_commitment_and_selected_row, get_holder_bound_commitment,
+    get_selected_row, verify_batch, verify_correct_selector, verify_holder_bound_selector,
+    BatchVerificationItem, CancellationToken, ProvingPhase,
 };
 const ROW: usize = 10;
+/// Spinner style of every step
+const SPINNER: Spinners = Spinners::Dots12;
 
 #[derive(Deserialize)]
 struct GenerateCommitmentAndProofRequest {
     row_titles: [String; ROW],
     row_contents: [String; ROW],
     row_selectors: [u64; ROW],
+    holder_public_key: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -25,6 +32,8 @@ struct ProofVerificationRequest {
     row_title: String,
     row_content: String,
     commitment: String,
+    #[serde(default)]
+    holder_bound: bool,
 }
 
 fn save_to_file(filename: &str, data: &str) -> Result<(), std::io::Error> {
@@ -33,6 +42,91 @@ fn save_to_file(filename: &str, data: &str) -> Result<(), std::io::Error> {
     Ok(())
 }
 
+/// Reads proofs to verify from a directory of JSON files, or from a JSONL file with one proof
+/// per line. Each entry is named after its file or line for reporting.
+fn read_batch_entries(input: &str) -> Vec<(String, ProofVerificationRequest)> {
+    let parse = |name: &str, contents: &str| {
+        serde_json::from_str::<ProofVerificationRequest>(contents).unwrap_or_else(|_| {
+            panic!(
+                "{}: {}",
+                "Failed to deserialize entry, refer to the sample 'verify-proof.json' as reference"
+                    .red(),
+                name.red()
+            )
+        })
+    };
+
+    let path = Path::new(input);
+    if path.is_dir() {
+        let mut files = fs::read_dir(path)
+            .unwrap_or_else(|_| {
+                panic!(
+                    "{}",
+                    "Something went wrong reading the directory"
+                        .red()
+                        .to_string()
+                )
+            })
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        files
+            .into_iter()
+            .map(|file| {
+                let name = file.display().to_string();
+                let contents = fs::read_to_string(&file).unwrap_or_else(|_| {
+                    panic!(
+                        "{}",
+                        "Something went wrong reading the file".red().to_string()
+                    )
+                });
+                let entry = parse(&name, &contents);
+                (name, entry)
+            })
+            .collect()
+    } else {
+        let contents = fs::read_to_string(path).unwrap_or_else(|_| {
+            panic!(
+                "{}",
+                "Something went wrong reading the file".red().to_string()
+            )
+        });
+
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let name = format!("{input}:{}", i + 1);
+                let entry = parse(&name, line);
+                (name, entry)
+            })
+            .collect()
+    }
+}
+
+fn phase_message(phase: ProvingPhase) -> String {
+    format!(
+        "[{}/{}] {}",
+        phase.index() + 1,
+        ProvingPhase::ALL.len(),
+        phase.description()
+    )
+}
+
+/// Shows a spinner for the current proving phase, keeping the previous phases on screen.
+fn render_phase(current: &mut Option<(Spinner, ProvingPhase)>, next: Option<ProvingPhase>) {
+    if let Some((mut sp, phase)) = current.take() {
+        sp.stop_and_persist(&"✔".green().to_string(), phase_message(phase));
+    }
+    *current = next.map(|phase| {
+        let sp = Spinner::with_timer(SPINNER, format!("{}...", phase_message(phase)));
+        (sp, phase)
+    });
+}
+
 fn main() {
     let cmd = clap::Command::new("zkdoc-cli")
         .about("ZKDoc CLI")
@@ -58,6 +152,34 @@ fn main() {
                         .long("input-file")
                         .required(true)
                         .help("Input file needs to be a valid JSON file"),
+                )
+                .arg(
+                    Arg::new("holder-key")
+                        .short('k')
+                        .long("holder-key")
+                        .help("Holder key file, required if the document is holder bound"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-batch")
+                .about("Verifies many proofs at once")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .long("input")
+                        .required(true)
+                        .help("Directory of JSON files or a JSONL file with one proof per line"),
+                ),
+        )
+        .subcommand(
+            Command::new("gen-holder-key")
+                .about("Generates a holder key to bind documents to")
+                .arg(
+                    Arg::new("output-file")
+                        .short('o')
+                        .long("output-file")
+                        .default_value("holder.key")
+                        .help("File to save the holder secret to"),
                 ),
         )
         .subcommand(
@@ -71,9 +193,6 @@ fn main() {
                         .help("Input file needs to be a valid JSON file"),
                 ),
         );
-    // Choose a spinner style
-    let spinner_name = "Dots12".to_string();
-
     let matches = cmd.get_matches();
     /// Matches subcommand and runs the corresponding functions
     match matches.subcommand() {
@@ -93,16 +212,21 @@ fn main() {
                     .unwrap_or_else(|_| { panic!("{}", "Failed to deserialize JSON file.\nRefer to the sample 'gen-commitment.json' as reference".red().to_string()) });
             println!("{}: {}", "Input file".blue().bold(), input_file.blue());
             // Start the spinner animation
-            let mut sp = Spinner::with_timer(
-                Spinners::from_str(&spinner_name).unwrap(),
-                "ZK circuit is running...".into(),
-            );
+            let mut sp = Spinner::with_timer(SPINNER, "ZK circuit is running...".into());
             /// Calling the function to generate the commitment
-            let commitment: String = get_file_commitment_and_selected_row(
-                json_contents.row_titles.to_owned(),
-                json_contents.row_contents.to_owned(),
-                json_contents.row_selectors.to_owned(),
-            );
+            let commitment: String = match json_contents.holder_public_key {
+                Some(holder_public_key) => get_holder_bound_commitment(
+                    json_contents.row_titles.to_owned(),
+                    json_contents.row_contents.to_owned(),
+                    holder_public_key,
+                )
+                .unwrap_or_else(|| panic!("{}", "Invalid holder public key".red().to_string())),
+                None => get_file_commitment_and_selected_row(
+                    json_contents.row_titles.to_owned(),
+                    json_contents.row_contents.to_owned(),
+                    json_contents.row_selectors.to_owned(),
+                ),
+            };
             sp.stop_with_newline();
             println!("{}: {}", "Commitment".green().bold(), commitment.green());
             /// Save value to file
@@ -134,18 +258,43 @@ fn main() {
                 serde_json::from_str::<GenerateCommitmentAndProofRequest>(&contents)
                     .unwrap_or_else(|_| { panic!("{}", "Failed to deserialize JSON file.\nRefer to the sample 'gen-proof.json' as reference".red().to_string()) });
             println!("{}: {}", "Input file".blue().bold(), input_file.blue());
-            // Start the spinner animation
-            let mut sp = Spinner::with_timer(
-                Spinners::from_str(&spinner_name).unwrap(),
-                "ZK circuit is running...".into(),
-            );
-            /// Calling the function to generate the proof
-            let proof = generate_proof(
+            /// Load the holder key for holder bound documents
+            let holder_key = matches
+                .get_one::<String>("holder-key")
+                .map(|holder_key_file| {
+                    HolderKey::load(holder_key_file).unwrap_or_else(|_| {
+                        panic!("{}", "Failed to load the holder key file".red().to_string())
+                    })
+                });
+            /// Cancel proving on Ctrl-C
+            let cancellation = CancellationToken::new();
+            let on_ctrl_c = cancellation.clone();
+            ctrlc::set_handler(move || on_ctrl_c.cancel()).unwrap_or_else(|_| {
+                panic!("{}", "Failed to set the Ctrl-C handler".red().to_string())
+            });
+            /// Calling the function to generate the proof, with a spinner per proving phase
+            let mut current_phase = None;
+            let proof = generate_proof_cancellable(
                 json_contents.row_titles.to_owned(),
                 json_contents.row_contents.to_owned(),
                 json_contents.row_selectors.to_owned(),
+                holder_key.as_ref(),
+                &cancellation,
+                |phase| render_phase(&mut current_phase, Some(phase)),
             );
-            sp.stop_with_newline();
+            let proof = match proof {
+                Ok(proof) => {
+                    render_phase(&mut current_phase, None);
+                    proof
+                }
+                Err(_) => {
+                    if let Some((mut sp, phase)) = current_phase.take() {
+                        sp.stop_and_persist(&"✘".red().to_string(), phase_message(phase));
+                    }
+                    println!("{}", "Proof generation cancelled".red().bold());
+                    process::exit(130);
+                }
+            };
             /// Save value to file
             let proof_string = serde_json::to_string(&proof).unwrap();
             match save_to_file("proof.txt", &proof_string) {
@@ -173,16 +322,17 @@ fn main() {
                     .unwrap_or_else(|_| { panic!("{}", "Failed to deserialize JSON file.\nRefer to the sample 'gen-proof.json' as reference".red().to_string()) });
             println!("{}: {}", "Input file".blue().bold(), input_file.blue());
             // Start the spinner animation
-            let mut sp = Spinner::with_timer(
-                Spinners::from_str(&spinner_name).unwrap(),
-                "ZK circuit is running...".into(),
-            );
+            let mut sp = Spinner::with_timer(SPINNER, "ZK circuit is running...".into());
             /// Calling the function to verify the proof
             let row_accumulator = get_selected_row(
                 json_contents.row_title.to_owned(),
                 json_contents.row_content,
             );
-            let is_valid = verify_correct_selector(
+            let verify = match json_contents.holder_bound {
+                true => verify_holder_bound_selector,
+                false => verify_correct_selector,
+            };
+            let is_valid = verify(
                 json_contents.commitment.to_owned(),
                 row_accumulator,
                 json_contents.proof,
@@ -197,6 +347,73 @@ fn main() {
                 false => println!("{}: {}", "Proof verification".green().bold(), "false".red()),
             }
         }
+        Some(("verify-batch", matches)) => {
+            println!("{}", "## Verify batch ##".cyan().bold());
+            /// Get the input path
+            let input = matches.get_one::<String>("input").unwrap();
+            /// Parse the proofs to verify
+            let entries = read_batch_entries(input);
+            println!(
+                "{}: {} ({} proofs)",
+                "Input".blue().bold(),
+                input.blue(),
+                entries.len()
+            );
+            // Start the spinner animation
+            let mut sp = Spinner::with_timer(SPINNER, "ZK circuit is running...".into());
+            /// Calling the function to verify the proofs
+            let items = entries
+                .iter()
+                .map(|(_, json_contents)| BatchVerificationItem {
+                    commitment: json_contents.commitment.to_owned(),
+                    row_accumulator: get_selected_row(
+                        json_contents.row_title.to_owned(),
+                        json_contents.row_content.to_owned(),
+                    ),
+                    proof: json_contents.proof.to_owned(),
+                    holder_bound: json_contents.holder_bound,
+                })
+                .collect::<Vec<_>>();
+            let result = verify_batch(&items);
+            sp.stop_with_newline();
+            for i in result.failed.iter() {
+                println!("{}: {}", "Failed".red().bold(), entries[*i].0.red());
+            }
+            match result.is_valid() {
+                true => println!(
+                    "{}: {}",
+                    "Batch verification result".green().bold(),
+                    "true".green()
+                ),
+                false => println!(
+                    "{}: {} ({} of {} failed)",
+                    "Batch verification result".green().bold(),
+                    "false".red(),
+                    result.failed.len(),
+                    entries.len()
+                ),
+            }
+        }
+        Some(("gen-holder-key", matches)) => {
+            println!("{}", "## Generate holder key ##".cyan().bold());
+            /// Get the output file path
+            let output_file = matches.get_one::<String>("output-file").unwrap();
+            let holder_key = HolderKey::generate();
+            println!(
+                "{}: {}",
+                "Holder public key".green().bold(),
+                holder_key.public_key().green()
+            );
+            /// Save the secret to file
+            match holder_key.save(output_file) {
+                Ok(_) => println!("{}: {}", "Output file".green().bold(), output_file.green()),
+                Err(_) => println!(
+                    "{}: {}",
+                    "Failed to save holder key to file".red().bold(),
+                    output_file.red()
+                ),
+            }
+        }
         None => {
             println!("No subcommand was used");
             process::exit(1);
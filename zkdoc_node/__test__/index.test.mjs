@@ -0,0 +1,60 @@
+import assert from 'node:assert/strict'
+import { before, test } from 'node:test'
+
+import zkdoc from '../index.js'
+
+const rowTitles = ['name', 'age', '-', '-', '-', '-', '-', '-', '-', '-']
+const rowContents = ['alice', '42', '-', '-', '-', '-', '-', '-', '-', '-']
+const rowSelectors = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+
+let proof
+
+before(async () => {
+  proof = await zkdoc.prove(rowTitles, rowContents, rowSelectors)
+})
+
+test('commitment', () => {
+  const commitment = zkdoc.getCommitment(rowTitles, rowContents)
+
+  assert.match(commitment, /^0x[0-9a-f]{64}$/)
+  assert.equal(commitment, zkdoc.getCommitment([...rowTitles], [...rowContents]))
+  assert.throws(() => zkdoc.getCommitment(rowTitles.slice(2), rowContents), /expected 10 rows/)
+})
+
+test('row accumulator', () => {
+  assert.equal(
+    zkdoc.getRowAccumulator([{ title: 'name', content: 'alice' }]),
+    zkdoc.getSelectedRow('name', 'alice'),
+  )
+})
+
+test('prove and verify', async () => {
+  const commitment = zkdoc.getCommitment(rowTitles, rowContents)
+
+  assert.ok(Buffer.isBuffer(proof))
+  assert.equal(await zkdoc.verify(commitment, zkdoc.getSelectedRow('name', 'alice'), proof), true)
+  assert.equal(await zkdoc.verify(commitment, zkdoc.getSelectedRow('name', 'bob'), proof), false)
+  assert.throws(() => zkdoc.verify('0x1234', zkdoc.getSelectedRow('name', 'alice'), proof), /invalid commitment/)
+})
+
+test('verify runs off the event loop', async () => {
+  const commitment = zkdoc.getCommitment(rowTitles, rowContents)
+  let ticks = 0
+  const timer = setInterval(() => ticks++, 10)
+
+  try {
+    assert.equal(await zkdoc.verify(commitment, zkdoc.getSelectedRow('name', 'alice'), proof), true)
+  } finally {
+    clearInterval(timer)
+  }
+  assert.ok(ticks > 1)
+})
+
+test('holder key', () => {
+  const holderSecret = zkdoc.generateHolderKey()
+  const holderPublicKey = zkdoc.getHolderPublicKey(holderSecret)
+
+  assert.notEqual(zkdoc.getCommitment(rowTitles, rowContents, holderPublicKey), zkdoc.getCommitment(rowTitles, rowContents))
+  assert.throws(() => zkdoc.getHolderPublicKey('not a key'), /invalid holder secret/)
+  assert.throws(() => zkdoc.prove(rowTitles, rowContents, rowSelectors, 'not a key'), /invalid holder secret/)
+})
@@ -0,0 +1,24 @@
+/* Type definitions for the zkdoc addon, keep in sync with src/lib.rs. */
+
+export interface RevealedRow {
+  title: string
+  content: string
+}
+/**
+ * Generates a proof revealing the rows whose selector is 1, on the libuv threadpool. Passing
+ * the holder secret generates a holder bound proof.
+ */
+export function prove(rowTitles: Array<string>, rowContents: Array<string>, rowSelectors: Array<number>, holderSecret?: string | undefined | null): Promise<Buffer>
+/**
+ * Verifies a proof on the libuv threadpool, see `getSelectedRow` and `getRowAccumulator` for
+ * the row accumulator.
+ */
+export function verify(commitment: string, rowAccumulator: string, proof: Buffer, holderBound?: boolean | undefined | null): Promise<boolean>
+/** Computes the commitment of a document, bound to the holder if a public key is passed. */
+export function getCommitment(rowTitles: Array<string>, rowContents: Array<string>, holderPublicKey?: string | undefined | null): string
+export function getSelectedRow(rowTitle: string, rowContent: string): string
+/** Row accumulator of several revealed rows. */
+export function getRowAccumulator(revealedRows: Array<RevealedRow>): string
+/** Generates a new holder key, returning its hex encoded secret. */
+export function generateHolderKey(): string
+export function getHolderPublicKey(holderSecret: string): string
@@ -0,0 +1,2 @@
+// Loads the addon built by `npm run build`, see README.md.
+module.exports = require('./zkdoc.node')
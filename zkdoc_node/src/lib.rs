@@ -0,0 +1,197 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use zkdoc_sdk::holder::HolderKey;
+use zkdoc_sdk::services::{self, ROW};
+use zkdoc_sdk::utils::conversion::fp_from_hex;
+
+pub struct ProveTask {
+    row_titles: [String; ROW],
+    row_contents: [String; ROW],
+    row_selectors: [u64; ROW],
+    holder_key: Option<HolderKey>,
+}
+
+impl Task for ProveTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let row_titles = self.row_titles.clone();
+        let row_contents = self.row_contents.clone();
+        catch_panic(|| match &self.holder_key {
+            Some(holder_key) => services::generate_holder_bound_proof(
+                row_titles,
+                row_contents,
+                self.row_selectors,
+                holder_key,
+            ),
+            None => services::generate_proof(row_titles, row_contents, self.row_selectors),
+        })
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output.into())
+    }
+}
+
+pub struct VerifyTask {
+    commitment: String,
+    row_accumulator: String,
+    proof: Vec<u8>,
+    holder_bound: bool,
+}
+
+impl Task for VerifyTask {
+    type Output = bool;
+    type JsValue = bool;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let commitment = self.commitment.clone();
+        let row_accumulator = self.row_accumulator.clone();
+        let proof = self.proof.clone();
+        catch_panic(|| {
+            if self.holder_bound {
+                services::verify_holder_bound_selector(commitment, row_accumulator, proof)
+            } else {
+                services::verify_correct_selector(commitment, row_accumulator, proof)
+            }
+        })
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// Generates a proof revealing the rows whose selector is 1, on the libuv threadpool. Passing
+/// the holder secret generates a holder bound proof.
+#[napi(ts_return_type = "Promise<Buffer>")]
+pub fn prove(
+    row_titles: Vec<String>,
+    row_contents: Vec<String>,
+    row_selectors: Vec<u32>,
+    holder_secret: Option<String>,
+) -> Result<AsyncTask<ProveTask>> {
+    let row_selectors: [u32; ROW] = row_selectors
+        .try_into()
+        .map_err(|_| invalid_arg(format!("expected {ROW} row selectors")))?;
+    let holder_key = holder_secret
+        .map(|secret| {
+            HolderKey::from_secret_hex(&secret).ok_or_else(|| invalid_arg("invalid holder secret"))
+        })
+        .transpose()?;
+
+    Ok(AsyncTask::new(ProveTask {
+        row_titles: to_rows(row_titles)?,
+        row_contents: to_rows(row_contents)?,
+        row_selectors: row_selectors.map(u64::from),
+        holder_key,
+    }))
+}
+
+/// Verifies a proof on the libuv threadpool, see [`get_selected_row`] and
+/// [`get_row_accumulator`] for the row accumulator.
+#[napi(ts_return_type = "Promise<boolean>")]
+pub fn verify(
+    commitment: String,
+    row_accumulator: String,
+    proof: Buffer,
+    holder_bound: Option<bool>,
+) -> Result<AsyncTask<VerifyTask>> {
+    if fp_from_hex(&commitment).is_none() {
+        return Err(invalid_arg("invalid commitment"));
+    }
+    if fp_from_hex(&row_accumulator).is_none() {
+        return Err(invalid_arg("invalid row accumulator"));
+    }
+
+    Ok(AsyncTask::new(VerifyTask {
+        commitment,
+        row_accumulator,
+        proof: proof.to_vec(),
+        holder_bound: holder_bound.unwrap_or(false),
+    }))
+}
+
+/// Computes the commitment of a document, bound to the holder if a public key is passed.
+#[napi]
+pub fn get_commitment(
+    row_titles: Vec<String>,
+    row_contents: Vec<String>,
+    holder_public_key: Option<String>,
+) -> Result<String> {
+    let row_titles = to_rows(row_titles)?;
+    let row_contents = to_rows(row_contents)?;
+
+    match holder_public_key {
+        Some(holder_public_key) => {
+            services::get_holder_bound_commitment(row_titles, row_contents, holder_public_key)
+                .ok_or_else(|| invalid_arg("invalid holder public key"))
+        }
+        None => Ok(services::get_file_commitment_and_selected_row(
+            row_titles,
+            row_contents,
+            [0; ROW],
+        )),
+    }
+}
+
+#[napi]
+pub fn get_selected_row(row_title: String, row_content: String) -> String {
+    services::get_selected_row(row_title, row_content)
+}
+
+#[napi(object)]
+pub struct RevealedRow {
+    pub title: String,
+    pub content: String,
+}
+
+/// Row accumulator of several revealed rows.
+#[napi]
+pub fn get_row_accumulator(revealed_rows: Vec<RevealedRow>) -> String {
+    let revealed_rows: Vec<_> = revealed_rows
+        .into_iter()
+        .map(|row| (row.title, row.content))
+        .collect();
+    services::get_row_accumulator(&revealed_rows)
+}
+
+/// Generates a new holder key, returning its hex encoded secret.
+#[napi]
+pub fn generate_holder_key() -> String {
+    HolderKey::generate().secret()
+}
+
+#[napi]
+pub fn get_holder_public_key(holder_secret: String) -> Result<String> {
+    HolderKey::from_secret_hex(&holder_secret)
+        .map(|holder_key| holder_key.public_key())
+        .ok_or_else(|| invalid_arg("invalid holder secret"))
+}
+
+fn to_rows(rows: Vec<String>) -> Result<[String; ROW]> {
+    rows.try_into()
+        .map_err(|_| invalid_arg(format!("expected {ROW} rows")))
+}
+
+/// Runs `f` on the libuv threadpool, rejecting the Promise rather than aborting the process when
+/// it panics.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|err| {
+        let message = if let Some(message) = err.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = err.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_owned()
+        };
+        Error::new(Status::GenericFailure, message)
+    })
+}
+
+fn invalid_arg(reason: impl Into<String>) -> Error {
+    Error::new(Status::InvalidArg, reason.into())
+}
@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use zkdoc_sdk::holder::HolderKey;
+use zkdoc_sdk::services::{self, ROW};
+use zkdoc_sdk::utils::conversion::fp_from_hex;
+
+/// A document of 10 `(title, content)` rows.
+#[pyclass(module = "zkdoc")]
+#[derive(Clone)]
+struct Document {
+    rows: Vec<(String, String)>,
+}
+
+impl Document {
+    fn columns(&self) -> ([String; ROW], [String; ROW]) {
+        let titles: Vec<_> = self.rows.iter().map(|(title, _)| title.clone()).collect();
+        let contents: Vec<_> = self
+            .rows
+            .iter()
+            .map(|(_, content)| content.clone())
+            .collect();
+        (
+            titles.try_into().expect("rows are checked on creation"),
+            contents.try_into().expect("rows are checked on creation"),
+        )
+    }
+}
+
+#[pymethods]
+impl Document {
+    #[new]
+    fn new(rows: Vec<(String, String)>) -> PyResult<Self> {
+        if rows.len() != ROW {
+            return Err(PyValueError::new_err(format!(
+                "a document has {ROW} rows, got {}",
+                rows.len()
+            )));
+        }
+        Ok(Self { rows })
+    }
+
+    #[getter]
+    fn rows(&self) -> Vec<(String, String)> {
+        self.rows.clone()
+    }
+
+    /// Commitment of the document, published by the issuer.
+    fn commitment(&self) -> String {
+        let (titles, contents) = self.columns();
+        services::get_file_commitment_and_selected_row(titles, contents, [0; ROW])
+    }
+
+    /// Commitment of the document bound to the holder public key.
+    fn holder_bound_commitment(&self, holder_public_key: String) -> PyResult<String> {
+        let (titles, contents) = self.columns();
+        services::get_holder_bound_commitment(titles, contents, holder_public_key)
+            .ok_or_else(|| PyValueError::new_err("invalid holder public key"))
+    }
+
+    /// Proves the rows at `selected_rows`. Passing the holder secret generates a holder bound
+    /// proof. The GIL is released while proving.
+    #[pyo3(signature = (selected_rows, holder_secret=None))]
+    fn prove(
+        &self,
+        py: Python<'_>,
+        selected_rows: Vec<usize>,
+        holder_secret: Option<String>,
+    ) -> PyResult<Proof> {
+        let mut row_selectors = [0; ROW];
+        for &row in &selected_rows {
+            *row_selectors
+                .get_mut(row)
+                .ok_or_else(|| PyIndexError::new_err(format!("row {row} out of range")))? = 1;
+        }
+        let holder_key = holder_secret
+            .map(|secret| {
+                HolderKey::from_secret_hex(&secret)
+                    .ok_or_else(|| PyValueError::new_err("invalid holder secret"))
+            })
+            .transpose()?;
+
+        let (titles, contents) = self.columns();
+        let commitment = match &holder_key {
+            Some(holder_key) => services::get_holder_bound_commitment(
+                titles.clone(),
+                contents.clone(),
+                holder_key.public_key(),
+            )
+            .expect("holder keys have valid public keys"),
+            None => services::get_file_commitment_and_selected_row(
+                titles.clone(),
+                contents.clone(),
+                [0; ROW],
+            ),
+        };
+        let proof = py.allow_threads(|| match &holder_key {
+            Some(holder_key) => {
+                services::generate_holder_bound_proof(titles, contents, row_selectors, holder_key)
+            }
+            None => services::generate_proof(titles, contents, row_selectors),
+        });
+
+        Ok(Proof {
+            commitment,
+            revealed_rows: (0..ROW)
+                .filter(|&row| row_selectors[row] == 1)
+                .map(|row| self.rows[row].clone())
+                .collect(),
+            proof,
+            holder_bound: holder_key.is_some(),
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Document({:?})", self.rows)
+    }
+}
+
+/// A proof revealing some rows of a committed document.
+#[pyclass(module = "zkdoc")]
+#[derive(Clone)]
+struct Proof {
+    #[pyo3(get)]
+    commitment: String,
+    #[pyo3(get)]
+    revealed_rows: Vec<(String, String)>,
+    proof: Vec<u8>,
+    #[pyo3(get)]
+    holder_bound: bool,
+}
+
+#[pymethods]
+impl Proof {
+    #[new]
+    #[pyo3(signature = (commitment, revealed_rows, proof, holder_bound=false))]
+    fn new(
+        commitment: String,
+        revealed_rows: Vec<(String, String)>,
+        proof: Vec<u8>,
+        holder_bound: bool,
+    ) -> PyResult<Self> {
+        if fp_from_hex(&commitment).is_none() {
+            return Err(PyValueError::new_err("invalid commitment"));
+        }
+
+        Ok(Self {
+            commitment,
+            revealed_rows,
+            proof,
+            holder_bound,
+        })
+    }
+
+    #[getter]
+    fn proof(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.proof)
+    }
+
+    /// Accumulated hash of the revealed rows, the public input along with the commitment.
+    #[getter]
+    fn row_accumulator(&self) -> String {
+        services::get_row_accumulator(&self.revealed_rows)
+    }
+
+    /// Verifies the proof against the commitment and the revealed rows. The GIL is released
+    /// while verifying.
+    fn verify(&self, py: Python<'_>) -> bool {
+        let commitment = self.commitment.clone();
+        let row_accumulator = self.row_accumulator();
+        let proof = self.proof.clone();
+        let holder_bound = self.holder_bound;
+
+        py.allow_threads(|| {
+            if holder_bound {
+                services::verify_holder_bound_selector(commitment, row_accumulator, proof)
+            } else {
+                services::verify_correct_selector(commitment, row_accumulator, proof)
+            }
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Proof(commitment={:?}, revealed_rows={:?}, holder_bound={})",
+            self.commitment,
+            self.revealed_rows,
+            if self.holder_bound { "True" } else { "False" }
+        )
+    }
+}
+
+/// Row hash of a single revealed row.
+#[pyfunction]
+fn get_selected_row(row_title: String, row_content: String) -> String {
+    services::get_selected_row(row_title, row_content)
+}
+
+/// Generates a new holder key, returning its hex encoded secret.
+#[pyfunction]
+fn generate_holder_key() -> String {
+    HolderKey::generate().secret()
+}
+
+/// Derives the holder public key, handed to the issuer, from the holder secret.
+#[pyfunction]
+fn holder_public_key(holder_secret: String) -> PyResult<String> {
+    HolderKey::from_secret_hex(&holder_secret)
+        .map(|holder_key| holder_key.public_key())
+        .ok_or_else(|| PyValueError::new_err("invalid holder secret"))
+}
+
+#[pymodule]
+fn zkdoc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ROWS", ROW)?;
+    m.add_class::<Document>()?;
+    m.add_class::<Proof>()?;
+    m.add_function(wrap_pyfunction!(get_selected_row, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_holder_key, m)?)?;
+    m.add_function(wrap_pyfunction!(holder_public_key, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+import threading
+
+import pytest
+
+import zkdoc
+
+ROWS = [("name", "alice"), ("age", "42")] + [("-", "-")] * (zkdoc.ROWS - 2)
+
+
+@pytest.fixture(scope="module")
+def proof():
+    return zkdoc.Document(ROWS).prove([0])
+
+
+def test_commitment():
+    document = zkdoc.Document(ROWS)
+
+    assert len(document) == zkdoc.ROWS
+    assert document.rows == ROWS
+    assert document.commitment().startswith("0x")
+    assert document.commitment() == zkdoc.Document(list(ROWS)).commitment()
+    assert document.commitment() != zkdoc.Document([("name", "bob")] + ROWS[1:]).commitment()
+
+
+def test_invalid_document():
+    with pytest.raises(ValueError):
+        zkdoc.Document(ROWS[:2])
+    with pytest.raises(IndexError):
+        zkdoc.Document(ROWS).prove([zkdoc.ROWS])
+
+
+def test_prove_and_verify(proof):
+    assert proof.commitment == zkdoc.Document(ROWS).commitment()
+    assert proof.revealed_rows == [("name", "alice")]
+    assert proof.row_accumulator == zkdoc.get_selected_row("name", "alice")
+    assert not proof.holder_bound
+    assert isinstance(proof.proof, bytes)
+    assert proof.verify()
+
+
+def test_verify_tampered(proof):
+    tampered = zkdoc.Proof(proof.commitment, [("name", "bob")], proof.proof)
+    assert not tampered.verify()
+
+    with pytest.raises(ValueError):
+        zkdoc.Proof("not a commitment", proof.revealed_rows, proof.proof)
+
+
+def test_verify_releases_gil(proof):
+    # another thread keeps running while the proof is verified
+    ticks = []
+    stop = threading.Event()
+
+    def tick():
+        while not stop.is_set():
+            ticks.append(1)
+            stop.wait(0.01)
+
+    thread = threading.Thread(target=tick)
+    thread.start()
+    try:
+        assert proof.verify()
+    finally:
+        stop.set()
+        thread.join()
+
+    assert len(ticks) > 1
+
+
+def test_holder_key():
+    holder_secret = zkdoc.generate_holder_key()
+    holder_public_key = zkdoc.holder_public_key(holder_secret)
+    document = zkdoc.Document(ROWS)
+
+    assert document.holder_bound_commitment(holder_public_key) != document.commitment()
+    with pytest.raises(ValueError):
+        zkdoc.holder_public_key("not a key")
+    with pytest.raises(ValueError):
+        document.prove([0], holder_secret="not a key")
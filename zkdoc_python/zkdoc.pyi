@@ -0,0 +1,32 @@
+from typing import List, Optional, Tuple
+
+ROWS: int
+
+class Document:
+    def __init__(self, rows: List[Tuple[str, str]]) -> None: ...
+    @property
+    def rows(self) -> List[Tuple[str, str]]: ...
+    def commitment(self) -> str: ...
+    def holder_bound_commitment(self, holder_public_key: str) -> str: ...
+    def prove(self, selected_rows: List[int], holder_secret: Optional[str] = None) -> Proof: ...
+    def __len__(self) -> int: ...
+
+class Proof:
+    commitment: str
+    revealed_rows: List[Tuple[str, str]]
+    proof: bytes
+    holder_bound: bool
+    def __init__(
+        self,
+        commitment: str,
+        revealed_rows: List[Tuple[str, str]],
+        proof: bytes,
+        holder_bound: bool = False,
+    ) -> None: ...
+    @property
+    def row_accumulator(self) -> str: ...
+    def verify(self) -> bool: ...
+
+def get_selected_row(row_title: str, row_content: str) -> str: ...
+def generate_holder_key() -> str: ...
+def holder_public_key(holder_secret: str) -> str: ...
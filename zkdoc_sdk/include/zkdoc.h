@@ -0,0 +1,107 @@
+#ifndef ZKDOC_H
+#define ZKDOC_H
+
+/* Generated with cbindgen, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum ZkdocStatus {
+  ZKDOC_STATUS_OK = 0,
+  // A required pointer was null
+  ZKDOC_STATUS_NULL_POINTER = 1,
+  // A string was not valid UTF-8
+  ZKDOC_STATUS_INVALID_UTF8 = 2,
+  // An argument was malformed, e.g. the wrong number of rows or an invalid key
+  ZKDOC_STATUS_INVALID_ARGUMENT = 3,
+  // The SDK panicked, which is a bug
+  ZKDOC_STATUS_PANIC = 4,
+} ZkdocStatus;
+
+// Borrowed bytes passed into the SDK.
+typedef struct ZkdocSlice {
+  const uint8_t *data;
+  size_t len;
+} ZkdocSlice;
+
+// Bytes returned by the SDK, to be released with [`zkdoc_buffer_free`].
+typedef struct ZkdocBuffer {
+  uint8_t *data;
+  size_t len;
+} ZkdocBuffer;
+
+// Computes the commitment of a document of 10 rows as a hex string. An empty
+// `holder_public_key` commits a plain document, otherwise a holder bound one.
+//
+// # Safety
+//
+// `row_titles` and `row_contents` must point to `row_count` valid slices, every slice must
+// point to `len` readable bytes, and `out_commitment` must be valid for writes.
+enum ZkdocStatus zkdoc_get_commitment(const struct ZkdocSlice *row_titles,
+                                      const struct ZkdocSlice *row_contents,
+                                      size_t row_count,
+                                      struct ZkdocSlice holder_public_key,
+                                      struct ZkdocBuffer *out_commitment);
+
+// Computes the row accumulator of a single revealed row, see [`services::get_selected_row`].
+//
+// # Safety
+//
+// Both slices must point to `len` readable bytes and `out_row_accumulator` must be valid for
+// writes.
+enum ZkdocStatus zkdoc_get_selected_row(struct ZkdocSlice row_title,
+                                        struct ZkdocSlice row_content,
+                                        struct ZkdocBuffer *out_row_accumulator);
+
+// Generates a proof revealing the rows whose selector is 1. An empty `holder_secret`
+// generates a plain proof, otherwise a holder bound one.
+//
+// # Safety
+//
+// `row_titles`, `row_contents` and `row_selectors` must point to `row_count` valid elements,
+// every slice must point to `len` readable bytes, and `out_proof` must be valid for writes.
+enum ZkdocStatus zkdoc_generate_proof(const struct ZkdocSlice *row_titles,
+                                      const struct ZkdocSlice *row_contents,
+                                      const uint64_t *row_selectors,
+                                      size_t row_count,
+                                      struct ZkdocSlice holder_secret,
+                                      struct ZkdocBuffer *out_proof);
+
+// Verifies a proof, writing whether it is valid to `out_valid`.
+//
+// # Safety
+//
+// Every slice must point to `len` readable bytes and `out_valid` must be valid for writes.
+enum ZkdocStatus zkdoc_verify_proof(struct ZkdocSlice commitment,
+                                    struct ZkdocSlice row_accumulator,
+                                    struct ZkdocSlice proof,
+                                    bool holder_bound,
+                                    bool *out_valid);
+
+// Generates a new holder key, writing its hex encoded secret to `out_secret`.
+//
+// # Safety
+//
+// `out_secret` must be valid for writes.
+enum ZkdocStatus zkdoc_generate_holder_key(struct ZkdocBuffer *out_secret);
+
+// Derives the holder public key from a hex encoded holder secret.
+//
+// # Safety
+//
+// `holder_secret` must point to `len` readable bytes and `out_public_key` must be valid for
+// writes.
+enum ZkdocStatus zkdoc_get_holder_public_key(struct ZkdocSlice holder_secret,
+                                             struct ZkdocBuffer *out_public_key);
+
+// Releases a buffer returned by the SDK. Freeing an empty buffer is a no-op.
+//
+// # Safety
+//
+// `buffer` must have been returned by the SDK and not freed before.
+void zkdoc_buffer_free(struct ZkdocBuffer buffer);
+
+#endif  /* ZKDOC_H */
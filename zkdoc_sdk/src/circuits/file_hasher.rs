@@ -5,9 +5,9 @@ use crate::gadgets::{
 };
 use halo2_gadgets::poseidon::primitives::P128Pow5T3;
 use halo2_proofs::{
-    circuit::{floor_planner::V1, Value},
+    circuit::{floor_planner::V1, Layouter, Value},
     pasta::Fp,
-    plonk::{Circuit, Column, ConstraintSystem, Instance},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
 };
 
 #[derive(Clone)]
@@ -18,7 +18,6 @@ pub struct FileHashPartialConfig {
     row_selector_accumulator: FileSelectorAccumulatorConfig,
 }
 
-#[allow(unused)]
 pub struct FileHashPartialChip {
     config: FileHashPartialConfig,
 }
@@ -49,56 +48,26 @@ impl FileHashPartialChip {
             row_selector_accumulator,
         }
     }
-}
-
-#[derive(Clone)]
-pub struct FileHashPartialCircuit<const L: usize> {
-    pub row_title: [[Value<Fp>; 4]; L],
-    pub row_content: [[Value<Fp>; 4]; L],
-    pub row_selectors: [Value<Fp>; L],
-}
-
-impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
-    type Config = FileHashPartialConfig;
-
-    type FloorPlanner = V1;
-
-    fn without_witnesses(&self) -> Self {
-        Self {
-            row_title: (0..L)
-                .map(|_i| [Value::unknown(); 4])
-                .collect::<Vec<[Value<Fp>; 4]>>()
-                .try_into()
-                .unwrap(),
-            row_content: (0..L)
-                .map(|_i| [Value::unknown(); 4])
-                .collect::<Vec<[Value<Fp>; 4]>>()
-                .try_into()
-                .unwrap(),
-            row_selectors: (0..L)
-                .map(|_i| Value::unknown())
-                .collect::<Vec<Value<Fp>>>()
-                .try_into()
-                .unwrap(),
-        }
-    }
-
-    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
-        FileHashPartialChip::configure(meta)
-    }
 
-    fn synthesize(
+    /// Assigns a document and constrains its commitment and the accumulated hash of its
+    /// selected rows to the instance column, at `instance_offset` and `instance_offset + 1`.
+    pub fn assign_document(
         &self,
-        config: Self::Config,
-        mut layouter: impl halo2_proofs::circuit::Layouter<Fp>,
-    ) -> Result<(), halo2_proofs::plonk::Error> {
+        mut layouter: impl Layouter<Fp>,
+        row_title: &[[Value<Fp>; 4]],
+        row_content: &[[Value<Fp>; 4]],
+        row_selectors: &[Value<Fp>],
+        holder_secret: Option<Value<Fp>>,
+        instance_offset: usize,
+    ) -> Result<(), Error> {
         // get all row hashes
-        let poseidon_cs = PoseidonChip::<P128Pow5T3, 3, 2, 2>::construct(config.poseidon_config);
+        let poseidon_cs =
+            PoseidonChip::<P128Pow5T3, 3, 2, 2>::construct(self.config.poseidon_config.clone());
 
         let mut file_hashes = Vec::new();
-        for i in 0..L {
-            let row_title_hash_1_message = [self.row_title[i][0], self.row_title[i][1]];
-            let row_title_hash_2_message = [self.row_title[i][2], self.row_title[i][3]];
+        for i in 0..row_title.len() {
+            let row_title_hash_1_message = [row_title[i][0], row_title[i][1]];
+            let row_title_hash_2_message = [row_title[i][2], row_title[i][3]];
 
             // get row_title hash
             let row_title_hash_1_message_cell = poseidon_cs.load_private_inputs(
@@ -128,8 +97,8 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
             )?;
 
             // get row_content hash
-            let row_content_hash_1_message = [self.row_content[i][0], self.row_content[i][1]];
-            let row_content_hash_2_message = [self.row_content[i][2], self.row_content[i][3]];
+            let row_content_hash_1_message = [row_content[i][0], row_content[i][1]];
+            let row_content_hash_2_message = [row_content[i][2], row_content[i][3]];
 
             // get row_title hash
             let row_content_hash_1_message_cell = poseidon_cs.load_private_inputs(
@@ -167,14 +136,15 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
         }
 
         // multiply by row_selector
-        let row_selector_cs = FileHashRowSelectorChip::<Fp>::construct(config.row_selector_config);
+        let row_selector_cs =
+            FileHashRowSelectorChip::<Fp>::construct(self.config.row_selector_config.clone());
 
         let mut selected_rows = Vec::new();
         for (i, hash) in file_hashes.iter().enumerate() {
             let (file_hash_cell, _, file_res_cell) = row_selector_cs.assign(
                 layouter.namespace(|| "row selectors"),
                 hash.value().copied(),
-                self.row_selectors[i],
+                row_selectors[i],
                 i,
             )?;
 
@@ -187,8 +157,9 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
         }
 
         // accumulate selected row
-        let row_selector_accumulator_cs =
-            FileSelectorAccumulatorChip::<Fp>::construct(config.row_selector_accumulator);
+        let row_selector_accumulator_cs = FileSelectorAccumulatorChip::<Fp>::construct(
+            self.config.row_selector_accumulator.clone(),
+        );
 
         let (first_cell, second_cell, mut row_selector_accumulator) = row_selector_accumulator_cs
             .assign_first(
@@ -208,7 +179,7 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
             },
         )?;
 
-        for (i, selected) in selected_rows.iter().enumerate().take(L).skip(2) {
+        for (i, selected) in selected_rows.iter().enumerate().skip(2) {
             let (b_cell, res_cell) = row_selector_accumulator_cs.assign(
                 layouter.namespace(|| "row selector rest of the rows"),
                 &row_selector_accumulator,
@@ -229,7 +200,11 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
         }
 
         // expose row_selector_accumulator
-        layouter.constrain_instance(row_selector_accumulator.cell(), config.instance, 1)?;
+        layouter.constrain_instance(
+            row_selector_accumulator.cell(),
+            self.config.instance,
+            instance_offset + 1,
+        )?;
 
         // get final commitment
         let starting_poseidon_hash_message = [file_hashes[0].clone(), file_hashes[1].clone()];
@@ -238,15 +213,97 @@ impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
             &starting_poseidon_hash_message,
         )?;
 
-        for hash in file_hashes.into_iter().take(L).skip(2) {
+        for hash in file_hashes.into_iter().skip(2) {
             let message_cells = [accumulated_hash.clone(), hash];
             accumulated_hash =
                 poseidon_cs.hash(layouter.namespace(|| "poseidon chip"), &message_cells)?;
         }
 
-        layouter.constrain_instance(accumulated_hash.cell(), config.instance, 0)?;
+        // append the reserved holder row, proving knowledge of the holder secret
+        if let Some(holder_secret) = holder_secret {
+            let holder_secret_cell = poseidon_cs
+                .load_private_input(layouter.namespace(|| "load holder secret"), holder_secret)?;
+            let zero_cell =
+                poseidon_cs.load_constant(layouter.namespace(|| "load zero"), Fp::zero())?;
+            let holder_public_key = poseidon_cs.hash(
+                layouter.namespace(|| "holder public key"),
+                &[holder_secret_cell, zero_cell],
+            )?;
+
+            accumulated_hash = poseidon_cs.hash(
+                layouter.namespace(|| "poseidon chip"),
+                &[accumulated_hash, holder_public_key],
+            )?;
+        }
+
+        layouter.constrain_instance(
+            accumulated_hash.cell(),
+            self.config.instance,
+            instance_offset,
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct FileHashPartialCircuit<const L: usize> {
+    pub row_title: [[Value<Fp>; 4]; L],
+    pub row_content: [[Value<Fp>; 4]; L],
+    pub row_selectors: [Value<Fp>; L],
+    /// Secret behind the holder public key in the reserved holder row, if the document is
+    /// holder bound. The row is appended after the last document row when committing.
+    pub holder_secret: Option<Value<Fp>>,
+}
+
+impl<const L: usize> Circuit<Fp> for FileHashPartialCircuit<L> {
+    type Config = FileHashPartialConfig;
+
+    type FloorPlanner = V1;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            row_title: (0..L)
+                .map(|_i| [Value::unknown(); 4])
+                .collect::<Vec<[Value<Fp>; 4]>>()
+                .try_into()
+                .unwrap(),
+            row_content: (0..L)
+                .map(|_i| [Value::unknown(); 4])
+                .collect::<Vec<[Value<Fp>; 4]>>()
+                .try_into()
+                .unwrap(),
+            row_selectors: (0..L)
+                .map(|_i| Value::unknown())
+                .collect::<Vec<Value<Fp>>>()
+                .try_into()
+                .unwrap(),
+            holder_secret: self.holder_secret.map(|_| Value::unknown()),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        FileHashPartialChip::configure(meta)
+    }
 
-        Ok(())
+    #[tracing::instrument(
+        name = "synthesize",
+        level = "debug",
+        skip_all,
+        fields(rows = L, holder_bound = self.holder_secret.is_some())
+    )]
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl halo2_proofs::circuit::Layouter<Fp>,
+    ) -> Result<(), halo2_proofs::plonk::Error> {
+        let chip = FileHashPartialChip::construct(config);
+        chip.assign_document(
+            layouter.namespace(|| "document"),
+            &self.row_title,
+            &self.row_content,
+            &self.row_selectors,
+            self.holder_secret,
+            0,
+        )
     }
 }
 
@@ -264,6 +321,9 @@ mod tests {
     use rand_core::OsRng;
 
     use crate::utils::conversion::convert_hash_u32_to_u64;
+    use crate::utils::poseidon::{
+        bind_holder_public_key, get_file_commitment_and_selected_row, get_holder_public_key,
+    };
 
     use super::FileHashPartialCircuit;
     use std::time::Instant;
@@ -283,9 +343,10 @@ mod tests {
         ];
 
         let circuit = FileHashPartialCircuit::<2> {
-            row_title: row_title.map(|x| x.map(|y| Value::known(y))),
-            row_content: row_content.map(|x| x.map(|y| Value::known(y))),
-            row_selectors: row_selector.map(|x| Value::known(x)),
+            row_title: row_title.map(|x| x.map(Value::known)),
+            row_content: row_content.map(|x| x.map(Value::known)),
+            row_selectors: row_selector.map(Value::known),
+            holder_secret: None,
         };
 
         let mut row_hash = Vec::new();
@@ -331,8 +392,8 @@ mod tests {
         let mut accumulator_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
             .hash([row_hash[0], row_hash[1]]);
 
-        for i in 2..row_content.len() {
-            let message = [accumulator_hash, row_hash[i]];
+        for i in row_hash.into_iter().skip(2) {
+            let message = [accumulator_hash, i];
             let output =
                 poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
             accumulator_hash = output;
@@ -343,6 +404,61 @@ mod tests {
         prover.assert_satisfied();
     }
 
+    #[test]
+    fn test_holder_bound() {
+        let k = 10;
+        let row_selector = [Fp::from(1), Fp::from(0)];
+        let row_title = [[Fp::from(1), Fp::from(5), Fp::from(7), Fp::from(8)]; 2];
+        let row_content = [[Fp::from(2), Fp::from(4), Fp::from(6), Fp::from(8)]; 2];
+        let holder_secret = Fp::from(42);
+
+        let (file_commitment, row_accumulator) =
+            get_file_commitment_and_selected_row(row_title, row_content, row_selector);
+        let commitment =
+            bind_holder_public_key(file_commitment, get_holder_public_key(holder_secret));
+
+        let circuit = |holder_secret: Fp| FileHashPartialCircuit::<2> {
+            row_title: row_title.map(|x| x.map(Value::known)),
+            row_content: row_content.map(|x| x.map(Value::known)),
+            row_selectors: row_selector.map(Value::known),
+            holder_secret: Some(Value::known(holder_secret)),
+        };
+
+        let pub_instance = vec![commitment, row_accumulator];
+        let prover =
+            MockProver::run(k, &circuit(holder_secret), vec![pub_instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // the document contents alone are not enough without the matching secret
+        let params: Params<EqAffine> = Params::new(k);
+        let vk = keygen_vk(&params, &circuit(holder_secret)).expect("keygen_vk should not fail");
+        let pk =
+            keygen_pk(&params, vk, &circuit(holder_secret)).expect("keygen_pk should not fail");
+
+        let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+        create_proof(
+            &params,
+            &pk,
+            &[circuit(Fp::from(43))],
+            &[&[&pub_instance[..]]],
+            OsRng,
+            &mut transcript,
+        )
+        .expect("proof generation should not fail");
+        let proof: Vec<u8> = transcript.finalize();
+
+        let strategy = SingleVerifier::new(&params);
+        let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+        assert!(verify_proof(
+            &params,
+            pk.get_vk(),
+            strategy,
+            &[&[&pub_instance[..]]],
+            &mut transcript,
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_real_prover() {
         const ROW_NUMBER: usize = 14;
@@ -352,13 +468,13 @@ mod tests {
             1803989619, 4281662689, 2641068110, 4284104535, 1202562282, 2720996681, 3223212765,
             3079101259,
         ];
-        let row_content_u32 = row_title_u32.clone();
+        let row_content_u32 = row_title_u32;
 
         let row_title_u64 = convert_hash_u32_to_u64(row_title_u32);
         let row_content_u64 = convert_hash_u32_to_u64(row_content_u32);
 
-        let row_title = row_title_u64.map(|x| return Fp::from(x));
-        let row_content = row_content_u64.map(|x| return Fp::from(x));
+        let row_title = row_title_u64.map(Fp::from);
+        let row_content = row_content_u64.map(Fp::from);
 
         let row_title = [row_title; ROW_NUMBER];
         let row_content = [row_content; ROW_NUMBER];
@@ -424,17 +540,19 @@ mod tests {
         println!("accumulator_hash: {:?}", accumulator_hash);
         println!("row_accumulator: {:?}", row_accumulator);
 
-        let public_input = vec![accumulator_hash, row_accumulator];
+        let public_input = [accumulator_hash, row_accumulator];
         let circuit = FileHashPartialCircuit::<ROW_NUMBER> {
-            row_title: row_title.map(|x| x.map(|y| Value::known(y))),
-            row_content: row_content.map(|x| x.map(|y| Value::known(y))),
-            row_selectors: row_selector.map(|x| Value::known(x)),
+            row_title: row_title.map(|x| x.map(Value::known)),
+            row_content: row_content.map(|x| x.map(Value::known)),
+            row_selectors: row_selector.map(Value::known),
+            holder_secret: None,
         };
 
         let empty_circuit = FileHashPartialCircuit::<ROW_NUMBER> {
             row_title: [[Value::unknown(); 4]; ROW_NUMBER],
             row_content: [[Value::unknown(); 4]; ROW_NUMBER],
             row_selectors: [Value::unknown(); ROW_NUMBER],
+            holder_secret: None,
         };
         let params: Params<EqAffine> = Params::new(k);
 
@@ -486,6 +604,7 @@ mod tests {
             row_title: [[Value::unknown(); 4]; 2],
             row_content: [[Value::unknown(); 4]; 2],
             row_selectors: [Value::unknown(); 2],
+            holder_secret: None,
         };
 
         halo2_proofs::dev::CircuitLayout::default()
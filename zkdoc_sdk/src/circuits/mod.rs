@@ -1,2 +1,4 @@
 pub mod file_hasher;
-pub mod poseidon;
+pub mod multi_document;
+#[cfg(test)]
+mod poseidon;
@@ -0,0 +1,247 @@
+use crate::circuits::file_hasher::{FileHashPartialChip, FileHashPartialConfig};
+use halo2_proofs::{
+    circuit::{floor_planner::V1, Layouter, Value},
+    pasta::Fp,
+    plonk::{Circuit, ConstraintSystem, Error},
+};
+
+/// Rows taken by a single Poseidon hash, including loading its inputs
+const ROWS_PER_HASH: usize = 43;
+/// Rows reserved for blinding factors at the end of the table
+const BLINDING_ROWS: usize = 8;
+
+/// Private inputs of a single document of a [`MultiDocumentCircuit`].
+#[derive(Clone)]
+pub struct DocumentWitness {
+    pub row_title: Vec<[Value<Fp>; 4]>,
+    pub row_content: Vec<[Value<Fp>; 4]>,
+    pub row_selectors: Vec<Value<Fp>>,
+    pub holder_secret: Option<Value<Fp>>,
+}
+
+impl DocumentWitness {
+    /// Witness-free document with `rows` rows, used for key generation.
+    pub fn unknown(rows: usize, holder_bound: bool) -> Self {
+        Self {
+            row_title: vec![[Value::unknown(); 4]; rows],
+            row_content: vec![[Value::unknown(); 4]; rows],
+            row_selectors: vec![Value::unknown(); rows],
+            holder_secret: holder_bound.then(Value::unknown),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.row_title.len()
+    }
+}
+
+/// Discloses selected rows of several documents in a single proof.
+///
+/// Documents may have different row counts, each needing at least two rows. The commitment of
+/// the i-th document and the accumulated hash of its selected rows are exposed in the instance
+/// column at rows `2 * i` and `2 * i + 1`.
+#[derive(Clone)]
+pub struct MultiDocumentCircuit {
+    pub documents: Vec<DocumentWitness>,
+}
+
+impl MultiDocumentCircuit {
+    /// Smallest `k` that fits all documents. A document of `n` rows hashes each row with 7
+    /// Poseidon hashes and chains the row hashes with `n - 1` more, plus 2 for a holder row.
+    pub fn k(&self) -> u32 {
+        let hashes: usize = self
+            .documents
+            .iter()
+            .map(|document| 8 * document.rows() - 1 + 2 * document.holder_secret.iter().count())
+            .sum();
+        // row selector regions grow with their offset and are laid out next to the hashes
+        let selector_rows: usize = self
+            .documents
+            .iter()
+            .map(|document| document.rows() * (document.rows() + 1) / 2)
+            .sum();
+
+        let rows = (hashes * ROWS_PER_HASH).max(selector_rows) + BLINDING_ROWS;
+        rows.next_power_of_two().trailing_zeros()
+    }
+}
+
+impl Circuit<Fp> for MultiDocumentCircuit {
+    type Config = FileHashPartialConfig;
+
+    type FloorPlanner = V1;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            documents: self
+                .documents
+                .iter()
+                .map(|document| {
+                    DocumentWitness::unknown(document.rows(), document.holder_secret.is_some())
+                })
+                .collect(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        FileHashPartialChip::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let chip = FileHashPartialChip::construct(config);
+
+        for (i, document) in self.documents.iter().enumerate() {
+            chip.assign_document(
+                layouter.namespace(|| format!("document {i}")),
+                &document.row_title,
+                &document.row_content,
+                &document.row_selectors,
+                document.holder_secret,
+                2 * i,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::{circuit::Value, dev::MockProver, pasta::Fp};
+
+    use super::{DocumentWitness, MultiDocumentCircuit};
+    use crate::services::MAX_DOCUMENT_ROWS;
+    use crate::utils::poseidon::{
+        bind_holder_public_key, get_document_commitment_and_selected_row, get_holder_public_key,
+    };
+
+    #[test]
+    fn test() {
+        let row_title = [[Fp::from(1), Fp::from(5), Fp::from(7), Fp::from(8)]; 3];
+        let row_content = [[Fp::from(2), Fp::from(4), Fp::from(6), Fp::from(8)]; 3];
+        let holder_secret = Fp::from(42);
+
+        // a plain document of two rows, and a holder bound document of three rows
+        let first_selector = [Fp::from(0), Fp::from(1)];
+        let second_selector = [Fp::from(1), Fp::from(0), Fp::from(1)];
+
+        let (first_commitment, first_accumulator) = get_document_commitment_and_selected_row(
+            &row_title[..2],
+            &row_content[..2],
+            &first_selector,
+        );
+        let (second_commitment, second_accumulator) =
+            get_document_commitment_and_selected_row(&row_title, &row_content, &second_selector);
+        let second_commitment =
+            bind_holder_public_key(second_commitment, get_holder_public_key(holder_secret));
+
+        let circuit = MultiDocumentCircuit {
+            documents: vec![
+                DocumentWitness {
+                    row_title: row_title[..2].iter().map(|x| x.map(Value::known)).collect(),
+                    row_content: row_content[..2]
+                        .iter()
+                        .map(|x| x.map(Value::known))
+                        .collect(),
+                    row_selectors: first_selector.map(Value::known).to_vec(),
+                    holder_secret: None,
+                },
+                DocumentWitness {
+                    row_title: row_title.iter().map(|x| x.map(Value::known)).collect(),
+                    row_content: row_content.iter().map(|x| x.map(Value::known)).collect(),
+                    row_selectors: second_selector.map(Value::known).to_vec(),
+                    holder_secret: Some(Value::known(holder_secret)),
+                },
+            ],
+        };
+        assert_eq!(circuit.k(), 11);
+
+        let pub_instance = vec![
+            first_commitment,
+            first_accumulator,
+            second_commitment,
+            second_accumulator,
+        ];
+        let prover = MockProver::run(circuit.k(), &circuit, vec![pub_instance]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    /// A document of `rows` rows disclosing every other row, along with its public inputs.
+    fn document(rows: usize, holder_bound: bool) -> (DocumentWitness, [Fp; 2]) {
+        let row_title = (0..rows)
+            .map(|i| [0, 1, 2, 3].map(|j| Fp::from((8 * i + j) as u64)))
+            .collect::<Vec<_>>();
+        let row_content = (0..rows)
+            .map(|i| [4, 5, 6, 7].map(|j| Fp::from((8 * i + j) as u64)))
+            .collect::<Vec<_>>();
+        let selectors = (0..rows)
+            .map(|i| Fp::from((i % 2) as u64))
+            .collect::<Vec<_>>();
+        let holder_secret = Fp::from(42);
+
+        let (mut commitment, accumulator) =
+            get_document_commitment_and_selected_row(&row_title, &row_content, &selectors);
+        if holder_bound {
+            commitment = bind_holder_public_key(commitment, get_holder_public_key(holder_secret));
+        }
+        let witness = DocumentWitness {
+            row_title: row_title.iter().map(|x| x.map(Value::known)).collect(),
+            row_content: row_content.iter().map(|x| x.map(Value::known)).collect(),
+            row_selectors: selectors.into_iter().map(Value::known).collect(),
+            holder_secret: holder_bound.then(|| Value::known(holder_secret)),
+        };
+        (witness, [commitment, accumulator])
+    }
+
+    /// The computed k is hand tuned to the layout of the Poseidon chip, so the circuit is run at
+    /// the computed k for the largest documents of each k, where the table is the fullest.
+    #[test]
+    fn test_k_fits() {
+        let k = |rows: usize, holder_bound: bool| {
+            MultiDocumentCircuit {
+                documents: vec![DocumentWitness::unknown(rows, holder_bound)],
+            }
+            .k()
+        };
+
+        for holder_bound in [false, true] {
+            let fullest = (2..=MAX_DOCUMENT_ROWS).filter(|&rows| {
+                rows == MAX_DOCUMENT_ROWS || k(rows + 1, holder_bound) > k(rows, holder_bound)
+            });
+            for rows in fullest {
+                let (witness, instance) = document(rows, holder_bound);
+                let circuit = MultiDocumentCircuit {
+                    documents: vec![witness],
+                };
+                let prover = MockProver::run(circuit.k(), &circuit, vec![instance.to_vec()])
+                    .unwrap_or_else(|err| panic!("{rows} rows at k = {}: {err:?}", circuit.k()));
+                assert_eq!(
+                    prover.verify(),
+                    Ok(()),
+                    "{rows} rows at k = {}",
+                    circuit.k()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_k() {
+        let k = |rows: usize, holder_bound: bool| {
+            MultiDocumentCircuit {
+                documents: vec![DocumentWitness::unknown(rows, holder_bound)],
+            }
+            .k()
+        };
+
+        // matches the k the single document circuit is proven with
+        assert_eq!(k(10, false), 12);
+        assert_eq!(k(10, true), 12);
+        assert_eq!(k(2, false), 10);
+        assert_eq!(k(12, true), 13);
+    }
+}
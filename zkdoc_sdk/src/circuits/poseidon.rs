@@ -1,3 +1,5 @@
+//! Circuit of a single Poseidon hash, checking the Poseidon chip against the primitive.
+
 use crate::gadgets::poseidon::{PoseidonChip as Chip, PoseidonConfig as Config};
 use halo2_gadgets::poseidon::primitives::P128Pow5T3;
 use halo2_proofs::{
@@ -12,18 +14,23 @@ struct PoseidonConfig {
     poseidon_config: Config<3, 2, 2>,
 }
 
-#[allow(unused)]
-struct PoseidonChip {
-    config: PoseidonConfig,
+/// Hashes two private inputs, exposing the hash as the first public input.
+#[derive(Default)]
+struct PoseidonCircuit {
+    a: Value<Fp>,
+    b: Value<Fp>,
 }
 
-impl PoseidonChip {
-    #[allow(unused)]
-    pub fn construct(config: PoseidonConfig) -> Self {
-        Self { config }
+impl Circuit<Fp> for PoseidonCircuit {
+    type Config = PoseidonConfig;
+
+    type FloorPlanner = V1;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
     }
 
-    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> PoseidonConfig {
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
         let instance = meta.instance_column();
         meta.enable_equality(instance);
 
@@ -39,26 +46,6 @@ impl PoseidonChip {
             poseidon_config,
         }
     }
-}
-
-#[derive(Default)]
-struct PoseidonCircuit {
-    a: Value<Fp>,
-    b: Value<Fp>,
-}
-
-impl Circuit<Fp> for PoseidonCircuit {
-    type Config = PoseidonConfig;
-
-    type FloorPlanner = V1;
-
-    fn without_witnesses(&self) -> Self {
-        Self::default()
-    }
-
-    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
-        PoseidonChip::configure(meta)
-    }
 
     fn synthesize(
         &self,
@@ -84,7 +71,9 @@ mod tests {
     use halo2_gadgets::poseidon::primitives::{self as poseidon, ConstantLength, P128Pow5T3};
     use halo2_proofs::{arithmetic::FieldExt, circuit::Value, dev::MockProver, pasta::Fp};
 
-    use crate::utils::conversion::{convert_hash_u32_to_u64, convert_hash_u64_to_u32};
+    use crate::utils::conversion::{
+        convert_hash_u32_to_u64, convert_hash_u64_to_u32, fp_to_u64_array,
+    };
 
     use super::PoseidonCircuit;
 
@@ -142,7 +131,7 @@ mod tests {
         );
         println!(
             "output bits raw slice u32: {:?}",
-            convert_hash_u64_to_u32(output.to_le_bits().as_raw_slice().try_into().unwrap())
+            convert_hash_u64_to_u32(fp_to_u64_array(output))
         );
 
         let pub_instance = vec![output];
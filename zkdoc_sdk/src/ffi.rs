@@ -0,0 +1,279 @@
+//! C ABI over commitment generation, proving and verification, for use from Swift, Kotlin or C.
+//!
+//! Every function returns a [`ZkdocStatus`] instead of panicking. Strings are passed as UTF-8
+//! [`ZkdocSlice`]s without a trailing NUL, and results are written to caller provided
+//! [`ZkdocBuffer`]s which are owned by the caller and released with [`zkdoc_buffer_free`].
+//! The header is generated with `cbindgen` into `include/zkdoc.h`.
+
+use std::panic::{self, UnwindSafe};
+use std::{ptr, slice, str};
+
+use crate::holder::HolderKey;
+use crate::services::{self, prove, ZkdocContext, ROW};
+use crate::utils::conversion::fp_from_hex;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZkdocStatus {
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// A string was not valid UTF-8
+    InvalidUtf8 = 2,
+    /// An argument was malformed, e.g. the wrong number of rows or an invalid key
+    InvalidArgument = 3,
+    /// The SDK panicked, which is a bug
+    Panic = 4,
+}
+
+/// Borrowed bytes passed into the SDK.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ZkdocSlice {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// Bytes returned by the SDK, to be released with [`zkdoc_buffer_free`].
+#[repr(C)]
+pub struct ZkdocBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl ZkdocBuffer {
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        Self { data, len }
+    }
+}
+
+/// Computes the commitment of a document of 10 rows as a hex string. An empty
+/// `holder_public_key` commits a plain document, otherwise a holder bound one.
+///
+/// # Safety
+///
+/// `row_titles` and `row_contents` must point to `row_count` valid slices, every slice must
+/// point to `len` readable bytes, and `out_commitment` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_get_commitment(
+    row_titles: *const ZkdocSlice,
+    row_contents: *const ZkdocSlice,
+    row_count: usize,
+    holder_public_key: ZkdocSlice,
+    out_commitment: *mut ZkdocBuffer,
+) -> ZkdocStatus {
+    call(out_commitment, || {
+        let row_titles = read_rows(row_titles, row_count)?;
+        let row_contents = read_rows(row_contents, row_count)?;
+        let holder_public_key = read_str(holder_public_key)?;
+
+        let commitment = if holder_public_key.is_empty() {
+            services::get_file_commitment_and_selected_row(row_titles, row_contents, [0; ROW])
+        } else {
+            services::get_holder_bound_commitment(
+                row_titles,
+                row_contents,
+                holder_public_key.to_owned(),
+            )
+            .ok_or(ZkdocStatus::InvalidArgument)?
+        };
+        Ok(commitment.into_bytes())
+    })
+}
+
+/// Computes the row accumulator of a single revealed row, see [`services::get_selected_row`].
+///
+/// # Safety
+///
+/// Both slices must point to `len` readable bytes and `out_row_accumulator` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_get_selected_row(
+    row_title: ZkdocSlice,
+    row_content: ZkdocSlice,
+    out_row_accumulator: *mut ZkdocBuffer,
+) -> ZkdocStatus {
+    call(out_row_accumulator, || {
+        let row_accumulator = services::get_selected_row(
+            read_str(row_title)?.to_owned(),
+            read_str(row_content)?.to_owned(),
+        );
+        Ok(row_accumulator.into_bytes())
+    })
+}
+
+/// Generates a proof revealing the rows whose selector is 1. An empty `holder_secret`
+/// generates a plain proof, otherwise a holder bound one.
+///
+/// # Safety
+///
+/// `row_titles`, `row_contents` and `row_selectors` must point to `row_count` valid elements,
+/// every slice must point to `len` readable bytes, and `out_proof` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_generate_proof(
+    row_titles: *const ZkdocSlice,
+    row_contents: *const ZkdocSlice,
+    row_selectors: *const u64,
+    row_count: usize,
+    holder_secret: ZkdocSlice,
+    out_proof: *mut ZkdocBuffer,
+) -> ZkdocStatus {
+    call(out_proof, || {
+        let row_titles = read_rows(row_titles, row_count)?;
+        let row_contents = read_rows(row_contents, row_count)?;
+        let row_selectors: [u64; ROW] = read_array(row_selectors, row_count)?
+            .try_into()
+            .map_err(|_| ZkdocStatus::InvalidArgument)?;
+        let holder_secret = read_str(holder_secret)?;
+        let holder_key = if holder_secret.is_empty() {
+            None
+        } else {
+            Some(HolderKey::from_secret_hex(holder_secret).ok_or(ZkdocStatus::InvalidArgument)?)
+        };
+
+        Ok(prove(
+            ZkdocContext::shared(),
+            row_titles,
+            row_contents,
+            row_selectors,
+            holder_key.as_ref(),
+        ))
+    })
+}
+
+/// Verifies a proof, writing whether it is valid to `out_valid`.
+///
+/// # Safety
+///
+/// Every slice must point to `len` readable bytes and `out_valid` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_verify_proof(
+    commitment: ZkdocSlice,
+    row_accumulator: ZkdocSlice,
+    proof: ZkdocSlice,
+    holder_bound: bool,
+    out_valid: *mut bool,
+) -> ZkdocStatus {
+    if out_valid.is_null() {
+        return ZkdocStatus::NullPointer;
+    }
+
+    catch(|| {
+        let public_input = [
+            fp_from_hex(read_str(commitment)?).ok_or(ZkdocStatus::InvalidArgument)?,
+            fp_from_hex(read_str(row_accumulator)?).ok_or(ZkdocStatus::InvalidArgument)?,
+        ];
+        let proof = read_bytes(proof)?;
+
+        let valid = ZkdocContext::shared().verify_public_input(public_input, proof, holder_bound);
+        *out_valid = valid;
+        Ok(())
+    })
+}
+
+/// Generates a new holder key, writing its hex encoded secret to `out_secret`.
+///
+/// # Safety
+///
+/// `out_secret` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_generate_holder_key(out_secret: *mut ZkdocBuffer) -> ZkdocStatus {
+    call(out_secret, || {
+        Ok(HolderKey::generate().secret().into_bytes())
+    })
+}
+
+/// Derives the holder public key from a hex encoded holder secret.
+///
+/// # Safety
+///
+/// `holder_secret` must point to `len` readable bytes and `out_public_key` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_get_holder_public_key(
+    holder_secret: ZkdocSlice,
+    out_public_key: *mut ZkdocBuffer,
+) -> ZkdocStatus {
+    call(out_public_key, || {
+        let holder_key = HolderKey::from_secret_hex(read_str(holder_secret)?)
+            .ok_or(ZkdocStatus::InvalidArgument)?;
+        Ok(holder_key.public_key().into_bytes())
+    })
+}
+
+/// Releases a buffer returned by the SDK. Freeing an empty buffer is a no-op.
+///
+/// # Safety
+///
+/// `buffer` must have been returned by the SDK and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn zkdoc_buffer_free(buffer: ZkdocBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+fn catch(f: impl FnOnce() -> Result<(), ZkdocStatus> + UnwindSafe) -> ZkdocStatus {
+    match panic::catch_unwind(f) {
+        Ok(Ok(())) => ZkdocStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => ZkdocStatus::Panic,
+    }
+}
+
+/// Runs `f`, writing its result to `out` on success.
+unsafe fn call(
+    out: *mut ZkdocBuffer,
+    f: impl FnOnce() -> Result<Vec<u8>, ZkdocStatus> + UnwindSafe,
+) -> ZkdocStatus {
+    if out.is_null() {
+        return ZkdocStatus::NullPointer;
+    }
+
+    catch(|| {
+        let bytes = f()?;
+        out.write(ZkdocBuffer::from_vec(bytes));
+        Ok(())
+    })
+}
+
+unsafe fn read_bytes<'a>(slice: ZkdocSlice) -> Result<&'a [u8], ZkdocStatus> {
+    if slice.len == 0 {
+        return Ok(&[]);
+    }
+    if slice.data.is_null() {
+        return Err(ZkdocStatus::NullPointer);
+    }
+    Ok(slice::from_raw_parts(slice.data, slice.len))
+}
+
+unsafe fn read_str<'a>(slice: ZkdocSlice) -> Result<&'a str, ZkdocStatus> {
+    str::from_utf8(read_bytes(slice)?).map_err(|_| ZkdocStatus::InvalidUtf8)
+}
+
+unsafe fn read_array<'a, T>(data: *const T, len: usize) -> Result<&'a [T], ZkdocStatus> {
+    if data.is_null() {
+        return Err(ZkdocStatus::NullPointer);
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+unsafe fn read_rows(
+    rows: *const ZkdocSlice,
+    row_count: usize,
+) -> Result<[String; ROW], ZkdocStatus> {
+    if row_count != ROW {
+        return Err(ZkdocStatus::InvalidArgument);
+    }
+
+    let rows = read_array(rows, row_count)?
+        .iter()
+        .map(|&row| read_str(row).map(str::to_owned))
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.try_into().map_err(|_| ZkdocStatus::InvalidArgument)
+}
@@ -92,6 +92,37 @@ impl<S: Spec<Fp, WIDTH, RATE>, const WIDTH: usize, const RATE: usize, const L: u
         )
     }
 
+    pub fn load_private_input(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        input: Value<Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        layouter.assign_region(
+            || "load private input",
+            |mut region| {
+                region.assign_advice(|| "private input", self.config.inputs[0], 0, || input)
+            },
+        )
+    }
+
+    pub fn load_constant(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        constant: Fp,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        layouter.assign_region(
+            || "load constant",
+            |mut region| {
+                region.assign_advice_from_constant(
+                    || "constant",
+                    self.config.inputs[0],
+                    0,
+                    constant,
+                )
+            },
+        )
+    }
+
     // pub fn expose_public(
     //     &self,
     //     mut layouter: impl Layouter<Fp>,
@@ -0,0 +1,101 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use ff::Field;
+use halo2_proofs::pasta::Fp;
+use rand_core::OsRng;
+
+use crate::utils::conversion::fp_from_hex;
+use crate::utils::poseidon::get_holder_public_key;
+
+/// Key of the holder of a document.
+///
+/// The issuer commits the holder public key in a reserved row of the document, and proofs for
+/// such a holder bound document can only be generated with the matching secret. Holders proving
+/// on their own device, e.g. with the CLI or the WebAssembly bindings, never hand the secret out,
+/// so a leaked document alone is not enough to present it. Proving on `zkdoc_server` is
+/// custodial instead: the request carries the secret, and the server is trusted with it.
+#[derive(Clone)]
+pub struct HolderKey {
+    pub(crate) secret: Fp,
+    pub(crate) public_key: Fp,
+}
+
+impl HolderKey {
+    /// Generates a new random holder key.
+    pub fn generate() -> Self {
+        Self::from_secret(Fp::random(OsRng))
+    }
+
+    /// Restores a holder key from its hex encoded secret, see [`HolderKey::secret`].
+    pub fn from_secret_hex(secret: &str) -> Option<Self> {
+        fp_from_hex(secret.trim()).map(Self::from_secret)
+    }
+
+    fn from_secret(secret: Fp) -> Self {
+        Self {
+            secret,
+            public_key: get_holder_public_key(secret),
+        }
+    }
+
+    /// Hex encoded holder secret. This must be kept private by the holder.
+    pub fn secret(&self) -> String {
+        format!("{:?}", self.secret)
+    }
+
+    /// Hex encoded holder public key, which is handed to the issuer to bind the document.
+    pub fn public_key(&self) -> String {
+        format!("{:?}", self.public_key)
+    }
+
+    /// Saves the holder secret to `path`, readable only by the current user on unix.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(path)?;
+        file.write_all(self.secret().as_bytes())
+    }
+
+    /// Loads a holder key previously written with [`HolderKey::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::from_secret_hex(&contents)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid holder key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HolderKey;
+
+    #[test]
+    fn test_save_and_load() {
+        let holder_key = HolderKey::generate();
+        let path = std::env::temp_dir().join(format!("zkdoc-holder-{}.key", std::process::id()));
+
+        holder_key.save(&path).unwrap();
+        let loaded = HolderKey::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.secret(), holder_key.secret());
+        assert_eq!(loaded.public_key(), holder_key.public_key());
+        assert_ne!(holder_key.public_key(), HolderKey::generate().public_key());
+    }
+
+    #[test]
+    fn test_from_secret_hex() {
+        let holder_key = HolderKey::generate();
+        let restored = HolderKey::from_secret_hex(&holder_key.secret()).unwrap();
+
+        assert_eq!(restored.public_key(), holder_key.public_key());
+        assert!(HolderKey::from_secret_hex("not a key").is_none());
+    }
+}
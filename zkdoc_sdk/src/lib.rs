@@ -1,4 +1,9 @@
 pub mod circuits;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod gadgets;
+pub mod holder;
 pub mod services;
 pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
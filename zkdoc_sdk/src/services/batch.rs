@@ -0,0 +1,141 @@
+use halo2_proofs::pasta::Fp;
+use halo2_proofs::plonk::BatchVerifier;
+
+use super::telemetry::{self, Timer, BATCH_VERIFICATION_DURATION};
+use super::{verify_with_key, ZkdocContext, K};
+use crate::utils::conversion::fp_from_hex;
+
+/// A proof to verify as part of a batch, along with the public inputs it was generated for.
+#[derive(Clone, Debug)]
+pub struct BatchVerificationItem {
+    pub commitment: String,
+    /// Accumulated hash of the revealed rows, see [`super::get_selected_row`]
+    pub row_accumulator: String,
+    pub proof: Vec<u8>,
+    /// Whether the proof was generated with [`super::generate_holder_bound_proof`]
+    pub holder_bound: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchVerificationResult {
+    /// Indexes of the items that failed verification, in ascending order
+    pub failed: Vec<usize>,
+}
+
+impl BatchVerificationResult {
+    pub fn is_valid(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Verifies many proofs at once, amortizing the final multi-scalar multiplication across the
+/// batch. Should the batch fail, the proofs are checked one by one to report which failed.
+pub fn verify_batch(items: &[BatchVerificationItem]) -> BatchVerificationResult {
+    verify_batch_with(ZkdocContext::shared(), items)
+}
+
+#[tracing::instrument(skip_all, fields(k = K, items = items.len()))]
+pub(super) fn verify_batch_with(
+    context: &ZkdocContext,
+    items: &[BatchVerificationItem],
+) -> BatchVerificationResult {
+    let timer = Timer::start();
+    let params = &context.params;
+    let mut failed = Vec::new();
+
+    // holder bound proofs are checked against a different verifying key
+    for holder_bound in [false, true] {
+        let mut batch = BatchVerifier::new();
+        let mut batched = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            if item.holder_bound != holder_bound {
+                continue;
+            }
+
+            match parse_public_input(item) {
+                Some(public_input) => {
+                    batch.add_proof(
+                        vec![vec![public_input.to_vec()], vec![public_input.to_vec()]],
+                        item.proof.clone(),
+                    );
+                    batched.push((i, public_input));
+                }
+                None => {
+                    telemetry::count_verification("malformed");
+                    failed.push(i);
+                }
+            }
+        }
+
+        if batched.is_empty() {
+            continue;
+        }
+
+        let vk = context.keys.verifying_key(params, holder_bound);
+        if batch.finalize(params, vk) {
+            for _ in &batched {
+                telemetry::count_verification("valid");
+            }
+        } else {
+            // the proofs are counted as they are checked one by one
+            for (i, public_input) in batched {
+                if !verify_with_key(params, vk, public_input, &items[i].proof) {
+                    failed.push(i);
+                }
+            }
+        }
+    }
+
+    timer.record(metrics::histogram!(BATCH_VERIFICATION_DURATION));
+    failed.sort_unstable();
+    BatchVerificationResult { failed }
+}
+
+fn parse_public_input(item: &BatchVerificationItem) -> Option<[Fp; 2]> {
+    Some([
+        fp_from_hex(&item.commitment)?,
+        fp_from_hex(&item.row_accumulator)?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_batch, BatchVerificationItem};
+    use crate::services::{generate_proof, get_file_commitment_and_selected_row, get_selected_row};
+
+    #[test]
+    fn test_verify_batch() {
+        let row_titles = ["name", "age", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_contents =
+            ["alice", "42", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_selectors = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let commitment = get_file_commitment_and_selected_row(
+            row_titles.clone(),
+            row_contents.clone(),
+            row_selectors,
+        );
+        let proof = generate_proof(row_titles, row_contents, row_selectors);
+
+        let item = |row_title: &str, row_content: &str| BatchVerificationItem {
+            commitment: commitment.clone(),
+            row_accumulator: get_selected_row(row_title.to_owned(), row_content.to_owned()),
+            proof: proof.clone(),
+            holder_bound: false,
+        };
+
+        let result = verify_batch(&[item("name", "alice"), item("name", "alice")]);
+        assert!(result.is_valid());
+
+        let mut malformed = item("name", "alice");
+        malformed.commitment = "0x1234".to_owned();
+        let result = verify_batch(&[
+            item("name", "alice"),
+            item("name", "bob"),
+            malformed,
+            item("name", "alice"),
+        ]);
+        assert!(!result.is_valid());
+        assert_eq!(result.failed, vec![1, 2]);
+    }
+}
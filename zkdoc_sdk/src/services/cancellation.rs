@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Cancels proof generation from another thread, see
+/// [`super::generate_proof_cancellable`]. Clones share the cancellation, but not the deadline.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also cancels once `deadline` has passed.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Proof generation was cancelled before completion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("proof generation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::CancellationToken;
+    use crate::services::{generate_proof_cancellable, ProvingPhase};
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let with_deadline = token
+            .clone()
+            .with_deadline(Instant::now() + Duration::from_secs(60));
+        assert!(!with_deadline.is_cancelled());
+        token.cancel();
+        assert!(with_deadline.is_cancelled());
+
+        let expired = CancellationToken::new().with_deadline(Instant::now());
+        assert!(expired.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_while_proving() {
+        let rows = ["-"; 10].map(String::from);
+        let token = CancellationToken::new();
+
+        let mut phases = Vec::new();
+        let result =
+            generate_proof_cancellable(rows.clone(), rows, [0; 10], None, &token, |phase| {
+                phases.push(phase);
+                if phase == ProvingPhase::Quotient {
+                    token.cancel();
+                }
+            });
+
+        assert!(result.is_err());
+        assert_eq!(phases, ProvingPhase::ALL[..4]);
+    }
+}
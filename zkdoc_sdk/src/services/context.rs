@@ -0,0 +1,252 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use halo2_proofs::pasta::{EqAffine, Fp};
+use halo2_proofs::plonk;
+use halo2_proofs::poly::commitment::Params;
+
+use super::batch::verify_batch_with;
+use super::keys::Keys;
+use super::presentation::{verify_presentation_with, PresentationKeys};
+use super::{check_with_key, prove_cancellable, verify_with_key, K, ROW};
+use super::{
+    BatchVerificationItem, BatchVerificationResult, CancellationToken, Cancelled,
+    DisclosedDocument, PresentationProof, ProvingPhase,
+};
+use crate::holder::HolderKey;
+use crate::utils::conversion::fp_from_hex;
+
+/// Proving parameters shared by the proofs of a long running process such as a server, which
+/// can be saved to a file instead of being regenerated on every start. The proving and
+/// verifying keys are derived from the parameters on first use, as `halo2_proofs` 0.2 can not
+/// serialize them, then kept for the following proofs. Clones share the keys.
+///
+/// Presentations are proven with circuits of several sizes, whose parameters and verifying keys
+/// are generated and kept apart from the saved parameters.
+#[derive(Clone, Debug)]
+pub struct ZkdocContext {
+    pub(super) params: Params<EqAffine>,
+    pub(super) keys: Arc<Keys>,
+    pub(super) presentation_keys: Arc<PresentationKeys>,
+}
+
+impl Default for ZkdocContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZkdocContext {
+    /// Generates new parameters.
+    pub fn new() -> Self {
+        Self::from_params(Params::new(K))
+    }
+
+    pub(crate) fn from_params(params: Params<EqAffine>) -> Self {
+        Self {
+            params,
+            keys: Arc::default(),
+            presentation_keys: Arc::default(),
+        }
+    }
+
+    /// The context of the free proving and verifying functions, generated on first use and kept
+    /// for the whole process so that their keys are only derived once.
+    pub(crate) fn shared() -> &'static Self {
+        static SHARED: OnceLock<ZkdocContext> = OnceLock::new();
+        SHARED.get_or_init(Self::new)
+    }
+
+    /// Verifies a proof against public inputs already read as field elements.
+    pub(crate) fn verify_public_input(
+        &self,
+        public_input: [Fp; 2],
+        proof: &[u8],
+        holder_bound: bool,
+    ) -> bool {
+        let vk = self.keys.verifying_key(&self.params, holder_bound);
+        verify_with_key(&self.params, vk, public_input, proof)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.params.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads parameters previously written with [`ZkdocContext::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let params: Params<EqAffine> = Params::read(&mut BufReader::new(File::open(path)?))?;
+        if params.get_g().len() != 1 << K {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected params for k = {K}"),
+            ));
+        }
+        Ok(Self::from_params(params))
+    }
+
+    /// Derives the verifying keys of both circuits now rather than on the first verification,
+    /// e.g. before a verifier reports ready.
+    pub fn load_verifying_keys(&self) {
+        for holder_bound in [false, true] {
+            self.keys.verifying_key(&self.params, holder_bound);
+        }
+    }
+
+    /// Derives the proving keys of both circuits, and their verifying keys along, now rather
+    /// than on the first proof.
+    pub fn load_proving_keys(&self) {
+        for holder_bound in [false, true] {
+            self.keys.proving_key(&self.params, holder_bound);
+        }
+    }
+
+    /// Same as [`super::generate_proof_cancellable`] with these parameters.
+    pub fn generate_proof(
+        &self,
+        row_title_str: [String; ROW],
+        row_content_str: [String; ROW],
+        row_selector_u64: [u64; ROW],
+        holder_key: Option<&HolderKey>,
+        cancellation: &CancellationToken,
+        mut on_phase: impl FnMut(ProvingPhase),
+    ) -> Result<Vec<u8>, Cancelled> {
+        prove_cancellable(
+            self,
+            row_title_str,
+            row_content_str,
+            row_selector_u64,
+            holder_key,
+            cancellation,
+            &mut on_phase,
+        )
+    }
+
+    /// Same as [`super::verify_correct_selector`], or [`super::verify_holder_bound_selector`]
+    /// for holder bound proofs, with these parameters. Returns `Ok(false)` for well formed
+    /// proofs that do not verify, and an error when the inputs can not be read at all.
+    pub fn verify_proof(
+        &self,
+        commitment: &str,
+        row_accumulator: &str,
+        proof: &[u8],
+        holder_bound: bool,
+    ) -> Result<bool, VerificationError> {
+        let commitment = fp_from_hex(commitment).ok_or(VerificationError::InvalidCommitment)?;
+        let row_accumulator =
+            fp_from_hex(row_accumulator).ok_or(VerificationError::InvalidRowAccumulator)?;
+
+        let vk = self.keys.verifying_key(&self.params, holder_bound);
+        match check_with_key(&self.params, vk, [commitment, row_accumulator], proof) {
+            Ok(()) => Ok(true),
+            Err(plonk::Error::Transcript(_)) => Err(VerificationError::MalformedProof),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Same as [`super::verify_batch`] with these parameters.
+    pub fn verify_batch(&self, items: &[BatchVerificationItem]) -> BatchVerificationResult {
+        verify_batch_with(self, items)
+    }
+
+    /// Same as [`super::verify_presentation`], keeping the verifying keys of the presentations
+    /// seen in this context.
+    pub fn verify_presentation(
+        &self,
+        presentation: &PresentationProof,
+    ) -> Option<Vec<DisclosedDocument>> {
+        verify_presentation_with(&self.presentation_keys, presentation)
+    }
+}
+
+/// Verification inputs that can not be read, as opposed to a proof that does not verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The commitment is not a hex encoded field element
+    InvalidCommitment,
+    /// The row accumulator is not a hex encoded field element
+    InvalidRowAccumulator,
+    /// The proof is truncated or holds invalid curve points
+    MalformedProof,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidCommitment => "invalid commitment",
+            Self::InvalidRowAccumulator => "invalid row accumulator",
+            Self::MalformedProof => "malformed proof",
+        })
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{VerificationError, ZkdocContext};
+    use crate::services::{
+        generate_proof, get_file_commitment_and_selected_row, get_selected_row, CancellationToken,
+    };
+
+    #[test]
+    fn test_context() {
+        let path = std::env::temp_dir().join(format!("zkdoc-params-{}", std::process::id()));
+        ZkdocContext::new().save(&path).unwrap();
+        let context = ZkdocContext::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let row_titles = ["name", "age", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_contents =
+            ["alice", "42", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_selectors = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let commitment =
+            get_file_commitment_and_selected_row(row_titles.clone(), row_contents.clone(), [0; 10]);
+        let row_accumulator = get_selected_row("name".to_owned(), "alice".to_owned());
+
+        let proof = context
+            .generate_proof(
+                row_titles.clone(),
+                row_contents.clone(),
+                row_selectors,
+                None,
+                &CancellationToken::new(),
+                |_| {},
+            )
+            .unwrap();
+        assert_eq!(
+            context.verify_proof(&commitment, &row_accumulator, &proof, false),
+            Ok(true)
+        );
+        assert_eq!(
+            context.verify_proof(&commitment, &row_accumulator, &proof, true),
+            Ok(false)
+        );
+        assert_eq!(
+            context.verify_proof("not a commitment", &row_accumulator, &proof, false),
+            Err(VerificationError::InvalidCommitment)
+        );
+        assert_eq!(
+            context.verify_proof(&commitment, &row_accumulator, &proof[..100], false),
+            Err(VerificationError::MalformedProof)
+        );
+
+        // keys are generated once, and shared with clones
+        let clone = context.clone();
+        assert!(std::ptr::eq(
+            context.keys.verifying_key(&context.params, false),
+            clone.keys.verifying_key(&clone.params, false)
+        ));
+
+        // the parameters are deterministic, so proofs interoperate with the other services
+        let proof = generate_proof(row_titles, row_contents, row_selectors);
+        assert_eq!(
+            context.verify_proof(&commitment, &row_accumulator, &proof, false),
+            Ok(true)
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::OnceLock;
+
+use halo2_proofs::pasta::EqAffine;
+use halo2_proofs::plonk::{keygen_pk, keygen_vk, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::Params;
+
+use super::telemetry::{self, Timer, KEYGEN, KEYGEN_DURATION, KEY_CACHE_HITS};
+use super::{empty_circuit, K};
+
+/// Proving and verifying keys, generated on first use. They only depend on the parameters and
+/// on whether proofs are holder bound, so a [`super::ZkdocContext`] keeps them across proofs,
+/// as does the context shared by the free functions.
+#[derive(Debug, Default)]
+pub(crate) struct Keys {
+    verifying: [OnceLock<VerifyingKey<EqAffine>>; 2],
+    proving: [OnceLock<ProvingKey<EqAffine>>; 2],
+}
+
+impl Keys {
+    pub(crate) fn verifying_key(
+        &self,
+        params: &Params<EqAffine>,
+        holder_bound: bool,
+    ) -> &VerifyingKey<EqAffine> {
+        cached(
+            &self.verifying[holder_bound as usize],
+            holder_bound,
+            "verifying",
+            || keygen_vk(params, &empty_circuit(holder_bound)).expect("keygen_vk should not fail"),
+        )
+    }
+
+    pub(crate) fn proving_key(
+        &self,
+        params: &Params<EqAffine>,
+        holder_bound: bool,
+    ) -> &ProvingKey<EqAffine> {
+        cached(
+            &self.proving[holder_bound as usize],
+            holder_bound,
+            "proving",
+            || {
+                let vk = self.verifying_key(params, holder_bound).clone();
+                keygen_pk(params, vk, &empty_circuit(holder_bound))
+                    .expect("keygen_pk should not fail")
+            },
+        )
+    }
+}
+
+fn cached<'a, T>(
+    cell: &'a OnceLock<T>,
+    holder_bound: bool,
+    key: &'static str,
+    keygen: impl FnOnce() -> T,
+) -> &'a T {
+    let circuit = telemetry::circuit(holder_bound);
+    if let Some(value) = cell.get() {
+        metrics::counter!(KEY_CACHE_HITS, "circuit" => circuit, "key" => key).increment(1);
+        return value;
+    }
+
+    cell.get_or_init(|| {
+        let _span = tracing::info_span!("keygen", k = K, circuit, key).entered();
+        metrics::counter!(KEYGEN, "circuit" => circuit, "key" => key).increment(1);
+        let timer = Timer::start();
+        let value = keygen();
+        timer.record(metrics::histogram!(KEYGEN_DURATION, "circuit" => circuit, "key" => key));
+        value
+    })
+}
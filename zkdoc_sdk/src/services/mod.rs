@@ -1,15 +1,41 @@
 use crate::circuits::file_hasher::FileHashPartialCircuit;
+use crate::holder::HolderKey;
+use crate::utils::conversion::fp_from_hex;
+use crate::utils::poseidon::{self as poseidon_utils, bind_holder_public_key};
 use crate::utils::sha256::{get_sha256, sha256_str_to_u64_arr};
 
 use halo2_gadgets::poseidon::primitives::{self as poseidon, ConstantLength, P128Pow5T3};
 use halo2_proofs::circuit::Value;
 use halo2_proofs::pasta::{EqAffine, Fp};
-use halo2_proofs::plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, SingleVerifier};
+use halo2_proofs::plonk::{self, create_proof, verify_proof, SingleVerifier, VerifyingKey};
 use halo2_proofs::poly::commitment::Params;
 use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255};
 use rand_core::OsRng;
 
-const ROW: usize = 10;
+pub use batch::{verify_batch, BatchVerificationItem, BatchVerificationResult};
+pub use cancellation::{CancellationToken, Cancelled};
+pub use context::{VerificationError, ZkdocContext};
+pub use presentation::{
+    get_document_commitment, verify_presentation, DisclosedDocument, Presentation,
+    PresentationError, PresentationProof, MAX_DOCUMENT_ROWS, MAX_PRESENTATION_DOCUMENTS,
+    MAX_PRESENTATION_K,
+};
+pub use progress::ProvingPhase;
+
+use progress::ProgressTranscript;
+use telemetry::{Timer, COMMITMENT_DURATION, PROVING_DURATION, VERIFICATION_DURATION};
+
+mod batch;
+mod cancellation;
+mod context;
+mod keys;
+mod presentation;
+mod progress;
+pub mod telemetry;
+
+/// Number of rows of a document, fixed by the circuit
+pub const ROW: usize = 10;
+pub(crate) const K: u32 = 12;
 
 pub fn generate_row_hash(row_title_str: String, row_content_str: String) -> String {
     let row_title_u64 = get_sha256(row_title_str.as_str());
@@ -48,64 +74,41 @@ pub fn get_file_commitment_and_selected_row(
     row_contents: [String; ROW],
     row_selectors: [u64; ROW],
 ) -> String {
-    let row_title_u64 = row_titles.map(|x| return get_sha256(x.as_str()));
-    let row_content_u64 = row_contents.map(|x| return get_sha256(x.as_str()));
-
-    let row_title_fp = row_title_u64.map(|x| x.map(Fp::from));
-    let row_content_fp = row_content_u64.map(|x| x.map(Fp::from));
-    let row_selector_fp = row_selectors.map(Fp::from);
-
-    let mut row_hash = Vec::new();
-    let mut row_accumulator = Fp::zero();
-
-    for ((&title, &content), &row_selector) in row_title_fp
-        .iter()
-        .zip(row_content_fp.iter())
-        .zip(row_selector_fp.iter())
-    {
-        let title_message_1 = [title[0], title[1]];
-        let title_message_2 = [title[2], title[3]];
-
-        let title_message_1_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(title_message_1);
-        let title_message_2_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(title_message_2);
-        let title_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-            .hash([title_message_1_output, title_message_2_output]);
-
-        let content_message_1 = [content[0], content[1]];
-        let content_message_2 = [content[2], content[3]];
-
-        let content_message_1_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-                .hash(content_message_1);
-        let content_message_2_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-                .hash(content_message_2);
-        let content_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-            .hash([content_message_1_output, content_message_2_output]);
-        let message = [title_hash, content_hash];
-        let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
-
-        row_hash.push(output);
-
-        if row_selector == Fp::one() {
-            row_accumulator += output;
-        }
-    }
-
-    let mut file_commitment = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-        .hash([row_hash[0], row_hash[1]]);
-
-    for i in row_hash.into_iter().skip(2) {
-        let message = [file_commitment, i];
-        let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
-        file_commitment = output;
-    }
+    let timer = Timer::start();
+    let (row_title, row_content) = hash_rows(row_titles, row_contents);
+    let (file_commitment, _) = poseidon_utils::get_file_commitment_and_selected_row(
+        row_title,
+        row_content,
+        row_selectors.map(Fp::from),
+    );
+    timer.record(metrics::histogram!(COMMITMENT_DURATION));
 
     format!("{file_commitment:?}")
 }
 
+/// Computes the commitment of a holder bound document, where the holder public key
+/// (see [`HolderKey::public_key`]) is committed in a reserved row after the document rows.
+///
+/// Returns `None` if the holder public key is not a hex encoded field element.
+pub fn get_holder_bound_commitment(
+    row_titles: [String; ROW],
+    row_contents: [String; ROW],
+    holder_public_key: String,
+) -> Option<String> {
+    let holder_public_key = fp_from_hex(&holder_public_key)?;
+    let timer = Timer::start();
+    let (row_title, row_content) = hash_rows(row_titles, row_contents);
+    let (file_commitment, _) = poseidon_utils::get_file_commitment_and_selected_row(
+        row_title,
+        row_content,
+        [Fp::zero(); ROW],
+    );
+    let file_commitment = bind_holder_public_key(file_commitment, holder_public_key);
+    timer.record(metrics::histogram!(COMMITMENT_DURATION));
+
+    Some(format!("{file_commitment:?}"))
+}
+
 pub fn get_selected_row(row_title_str: String, row_content_str: String) -> String {
     let row_title_u64 = get_sha256(row_title_str.as_str());
     let row_content_u64 = get_sha256(row_content_str.as_str());
@@ -138,110 +141,233 @@ pub fn get_selected_row(row_title_str: String, row_content_str: String) -> Strin
     format!("{output:?}")
 }
 
+/// Computes the row accumulator of several revealed rows, i.e. the sum of their row hashes
+/// (see [`get_selected_row`]).
+pub fn get_row_accumulator(revealed_rows: &[(String, String)]) -> String {
+    let mut row_accumulator = Fp::zero();
+    for (row_title, row_content) in revealed_rows {
+        row_accumulator += fp_from_hex(&get_selected_row(row_title.clone(), row_content.clone()))
+            .expect("row hash should be a field element");
+    }
+
+    format!("{row_accumulator:?}")
+}
+
 pub fn generate_proof(
     row_title_str: [String; ROW],
     row_content_str: [String; ROW],
     row_selector_u64: [u64; ROW],
 ) -> Vec<u8> {
-    let k = 12;
-    let params: Params<EqAffine> = Params::new(k);
+    prove(
+        ZkdocContext::shared(),
+        row_title_str,
+        row_content_str,
+        row_selector_u64,
+        None,
+    )
+}
 
-    let row_title_u64 = row_title_str.map(|x| return get_sha256(x.as_str()));
-    let row_content_u64 = row_content_str.map(|x| return get_sha256(x.as_str()));
+/// Generates a proof for a holder bound document. Besides the document contents, the proof
+/// requires the holder key whose public key was committed at issuance.
+pub fn generate_holder_bound_proof(
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+    row_selector_u64: [u64; ROW],
+    holder_key: &HolderKey,
+) -> Vec<u8> {
+    prove(
+        ZkdocContext::shared(),
+        row_title_str,
+        row_content_str,
+        row_selector_u64,
+        Some(holder_key),
+    )
+}
 
-    let row_title = row_title_u64.map(|x| x.map(Fp::from));
-    let row_content = row_content_u64.map(|x| x.map(Fp::from));
+/// Generates a proof like [`generate_proof`], or [`generate_holder_bound_proof`] when a holder
+/// key is passed, calling `on_phase` as proving enters each [`ProvingPhase`].
+pub fn generate_proof_with_progress(
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+    row_selector_u64: [u64; ROW],
+    holder_key: Option<&HolderKey>,
+    on_phase: impl FnMut(ProvingPhase),
+) -> Vec<u8> {
+    generate_proof_cancellable(
+        row_title_str,
+        row_content_str,
+        row_selector_u64,
+        holder_key,
+        &CancellationToken::new(),
+        on_phase,
+    )
+    .expect("a token nobody else holds is never cancelled")
+}
+
+/// Generates a proof like [`generate_proof_with_progress`], returning early once the
+/// cancellation token is cancelled. The token is checked between proving phases and between
+/// the prover's commitments.
+pub fn generate_proof_cancellable(
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+    row_selector_u64: [u64; ROW],
+    holder_key: Option<&HolderKey>,
+    cancellation: &CancellationToken,
+    mut on_phase: impl FnMut(ProvingPhase),
+) -> Result<Vec<u8>, Cancelled> {
+    prove_cancellable(
+        ZkdocContext::shared(),
+        row_title_str,
+        row_content_str,
+        row_selector_u64,
+        holder_key,
+        cancellation,
+        &mut on_phase,
+    )
+}
+
+// pass in accumulator_hash and row_accumulator in the form of [u64;4]
+pub fn verify_correct_selector(
+    accumulator_hash: String,
+    row_accumulator: String,
+    proof: Vec<u8>,
+) -> bool {
+    verify(accumulator_hash, row_accumulator, proof, false)
+}
+
+/// Verifies a proof generated by [`generate_holder_bound_proof`].
+pub fn verify_holder_bound_selector(
+    accumulator_hash: String,
+    row_accumulator: String,
+    proof: Vec<u8>,
+) -> bool {
+    verify(accumulator_hash, row_accumulator, proof, true)
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(rows = ROW))]
+pub(crate) fn hash_rows(
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+) -> ([[Fp; 4]; ROW], [[Fp; 4]; ROW]) {
+    let row_title_u64 = row_title_str.map(|x| get_sha256(x.as_str()));
+    let row_content_u64 = row_content_str.map(|x| get_sha256(x.as_str()));
+
+    (
+        row_title_u64.map(|x| x.map(Fp::from)),
+        row_content_u64.map(|x| x.map(Fp::from)),
+    )
+}
+
+pub(crate) fn empty_circuit(holder_bound: bool) -> FileHashPartialCircuit<ROW> {
+    FileHashPartialCircuit::<ROW> {
+        row_title: [[Value::unknown(); 4]; ROW],
+        row_content: [[Value::unknown(); 4]; ROW],
+        row_selectors: [Value::unknown(); ROW],
+        holder_secret: holder_bound.then(Value::unknown),
+    }
+}
+
+pub(crate) fn prove(
+    context: &ZkdocContext,
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+    row_selector_u64: [u64; ROW],
+    holder_key: Option<&HolderKey>,
+) -> Vec<u8> {
+    prove_cancellable(
+        context,
+        row_title_str,
+        row_content_str,
+        row_selector_u64,
+        holder_key,
+        &CancellationToken::new(),
+        &mut |_| {},
+    )
+    .expect("a token nobody else holds is never cancelled")
+}
+
+fn prove_cancellable(
+    context: &ZkdocContext,
+    row_title_str: [String; ROW],
+    row_content_str: [String; ROW],
+    row_selector_u64: [u64; ROW],
+    holder_key: Option<&HolderKey>,
+    cancellation: &CancellationToken,
+    on_phase: &mut dyn FnMut(ProvingPhase),
+) -> Result<Vec<u8>, Cancelled> {
+    cancellation.check()?;
+    let timer = Timer::start();
+    on_phase(ProvingPhase::WitnessHashing);
+    let (row_title, row_content) = hash_rows(row_title_str, row_content_str);
     let row_selector = row_selector_u64.map(Fp::from);
 
     let circuit = FileHashPartialCircuit::<ROW> {
         row_title: row_title.map(|x| x.map(Value::known)),
         row_content: row_content.map(|x| x.map(Value::known)),
         row_selectors: row_selector.map(Value::known),
+        holder_secret: holder_key.map(|key| Value::known(key.secret)),
     };
 
-    let empty_circuit = FileHashPartialCircuit::<ROW> {
-        row_title: [[Value::unknown(); 4]; ROW],
-        row_content: [[Value::unknown(); 4]; ROW],
-        row_selectors: [Value::unknown(); ROW],
-    };
-
-    let mut row_hash = Vec::new();
-    let mut row_accumulator = Fp::zero();
-    for ((&title, &content), &row_selector) in row_title
-        .iter()
-        .zip(row_content.iter())
-        .zip(row_selector.iter())
-    {
-        let title_message_1 = [title[0], title[1]];
-        let title_message_2 = [title[2], title[3]];
-
-        let title_message_1_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(title_message_1);
-        let title_message_2_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(title_message_2);
-        let title_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-            .hash([title_message_1_output, title_message_2_output]);
-
-        let content_message_1 = [content[0], content[1]];
-        let content_message_2 = [content[2], content[3]];
-
-        let content_message_1_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-                .hash(content_message_1);
-        let content_message_2_output =
-            poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-                .hash(content_message_2);
-        let content_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-            .hash([content_message_1_output, content_message_2_output]);
-        let message = [title_hash, content_hash];
-        let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
-
-        row_hash.push(output);
-
-        if row_selector == Fp::one() {
-            row_accumulator += output;
-        }
+    let (mut accumulator_hash, row_accumulator) =
+        poseidon_utils::get_file_commitment_and_selected_row(row_title, row_content, row_selector);
+    if let Some(holder_key) = holder_key {
+        accumulator_hash = bind_holder_public_key(accumulator_hash, holder_key.public_key);
     }
-    let mut accumulator_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
-        .hash([row_hash[0], row_hash[1]]);
-
-    for i in row_hash.into_iter().skip(2) {
-        let message = [accumulator_hash, i];
-        let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
-        accumulator_hash = output;
-    }
-
-    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
-    let pk = keygen_pk(&params, vk, &empty_circuit).expect("keygen_pk should not fail");
 
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    let public_input = vec![accumulator_hash, row_accumulator];
+    cancellation.check()?;
+    on_phase(ProvingPhase::Keygen);
+    let params = &context.params;
+    let pk = context.keys.proving_key(params, holder_key.is_some());
+    let circuit_name = telemetry::circuit(holder_key.is_some());
+
+    cancellation.check()?;
+    let span = tracing::info_span!(
+        "create_proof",
+        k = K,
+        rows = ROW,
+        circuit = circuit_name,
+        proof_size = tracing::field::Empty
+    )
+    .entered();
+    on_phase(ProvingPhase::Commitment);
+    let mut transcript = ProgressTranscript::new(
+        Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]),
+        on_phase,
+        cancellation,
+    );
+    let public_input = [accumulator_hash, row_accumulator];
 
     // Create a proof
-    create_proof(
-        &params,
-        &pk,
+    let result = create_proof(
+        params,
+        pk,
         &[circuit.clone(), circuit],
         &[&[&public_input[..]], &[&public_input[..]]],
         OsRng,
         &mut transcript,
-    )
-    .expect("proof generation should not fail");
+    );
+    // the transcript fails writes once cancelled
+    if let Err(cancelled) = cancellation.check() {
+        tracing::info!("proof generation cancelled");
+        return Err(cancelled);
+    }
+    result.expect("proof generation should not fail");
+    let proof = transcript.into_inner().finalize();
+    span.record("proof_size", proof.len());
+    drop(span);
+    timer.record(metrics::histogram!(PROVING_DURATION, "circuit" => circuit_name));
 
-    transcript.finalize()
+    Ok(proof)
 }
 
-// pass in accumulator_hash and row_accumulator in the form of [u64;4]
-pub fn verify_correct_selector(
+fn verify(
     accumulator_hash: String,
     row_accumulator: String,
     proof: Vec<u8>,
+    holder_bound: bool,
 ) -> bool {
     // verify
 
-    let k = 12;
-    let params: Params<EqAffine> = Params::new(k);
-
     let mut accumulator_hash_u64_array = sha256_str_to_u64_arr(&accumulator_hash);
     accumulator_hash_u64_array.reverse();
     let mut row_accumulator_u64_array = sha256_str_to_u64_arr(&row_accumulator);
@@ -250,25 +376,50 @@ pub fn verify_correct_selector(
     let accumulator_hash = Fp::from_raw(accumulator_hash_u64_array);
     let row_accumulator = Fp::from_raw(row_accumulator_u64_array);
 
-    let empty_circuit = FileHashPartialCircuit::<ROW> {
-        row_title: [[Value::unknown(); 4]; ROW],
-        row_content: [[Value::unknown(); 4]; ROW],
-        row_selectors: [Value::unknown(); ROW],
-    };
-
-    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
-    let pk = keygen_pk(&params, vk, &empty_circuit).expect("keygen_pk should not fail");
+    ZkdocContext::shared().verify_public_input(
+        [accumulator_hash, row_accumulator],
+        &proof,
+        holder_bound,
+    )
+}
 
-    let strategy = SingleVerifier::new(&params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-    let public_input = vec![accumulator_hash, row_accumulator];
+pub(crate) fn verify_with_key(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    public_input: [Fp; 2],
+    proof: &[u8],
+) -> bool {
+    check_with_key(params, vk, public_input, proof).is_ok()
+}
 
-    verify_proof(
-        &params,
-        pk.get_vk(),
+/// Same as [`verify_with_key`], keeping the error apart. Proofs that can not be read, i.e. too
+/// short or with invalid points, fail with [`plonk::Error::Transcript`].
+pub(crate) fn check_with_key(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    public_input: [Fp; 2],
+    proof: &[u8],
+) -> Result<(), plonk::Error> {
+    let _span = tracing::info_span!("verify_proof", k = K, proof_size = proof.len()).entered();
+    let timer = Timer::start();
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+
+    let result = verify_proof(
+        params,
+        vk,
         strategy,
         &[&[&public_input[..]], &[&public_input[..]]],
         &mut transcript,
-    )
-    .is_ok()
+    );
+    timer.record(metrics::histogram!(VERIFICATION_DURATION));
+    let outcome = match &result {
+        Ok(()) => "valid",
+        Err(plonk::Error::Transcript(_)) => "malformed",
+        Err(_) => "invalid",
+    };
+    tracing::debug!(outcome, "verified proof");
+    telemetry::count_verification(outcome);
+
+    result
 }
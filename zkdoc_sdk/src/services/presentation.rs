@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::circuits::multi_document::{DocumentWitness, MultiDocumentCircuit};
+use crate::holder::HolderKey;
+use crate::utils::conversion::fp_from_hex;
+use crate::utils::poseidon::{bind_holder_public_key, get_document_commitment_and_selected_row};
+use crate::utils::sha256::get_sha256;
+
+use halo2_proofs::circuit::Value;
+use halo2_proofs::pasta::{EqAffine, Fp};
+use halo2_proofs::plonk::{
+    create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, SingleVerifier, VerifyingKey,
+};
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255};
+use rand_core::OsRng;
+
+use super::{get_row_accumulator, ZkdocContext};
+
+/// Most rows a document of a presentation may have
+pub const MAX_DOCUMENT_ROWS: usize = 64;
+/// Most documents a presentation may disclose rows of
+pub const MAX_PRESENTATION_DOCUMENTS: usize = 8;
+/// Largest circuit a presentation may be proven with, 2^16 rows or around 190 document rows in
+/// total. Verifiers derive the circuit from the untrusted presentation, so this bounds the work
+/// a presentation can cause.
+pub const MAX_PRESENTATION_K: u32 = 16;
+/// Most presentation verifying keys kept, one per number of rows and holder binding of the
+/// documents
+const MAX_CACHED_VERIFYING_KEYS: usize = 32;
+
+/// A document whose rows are partly revealed by a [`PresentationProof`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisclosedDocument {
+    pub commitment: String,
+    pub row_count: usize,
+    /// Whether the commitment binds a holder key, see [`super::get_holder_bound_commitment`]
+    pub holder_bound: bool,
+    /// Title and content of the revealed rows, in document order
+    pub revealed_rows: Vec<(String, String)>,
+}
+
+/// A single proof disclosing rows of several documents.
+#[derive(Clone, Debug)]
+pub struct PresentationProof {
+    pub documents: Vec<DisclosedDocument>,
+    pub proof: Vec<u8>,
+}
+
+struct PresentationDocument {
+    row_title: Vec<[Fp; 4]>,
+    row_content: Vec<[Fp; 4]>,
+    row_selector: Vec<Fp>,
+    holder_key: Option<HolderKey>,
+    revealed_rows: Vec<(String, String)>,
+}
+
+/// Row count and holder binding of each document, which the presentation circuit depends on
+type Shape = Vec<(usize, bool)>;
+
+/// Parameters of each presentation circuit size, and verifying keys of the presentation
+/// circuits seen lately. Unlike [`super::keys::Keys`], the circuit depends on the number of rows
+/// of each document, so verifying keys are kept per shape of the documents.
+#[derive(Debug, Default)]
+pub(crate) struct PresentationKeys {
+    params: [OnceLock<Params<EqAffine>>; MAX_PRESENTATION_K as usize + 1],
+    verifying: Mutex<HashMap<Shape, Arc<VerifyingKey<EqAffine>>>>,
+}
+
+impl PresentationKeys {
+    fn params(&self, k: u32) -> &Params<EqAffine> {
+        self.params[k as usize].get_or_init(|| Params::new(k))
+    }
+
+    fn verifying_key(
+        &self,
+        params: &Params<EqAffine>,
+        circuit: &MultiDocumentCircuit,
+        shape: Shape,
+    ) -> Option<Arc<VerifyingKey<EqAffine>>> {
+        if let Some(vk) = self.verifying.lock().unwrap().get(&shape) {
+            return Some(vk.clone());
+        }
+
+        // derived outside the lock so other shapes are not held up
+        let vk = Arc::new(keygen_vk(params, circuit).ok()?);
+        let mut verifying = self.verifying.lock().unwrap();
+        if verifying.len() >= MAX_CACHED_VERIFYING_KEYS {
+            if let Some(evicted) = verifying.keys().next().cloned() {
+                verifying.remove(&evicted);
+            }
+        }
+        verifying.insert(shape, vk.clone());
+        Some(vk)
+    }
+}
+
+/// Documents a [`Presentation`] can not be proven for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentationError {
+    /// Row titles, contents and selectors have different lengths
+    MismatchedRows,
+    /// A document has fewer than 2 or more than [`MAX_DOCUMENT_ROWS`] rows
+    InvalidRowCount,
+    /// No documents, or more than [`MAX_PRESENTATION_DOCUMENTS`]
+    InvalidDocumentCount,
+    /// The documents have too many rows in total, see [`MAX_PRESENTATION_K`]
+    TooManyRows,
+    /// The holder public key is not a hex encoded field element
+    InvalidHolderPublicKey,
+}
+
+impl fmt::Display for PresentationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MismatchedRows => {
+                f.write_str("row titles, contents and selectors have different lengths")
+            }
+            Self::InvalidRowCount => {
+                write!(f, "a document has between 2 and {MAX_DOCUMENT_ROWS} rows")
+            }
+            Self::InvalidDocumentCount => write!(
+                f,
+                "a presentation has between 1 and {MAX_PRESENTATION_DOCUMENTS} documents"
+            ),
+            Self::TooManyRows => {
+                f.write_str("the documents have too many rows in total for a presentation")
+            }
+            Self::InvalidHolderPublicKey => f.write_str("invalid holder public key"),
+        }
+    }
+}
+
+impl std::error::Error for PresentationError {}
+
+/// Builds a proof revealing rows from several documents at once, e.g. the name from an ID and
+/// the result from a lab report. Each document may have its own number of rows.
+#[derive(Default)]
+pub struct Presentation {
+    documents: Vec<PresentationDocument>,
+}
+
+impl Presentation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a document, revealing the rows whose selector is 1.
+    pub fn add_document(
+        self,
+        row_titles: Vec<String>,
+        row_contents: Vec<String>,
+        row_selectors: Vec<u64>,
+    ) -> Result<Self, PresentationError> {
+        self.add(row_titles, row_contents, row_selectors, None)
+    }
+
+    /// Adds a holder bound document, which requires the holder key committed at issuance.
+    pub fn add_holder_bound_document(
+        self,
+        row_titles: Vec<String>,
+        row_contents: Vec<String>,
+        row_selectors: Vec<u64>,
+        holder_key: &HolderKey,
+    ) -> Result<Self, PresentationError> {
+        self.add(
+            row_titles,
+            row_contents,
+            row_selectors,
+            Some(holder_key.clone()),
+        )
+    }
+
+    fn add(
+        mut self,
+        row_titles: Vec<String>,
+        row_contents: Vec<String>,
+        row_selectors: Vec<u64>,
+        holder_key: Option<HolderKey>,
+    ) -> Result<Self, PresentationError> {
+        if row_titles.len() != row_contents.len() || row_titles.len() != row_selectors.len() {
+            return Err(PresentationError::MismatchedRows);
+        }
+        if !(2..=MAX_DOCUMENT_ROWS).contains(&row_titles.len()) {
+            return Err(PresentationError::InvalidRowCount);
+        }
+        if self.documents.len() == MAX_PRESENTATION_DOCUMENTS {
+            return Err(PresentationError::InvalidDocumentCount);
+        }
+
+        let revealed_rows = row_titles
+            .iter()
+            .zip(row_contents.iter())
+            .zip(row_selectors.iter())
+            .filter(|(_, &selector)| selector == 1)
+            .map(|((title, content), _)| (title.clone(), content.clone()))
+            .collect();
+        let (row_title, row_content) = hash_rows(&row_titles, &row_contents);
+
+        self.documents.push(PresentationDocument {
+            row_title,
+            row_content,
+            row_selector: row_selectors.into_iter().map(Fp::from).collect(),
+            holder_key,
+            revealed_rows,
+        });
+        Ok(self)
+    }
+
+    /// Generates a single proof for all added documents.
+    pub fn prove(self) -> Result<PresentationProof, PresentationError> {
+        if self.documents.is_empty() {
+            return Err(PresentationError::InvalidDocumentCount);
+        }
+
+        let mut documents = Vec::new();
+        let mut witnesses = Vec::new();
+        let mut public_input = Vec::new();
+        for document in self.documents {
+            let (mut commitment, row_accumulator) = get_document_commitment_and_selected_row(
+                &document.row_title,
+                &document.row_content,
+                &document.row_selector,
+            );
+            if let Some(holder_key) = &document.holder_key {
+                commitment = bind_holder_public_key(commitment, holder_key.public_key);
+            }
+            public_input.extend([commitment, row_accumulator]);
+
+            documents.push(DisclosedDocument {
+                commitment: format!("{commitment:?}"),
+                row_count: document.row_title.len(),
+                holder_bound: document.holder_key.is_some(),
+                revealed_rows: document.revealed_rows,
+            });
+            witnesses.push(DocumentWitness {
+                row_title: known(&document.row_title),
+                row_content: known(&document.row_content),
+                row_selectors: document
+                    .row_selector
+                    .into_iter()
+                    .map(Value::known)
+                    .collect(),
+                holder_secret: document.holder_key.map(|key| Value::known(key.secret)),
+            });
+        }
+
+        let circuit = MultiDocumentCircuit {
+            documents: witnesses,
+        };
+        if circuit.k() > MAX_PRESENTATION_K {
+            return Err(PresentationError::TooManyRows);
+        }
+        let params = ZkdocContext::shared().presentation_keys.params(circuit.k());
+        let empty_circuit = circuit.without_witnesses();
+        let vk = keygen_vk(params, &empty_circuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(params, vk, &empty_circuit).expect("keygen_pk should not fail");
+
+        let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+        create_proof(
+            params,
+            &pk,
+            &[circuit],
+            &[&[&public_input[..]]],
+            OsRng,
+            &mut transcript,
+        )
+        .expect("proof generation should not fail");
+
+        Ok(PresentationProof {
+            documents,
+            proof: transcript.finalize(),
+        })
+    }
+}
+
+/// Verifies a presentation, returning the per document breakdown of the revealed rows if the
+/// proof holds for all the listed commitments and revealed rows. Presentations over the limits,
+/// see [`MAX_PRESENTATION_K`], are rejected before deriving any key.
+pub fn verify_presentation(presentation: &PresentationProof) -> Option<Vec<DisclosedDocument>> {
+    ZkdocContext::shared().verify_presentation(presentation)
+}
+
+pub(super) fn verify_presentation_with(
+    keys: &PresentationKeys,
+    presentation: &PresentationProof,
+) -> Option<Vec<DisclosedDocument>> {
+    if presentation.documents.is_empty()
+        || presentation.documents.len() > MAX_PRESENTATION_DOCUMENTS
+    {
+        return None;
+    }
+
+    let mut public_input = Vec::new();
+    for document in &presentation.documents {
+        if !(2..=MAX_DOCUMENT_ROWS).contains(&document.row_count) {
+            return None;
+        }
+        public_input.extend([
+            fp_from_hex(&document.commitment)?,
+            fp_from_hex(&get_row_accumulator(&document.revealed_rows))?,
+        ]);
+    }
+
+    let circuit = MultiDocumentCircuit {
+        documents: presentation
+            .documents
+            .iter()
+            .map(|document| DocumentWitness::unknown(document.row_count, document.holder_bound))
+            .collect(),
+    };
+    if circuit.k() > MAX_PRESENTATION_K {
+        return None;
+    }
+    let params = keys.params(circuit.k());
+    let shape = presentation
+        .documents
+        .iter()
+        .map(|document| (document.row_count, document.holder_bound))
+        .collect();
+    let vk = keys.verifying_key(params, &circuit, shape)?;
+
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&presentation.proof[..]);
+    verify_proof(
+        params,
+        &vk,
+        strategy,
+        &[&[&public_input[..]]],
+        &mut transcript,
+    )
+    .ok()?;
+
+    Some(presentation.documents.clone())
+}
+
+/// Computes the commitment of a document with any number of rows, optionally binding the holder
+/// public key (see [`HolderKey::public_key`]).
+pub fn get_document_commitment(
+    row_titles: Vec<String>,
+    row_contents: Vec<String>,
+    holder_public_key: Option<String>,
+) -> Result<String, PresentationError> {
+    if row_titles.len() != row_contents.len() {
+        return Err(PresentationError::MismatchedRows);
+    }
+    if !(2..=MAX_DOCUMENT_ROWS).contains(&row_titles.len()) {
+        return Err(PresentationError::InvalidRowCount);
+    }
+
+    let (row_title, row_content) = hash_rows(&row_titles, &row_contents);
+    let (mut commitment, _) = get_document_commitment_and_selected_row(
+        &row_title,
+        &row_content,
+        &vec![Fp::zero(); row_title.len()],
+    );
+    if let Some(holder_public_key) = holder_public_key {
+        let holder_public_key =
+            fp_from_hex(&holder_public_key).ok_or(PresentationError::InvalidHolderPublicKey)?;
+        commitment = bind_holder_public_key(commitment, holder_public_key);
+    }
+
+    Ok(format!("{commitment:?}"))
+}
+
+fn hash_rows(row_titles: &[String], row_contents: &[String]) -> (Vec<[Fp; 4]>, Vec<[Fp; 4]>) {
+    (
+        row_titles
+            .iter()
+            .map(|x| get_sha256(x.as_str()).map(Fp::from))
+            .collect(),
+        row_contents
+            .iter()
+            .map(|x| get_sha256(x.as_str()).map(Fp::from))
+            .collect(),
+    )
+}
+
+fn known(rows: &[[Fp; 4]]) -> Vec<[Value<Fp>; 4]> {
+    rows.iter().map(|x| x.map(Value::known)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_document_commitment, verify_presentation, Presentation, PresentationError,
+        MAX_DOCUMENT_ROWS, MAX_PRESENTATION_DOCUMENTS,
+    };
+    use crate::holder::HolderKey;
+
+    #[test]
+    fn test_presentation() {
+        let holder_key = HolderKey::generate();
+        let id_titles = vec!["name".to_owned(), "birthdate".to_owned()];
+        let id_contents = vec!["alice".to_owned(), "1990-01-01".to_owned()];
+        let report_titles = ["test", "result", "date"].map(String::from).to_vec();
+        let report_contents = ["glucose", "negative", "2023-02-01"]
+            .map(String::from)
+            .to_vec();
+
+        let presentation = Presentation::new()
+            .add_document(id_titles.clone(), id_contents.clone(), vec![1, 0])
+            .unwrap()
+            .add_holder_bound_document(
+                report_titles.clone(),
+                report_contents.clone(),
+                vec![0, 1, 0],
+                &holder_key,
+            )
+            .unwrap()
+            .prove()
+            .unwrap();
+
+        assert_eq!(
+            presentation.documents[0].commitment,
+            get_document_commitment(id_titles.clone(), id_contents.clone(), None).unwrap()
+        );
+        assert_eq!(
+            presentation.documents[1].commitment,
+            get_document_commitment(
+                report_titles,
+                report_contents,
+                Some(holder_key.public_key())
+            )
+            .unwrap()
+        );
+
+        let documents = verify_presentation(&presentation).unwrap();
+        assert_eq!(
+            documents[0].revealed_rows,
+            vec![("name".to_owned(), "alice".to_owned())]
+        );
+        assert_eq!(
+            documents[1].revealed_rows,
+            vec![("result".to_owned(), "negative".to_owned())]
+        );
+
+        let mut tampered = presentation.clone();
+        tampered.documents[1].revealed_rows[0].1 = "positive".to_owned();
+        assert!(verify_presentation(&tampered).is_none());
+
+        let mut reordered = presentation.clone();
+        reordered.documents.swap(0, 1);
+        assert!(verify_presentation(&reordered).is_none());
+
+        // rejected before deriving keys for a huge circuit
+        let mut too_many_rows = presentation.clone();
+        too_many_rows.documents[0].row_count = 1 << 30;
+        assert!(verify_presentation(&too_many_rows).is_none());
+        too_many_rows.documents[0].row_count = MAX_DOCUMENT_ROWS + 1;
+        assert!(verify_presentation(&too_many_rows).is_none());
+        let mut too_many_documents = presentation.clone();
+        too_many_documents.documents =
+            vec![presentation.documents[0].clone(); MAX_PRESENTATION_DOCUMENTS + 1];
+        assert!(verify_presentation(&too_many_documents).is_none());
+        let mut too_large = presentation;
+        for document in &mut too_large.documents {
+            document.row_count = MAX_DOCUMENT_ROWS;
+        }
+        too_large.documents = vec![too_large.documents[0].clone(); 4];
+        assert!(verify_presentation(&too_large).is_none());
+
+        assert_eq!(
+            get_document_commitment(
+                id_titles.clone(),
+                id_contents.clone(),
+                Some("zz".to_owned())
+            ),
+            Err(PresentationError::InvalidHolderPublicKey)
+        );
+        assert_eq!(
+            get_document_commitment(id_titles[..1].to_vec(), id_contents[..1].to_vec(), None),
+            Err(PresentationError::InvalidRowCount)
+        );
+        assert_eq!(
+            Presentation::new()
+                .add_document(id_titles.clone(), id_contents.clone(), vec![1])
+                .err(),
+            Some(PresentationError::MismatchedRows)
+        );
+        assert_eq!(
+            Presentation::new()
+                .add_document(
+                    vec![String::new(); MAX_DOCUMENT_ROWS + 1],
+                    vec![String::new(); MAX_DOCUMENT_ROWS + 1],
+                    vec![0; MAX_DOCUMENT_ROWS + 1],
+                )
+                .err(),
+            Some(PresentationError::InvalidRowCount)
+        );
+        let mut full = Presentation::new();
+        for _ in 0..MAX_PRESENTATION_DOCUMENTS {
+            full = full
+                .add_document(id_titles.clone(), id_contents.clone(), vec![1, 0])
+                .unwrap();
+        }
+        assert_eq!(
+            full.add_document(id_titles, id_contents, vec![1, 0]).err(),
+            Some(PresentationError::InvalidDocumentCount)
+        );
+        assert_eq!(
+            Presentation::new().prove().err(),
+            Some(PresentationError::InvalidDocumentCount)
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::io;
+
+use halo2_proofs::arithmetic::CurveAffine;
+use halo2_proofs::transcript::{EncodedChallenge, Transcript, TranscriptWrite};
+
+use super::cancellation::CancellationToken;
+
+/// Phases of proof generation, reported in this order by
+/// [`super::generate_proof_with_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProvingPhase {
+    /// Hashing the document rows into the witness and computing the public inputs
+    WitnessHashing,
+    /// Generating the proving key
+    Keygen,
+    /// Synthesizing the circuit and committing to the witness, lookup and permutation polynomials
+    Commitment,
+    /// Computing and committing to the quotient polynomial
+    Quotient,
+    /// Evaluating the polynomials and creating the opening proof
+    Opening,
+}
+
+impl ProvingPhase {
+    pub const ALL: [ProvingPhase; 5] = [
+        ProvingPhase::WitnessHashing,
+        ProvingPhase::Keygen,
+        ProvingPhase::Commitment,
+        ProvingPhase::Quotient,
+        ProvingPhase::Opening,
+    ];
+
+    /// Stable identifier, e.g. for APIs.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProvingPhase::WitnessHashing => "witness_hashing",
+            ProvingPhase::Keygen => "keygen",
+            ProvingPhase::Commitment => "commitment",
+            ProvingPhase::Quotient => "quotient",
+            ProvingPhase::Opening => "opening",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ProvingPhase::WitnessHashing => "Hashing document rows",
+            ProvingPhase::Keygen => "Generating proving key",
+            ProvingPhase::Commitment => "Committing to witness",
+            ProvingPhase::Quotient => "Computing quotient polynomial",
+            ProvingPhase::Opening => "Creating opening proof",
+        }
+    }
+
+    /// Position of the phase in [`ProvingPhase::ALL`].
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Challenges squeezed by `create_proof` before the quotient polynomial is computed, i.e.
+/// theta, beta, gamma and y.
+const QUOTIENT_CHALLENGE: usize = 4;
+/// The evaluation point x is squeezed once the quotient polynomial is committed.
+const OPENING_CHALLENGE: usize = 5;
+
+/// Transcript reporting the proving phases as `create_proof` squeezes its challenges, as
+/// `halo2_proofs` has no other hooks into the prover. Once cancelled, writes fail so that
+/// `create_proof` returns early.
+pub(crate) struct ProgressTranscript<'a, T> {
+    inner: T,
+    challenges: usize,
+    on_phase: &'a mut dyn FnMut(ProvingPhase),
+    cancellation: &'a CancellationToken,
+}
+
+impl<'a, T> ProgressTranscript<'a, T> {
+    pub(crate) fn new(
+        inner: T,
+        on_phase: &'a mut dyn FnMut(ProvingPhase),
+        cancellation: &'a CancellationToken,
+    ) -> Self {
+        Self {
+            inner,
+            challenges: 0,
+            on_phase,
+            cancellation,
+        }
+    }
+
+    fn check_cancelled(&self) -> io::Result<()> {
+        self.cancellation
+            .check()
+            .map_err(|cancelled| io::Error::new(io::ErrorKind::Interrupted, cancelled))
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<'a, C, E, T> Transcript<C, E> for ProgressTranscript<'a, T>
+where
+    C: CurveAffine,
+    E: EncodedChallenge<C>,
+    T: Transcript<C, E>,
+{
+    fn squeeze_challenge(&mut self) -> E {
+        let challenge = self.inner.squeeze_challenge();
+        self.challenges += 1;
+        match self.challenges {
+            QUOTIENT_CHALLENGE => (self.on_phase)(ProvingPhase::Quotient),
+            OPENING_CHALLENGE => (self.on_phase)(ProvingPhase::Opening),
+            _ => {}
+        }
+        challenge
+    }
+
+    fn common_point(&mut self, point: C) -> io::Result<()> {
+        self.check_cancelled()?;
+        self.inner.common_point(point)
+    }
+
+    fn common_scalar(&mut self, scalar: C::Scalar) -> io::Result<()> {
+        self.check_cancelled()?;
+        self.inner.common_scalar(scalar)
+    }
+}
+
+impl<'a, C, E, T> TranscriptWrite<C, E> for ProgressTranscript<'a, T>
+where
+    C: CurveAffine,
+    E: EncodedChallenge<C>,
+    T: TranscriptWrite<C, E>,
+{
+    fn write_point(&mut self, point: C) -> io::Result<()> {
+        self.check_cancelled()?;
+        self.inner.write_point(point)
+    }
+
+    fn write_scalar(&mut self, scalar: C::Scalar) -> io::Result<()> {
+        self.check_cancelled()?;
+        self.inner.write_scalar(scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProvingPhase;
+    use crate::services::{
+        generate_proof_with_progress, get_file_commitment_and_selected_row, get_selected_row,
+        verify_correct_selector,
+    };
+
+    #[test]
+    fn test_proving_phases() {
+        let row_titles = ["name", "age", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_contents =
+            ["alice", "42", "-", "-", "-", "-", "-", "-", "-", "-"].map(String::from);
+        let row_selectors = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let mut phases = Vec::new();
+        let proof = generate_proof_with_progress(
+            row_titles.clone(),
+            row_contents.clone(),
+            row_selectors,
+            None,
+            |phase| phases.push(phase),
+        );
+        assert_eq!(phases, ProvingPhase::ALL);
+
+        let commitment = get_file_commitment_and_selected_row(row_titles, row_contents, [0; 10]);
+        let row_accumulator = get_selected_row("name".to_owned(), "alice".to_owned());
+        assert!(verify_correct_selector(commitment, row_accumulator, proof));
+    }
+}
@@ -0,0 +1,83 @@
+//! Metrics recorded by the services through the [`metrics`] facade. They cost nothing until the
+//! application installs a recorder, e.g. a Prometheus exporter.
+
+use metrics::{describe_counter, describe_histogram, Histogram, Unit};
+
+/// Time to compute a commitment
+pub const COMMITMENT_DURATION: &str = "zkdoc_commitment_duration_seconds";
+/// Time to generate a proof, keygen included, labelled by `circuit`
+pub const PROVING_DURATION: &str = "zkdoc_proving_duration_seconds";
+/// Time to verify a proof
+pub const VERIFICATION_DURATION: &str = "zkdoc_verification_duration_seconds";
+/// Time to verify a batch of proofs
+pub const BATCH_VERIFICATION_DURATION: &str = "zkdoc_batch_verification_duration_seconds";
+/// Time to generate a key, labelled by `circuit` and `key`
+pub const KEYGEN_DURATION: &str = "zkdoc_keygen_duration_seconds";
+/// Keys generated, labelled by `circuit` and `key`
+pub const KEYGEN: &str = "zkdoc_keygen_total";
+/// Keys reused from a [`super::ZkdocContext`], labelled by `circuit` and `key`
+pub const KEY_CACHE_HITS: &str = "zkdoc_key_cache_hits_total";
+/// Proofs verified, labelled by `result`: `valid`, `invalid` or `malformed`
+pub const VERIFICATIONS: &str = "zkdoc_verifications_total";
+
+/// Describes the metrics to the installed recorder, which then exports them with their help text
+/// and unit.
+pub fn describe() {
+    describe_histogram!(
+        COMMITMENT_DURATION,
+        Unit::Seconds,
+        "Time to compute a commitment"
+    );
+    describe_histogram!(
+        PROVING_DURATION,
+        Unit::Seconds,
+        "Time to generate a proof, keygen included"
+    );
+    describe_histogram!(
+        VERIFICATION_DURATION,
+        Unit::Seconds,
+        "Time to verify a proof"
+    );
+    describe_histogram!(
+        BATCH_VERIFICATION_DURATION,
+        Unit::Seconds,
+        "Time to verify a batch of proofs"
+    );
+    describe_histogram!(KEYGEN_DURATION, Unit::Seconds, "Time to generate a key");
+    describe_counter!(KEYGEN, "Proving and verifying keys generated");
+    describe_counter!(KEY_CACHE_HITS, "Keys reused rather than generated");
+    describe_counter!(VERIFICATIONS, "Proofs verified, by result");
+}
+
+pub(crate) fn circuit(holder_bound: bool) -> &'static str {
+    match holder_bound {
+        true => "holder_bound",
+        false => "plain",
+    }
+}
+
+pub(crate) fn count_verification(result: &'static str) {
+    metrics::counter!(VERIFICATIONS, "result" => result).increment(1);
+}
+
+/// Measures a duration. `Instant` panics on wasm, where nothing is measured.
+pub(crate) struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn record(self, histogram: Histogram) {
+        #[cfg(not(target_arch = "wasm32"))]
+        histogram.record(self.start.elapsed());
+        #[cfg(target_arch = "wasm32")]
+        let _ = histogram;
+    }
+}
@@ -1,3 +1,6 @@
+use ff::PrimeField;
+use halo2_proofs::pasta::Fp;
+
 #[inline]
 pub fn convert_hash_u32_to_u64(hash_u32: [u32; 8]) -> [u64; 4] {
     let mut res = Vec::new();
@@ -59,3 +62,56 @@ pub fn convert_to_u64_array(input: &[u64]) -> [u64; 4] {
 
     res.try_into().unwrap()
 }
+
+/// Parses a field element from the `0x`-prefixed big-endian hex produced by `format!("{:?}", fp)`.
+///
+/// Returns `None` if the input is not 32 bytes of hex or is not a canonical field element.
+pub fn fp_from_hex(input: &str) -> Option<Fp> {
+    let hex = input.trim_start_matches("0x");
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut repr = [0u8; 32];
+    for (i, byte) in repr.iter_mut().rev().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Option::from(Fp::from_repr(repr))
+}
+
+/// Returns the little-endian u64 limbs of a field element, on any pointer width.
+pub fn fp_to_u64_array(fp: Fp) -> [u64; 4] {
+    let repr = fp.to_repr();
+    let mut res = [0u64; 4];
+    for (limb, bytes) in res.iter_mut().zip(repr.chunks_exact(8)) {
+        *limb = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::pasta::Fp;
+
+    use super::{fp_from_hex, fp_to_u64_array};
+
+    #[test]
+    fn test_fp_from_hex() {
+        let value = Fp::from(123456789);
+        assert_eq!(fp_from_hex(&format!("{value:?}")), Some(value));
+        assert_eq!(fp_from_hex(&format!("{:?}", -value)), Some(-value));
+
+        assert_eq!(fp_from_hex("0x1234"), None);
+        assert_eq!(fp_from_hex(&"f".repeat(64)), None);
+    }
+
+    #[test]
+    fn test_fp_to_u64_array() {
+        assert_eq!(fp_to_u64_array(Fp::from(5)), [5, 0, 0, 0]);
+
+        let limbs = [u64::MAX, 1, 2, 3];
+        assert_eq!(fp_to_u64_array(Fp::from_raw(limbs)), limbs);
+    }
+}
@@ -39,6 +39,15 @@ pub fn get_file_commitment_and_selected_row<const L: usize>(
     row_title: [[Fp; 4]; L],
     row_content: [[Fp; 4]; L],
     row_selector: [Fp; L],
+) -> (Fp, Fp) {
+    get_document_commitment_and_selected_row(&row_title, &row_content, &row_selector)
+}
+
+/// Same as [`get_file_commitment_and_selected_row`], for documents with any number of rows.
+pub fn get_document_commitment_and_selected_row(
+    row_title: &[[Fp; 4]],
+    row_content: &[[Fp; 4]],
+    row_selector: &[Fp],
 ) -> (Fp, Fp) {
     let mut row_hash = Vec::new();
     let mut row_accumulator = Fp::zero();
@@ -92,12 +101,23 @@ pub fn get_file_commitment_and_selected_row<const L: usize>(
     (file_commitment, row_accumulator)
 }
 
+/// Derives the holder public key from the holder secret, i.e. `Poseidon(secret, 0)`.
+pub fn get_holder_public_key(holder_secret: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
+        .hash([holder_secret, Fp::zero()])
+}
+
+/// Appends the holder public key to the file commitment as the reserved holder row.
+pub fn bind_holder_public_key(file_commitment: Fp, holder_public_key: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
+        .hash([file_commitment, holder_public_key])
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::conversion::{
-        convert_hash_u32_to_u64, convert_hash_u64_to_u32, convert_to_u64_array,
+        convert_hash_u32_to_u64, convert_hash_u64_to_u32, convert_to_u64_array, fp_to_u64_array,
     };
-    use bitvec::array::BitArray;
     use ff::PrimeFieldBits;
     use halo2_gadgets::poseidon::primitives::{self as poseidon, ConstantLength, P128Pow5T3};
     use halo2_proofs::pasta::Fp;
@@ -113,7 +133,7 @@ mod tests {
 
         let hash_1_u64 = convert_hash_u32_to_u64(hash_1_u32);
         println!("hash_1_u64: {:?}", hash_1_u64);
-        let hash_1_fp = hash_1_u64.map(|x| return Fp::from(x));
+        let hash_1_fp = hash_1_u64.map(Fp::from);
 
         let row_title = [hash_1_fp, hash_1_fp];
         let row_content = [hash_1_fp, hash_1_fp];
@@ -161,8 +181,8 @@ mod tests {
         let mut accumulator_hash = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init()
             .hash([row_hash[0], row_hash[1]]);
 
-        for i in 2..row_content.len() {
-            let message = [accumulator_hash, row_hash[i]];
+        for i in row_hash.into_iter().skip(2) {
+            let message = [accumulator_hash, i];
             let output =
                 poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
             accumulator_hash = output;
@@ -175,13 +195,7 @@ mod tests {
         );
         println!(
             "row_accumulator_u32: {:?}",
-            convert_hash_u64_to_u32(
-                row_accumulator
-                    .to_le_bits()
-                    .as_raw_slice()
-                    .try_into()
-                    .unwrap()
-            )
+            convert_hash_u64_to_u32(fp_to_u64_array(row_accumulator))
         );
 
         // let xx = row_accumulator.to_le_bits();
@@ -195,13 +209,13 @@ mod tests {
             1803989619, 4281662689, 2641068110, 4284104535, 1202562282, 2720996681, 3223212765,
             3079101259,
         ];
-        let row_content_u32 = row_title_u32.clone();
+        let row_content_u32 = row_title_u32;
 
         let row_title_u64 = convert_hash_u32_to_u64(row_title_u32);
         let row_content_u64 = convert_hash_u32_to_u64(row_content_u32);
 
-        let row_title = row_title_u64.map(|x| return Fp::from(x));
-        let row_content = row_content_u64.map(|x| return Fp::from(x));
+        let row_title = row_title_u64.map(Fp::from);
+        let row_content = row_content_u64.map(Fp::from);
 
         let title_message_1 = [row_title[0], row_title[1]];
         let title_message_2 = [row_title[2], row_title[3]];
@@ -227,13 +241,12 @@ mod tests {
         let message = [title_hash, content_hash];
         let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
 
-        let output_le_bits: BitArray<[u64; 4]> = output.to_le_bits();
-        let output_raw_slice = output_le_bits.as_raw_slice();
+        let output_raw_slice = fp_to_u64_array(output);
 
         println!("output_raw_slice: {:?}", output_raw_slice);
 
-        let output_u32 = convert_hash_u64_to_u32(output_raw_slice.try_into().unwrap());
-        let output_u32_2 = convert_hash_u64_to_u32(convert_to_u64_array(output_raw_slice));
+        let output_u32 = convert_hash_u64_to_u32(output_raw_slice);
+        let output_u32_2 = convert_hash_u64_to_u32(convert_to_u64_array(&output_raw_slice));
 
         println!("output_u32: {:?}", output_u32);
         println!("output_u32_2: {:?}", output_u32_2);
@@ -3,10 +3,7 @@ use sha2::{Digest, Sha256};
 pub fn get_sha256(input: &str) -> [u64; 4] {
     let hashed = Sha256::digest(input);
 
-    #[cfg(test)]
-    {
-        println!("hashed: {:x}", hashed);
-    }
+    tracing::trace!(hash = %format_args!("{hashed:x}"), "hashed input");
 
     let x: [u8; 32] = hashed.as_slice().try_into().expect("Wrong length");
     let pow_0 = u64::pow(256, 0);
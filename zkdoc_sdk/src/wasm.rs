@@ -0,0 +1,213 @@
+//! JavaScript bindings, enabled with the `wasm` feature.
+//!
+//! Generating the proving parameters is slow, so they are generated once with
+//! [`generate_params`] and passed as bytes afterwards. The proving and verifying keys are
+//! derived deterministically from the parameters, as `halo2_proofs` 0.2 can not serialize them.
+//!
+//! Proving runs on the calling thread, as threads can not be spawned on `wasm32`.
+
+use std::convert::TryInto;
+
+use halo2_proofs::pasta::EqAffine;
+use halo2_proofs::plonk::keygen_vk;
+use halo2_proofs::poly::commitment::Params;
+use wasm_bindgen::prelude::*;
+
+use crate::holder::HolderKey;
+use crate::services::{self, empty_circuit, prove, verify_with_key, ZkdocContext, K, ROW};
+use crate::utils::conversion::fp_from_hex;
+
+/// Generates the parameters shared by provers and verifiers.
+#[wasm_bindgen(js_name = generateParams)]
+pub fn generate_params() -> Vec<u8> {
+    init_thread_pool();
+    let params: Params<EqAffine> = Params::new(K);
+    let mut bytes = Vec::new();
+    params
+        .write(&mut bytes)
+        .expect("writing to a vec should not fail");
+    bytes
+}
+
+#[wasm_bindgen(js_name = getCommitment)]
+pub fn get_commitment(
+    row_titles: Box<[JsValue]>,
+    row_contents: Box<[JsValue]>,
+) -> Result<String, JsError> {
+    Ok(services::get_file_commitment_and_selected_row(
+        to_rows(&row_titles)?,
+        to_rows(&row_contents)?,
+        [0; ROW],
+    ))
+}
+
+#[wasm_bindgen(js_name = getHolderBoundCommitment)]
+pub fn get_holder_bound_commitment(
+    row_titles: Box<[JsValue]>,
+    row_contents: Box<[JsValue]>,
+    holder_public_key: String,
+) -> Result<String, JsError> {
+    services::get_holder_bound_commitment(
+        to_rows(&row_titles)?,
+        to_rows(&row_contents)?,
+        holder_public_key,
+    )
+    .ok_or_else(|| JsError::new("invalid holder public key"))
+}
+
+#[wasm_bindgen(js_name = getSelectedRow)]
+pub fn get_selected_row(row_title: String, row_content: String) -> String {
+    services::get_selected_row(row_title, row_content)
+}
+
+/// Generates a proof revealing the rows whose selector is 1. Passing the holder secret
+/// generates a holder bound proof.
+#[wasm_bindgen(js_name = generateProof)]
+pub fn generate_proof(
+    params: &[u8],
+    row_titles: Box<[JsValue]>,
+    row_contents: Box<[JsValue]>,
+    row_selectors: &[u32],
+    holder_secret: Option<String>,
+) -> Result<Vec<u8>, JsError> {
+    let params = read_params(params)?;
+    init_thread_pool();
+    let row_selectors: [u32; ROW] = row_selectors
+        .try_into()
+        .map_err(|_| JsError::new(&format!("expected {ROW} row selectors")))?;
+    let holder_key = holder_secret
+        .map(|secret| {
+            HolderKey::from_secret_hex(&secret).ok_or_else(|| JsError::new("invalid holder secret"))
+        })
+        .transpose()?;
+
+    Ok(prove(
+        &ZkdocContext::from_params(params),
+        to_rows(&row_titles)?,
+        to_rows(&row_contents)?,
+        row_selectors.map(u64::from),
+        holder_key.as_ref(),
+    ))
+}
+
+#[wasm_bindgen(js_name = verifyProof)]
+pub fn verify_proof(
+    params: &[u8],
+    commitment: String,
+    row_accumulator: String,
+    proof: &[u8],
+    holder_bound: bool,
+) -> Result<bool, JsError> {
+    let params = read_params(params)?;
+    init_thread_pool();
+    let public_input = [
+        fp_from_hex(&commitment).ok_or_else(|| JsError::new("invalid commitment"))?,
+        fp_from_hex(&row_accumulator).ok_or_else(|| JsError::new("invalid row accumulator"))?,
+    ];
+
+    let vk = keygen_vk(&params, &empty_circuit(holder_bound)).expect("keygen_vk should not fail");
+    Ok(verify_with_key(&params, &vk, public_input, proof))
+}
+
+#[wasm_bindgen(js_name = generateHolderKey)]
+pub fn generate_holder_key() -> String {
+    HolderKey::generate().secret()
+}
+
+#[wasm_bindgen(js_name = getHolderPublicKey)]
+pub fn get_holder_public_key(holder_secret: String) -> Result<String, JsError> {
+    HolderKey::from_secret_hex(&holder_secret)
+        .map(|holder_key| holder_key.public_key())
+        .ok_or_else(|| JsError::new("invalid holder secret"))
+}
+
+/// `halo2_proofs` parallelizes with rayon, whose global pool would otherwise try to spawn
+/// threads on first use.
+fn init_thread_pool() {
+    #[cfg(target_arch = "wasm32")]
+    {
+        // fails if the pool was already initialized, which is fine
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .use_current_thread()
+            .build_global();
+    }
+}
+
+fn read_params(bytes: &[u8]) -> Result<Params<EqAffine>, JsError> {
+    let params: Params<EqAffine> = Params::read(&mut &bytes[..])
+        .map_err(|err| JsError::new(&format!("invalid params: {err}")))?;
+    if params.get_g().len() != 1 << K {
+        return Err(JsError::new(&format!("expected params for k = {K}")));
+    }
+    Ok(params)
+}
+
+fn to_rows(values: &[JsValue]) -> Result<[String; ROW], JsError> {
+    let rows = values
+        .iter()
+        .map(|value| {
+            value
+                .as_string()
+                .ok_or_else(|| JsError::new("rows should be strings"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    rows.try_into()
+        .map_err(|_| JsError::new(&format!("expected {ROW} rows")))
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{
+        generate_holder_key, generate_params, generate_proof, get_commitment,
+        get_holder_public_key, get_selected_row, verify_proof,
+    };
+
+    fn rows(values: [&str; 10]) -> Box<[JsValue]> {
+        values.map(JsValue::from).into()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_prove_and_verify() {
+        let row_titles = ["name", "age", "-", "-", "-", "-", "-", "-", "-", "-"];
+        let row_contents = ["alice", "42", "-", "-", "-", "-", "-", "-", "-", "-"];
+
+        let params = generate_params();
+        let commitment = get_commitment(rows(row_titles), rows(row_contents)).unwrap();
+        let proof = generate_proof(
+            &params,
+            rows(row_titles),
+            rows(row_contents),
+            &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            None,
+        )
+        .unwrap();
+
+        let row_accumulator = get_selected_row("name".to_owned(), "alice".to_owned());
+        assert!(verify_proof(&params, commitment.clone(), row_accumulator, &proof, false).unwrap());
+
+        let row_accumulator = get_selected_row("name".to_owned(), "bob".to_owned());
+        assert!(!verify_proof(&params, commitment, row_accumulator, &proof, false).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_invalid_inputs() {
+        let row_titles = ["name", "-", "-", "-", "-", "-", "-", "-", "-", "-"];
+
+        assert!(get_commitment(rows(row_titles), rows(row_titles)[..2].into()).is_err());
+        assert!(generate_proof(
+            &[1, 2, 3],
+            rows(row_titles),
+            rows(row_titles),
+            &[0; 10],
+            None
+        )
+        .is_err());
+        assert!(get_holder_public_key("not a key".to_owned()).is_err());
+        assert!(get_holder_public_key(generate_holder_key()).is_ok());
+    }
+}
@@ -0,0 +1,47 @@
+//! Builds a C program against the header and the shared library, on unix with the `ffi`
+//! feature only.
+#![cfg(all(unix, feature = "ffi"))]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory holding the `cdylib` built along with this test, i.e. `target/<profile>/deps`.
+fn library_dir() -> PathBuf {
+    let test_exe = env::current_exe().unwrap();
+    test_exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn test_c_program() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let program = env::temp_dir().join(format!("zkdoc-ffi-test-{}", std::process::id()));
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .arg(manifest_dir.join("tests/ffi/zkdoc_test.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&library_dir)
+        .arg("-lzkdoc_sdk")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("a C compiler should be installed");
+    assert!(status.success(), "compiling the C test program failed");
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DYLD_LIBRARY_PATH", &library_dir)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&program).unwrap();
+
+    assert!(
+        output.status.success(),
+        "C test program failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
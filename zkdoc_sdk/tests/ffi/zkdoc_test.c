@@ -0,0 +1,97 @@
+// Exercises the C ABI of zkdoc_sdk, linked against the cdylib. Run through `cargo test`.
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "zkdoc.h"
+
+#define ROWS 10
+
+#define CHECK(cond)                                                                    \
+  do {                                                                                 \
+    if (!(cond)) {                                                                     \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);         \
+      exit(1);                                                                         \
+    }                                                                                  \
+  } while (0)
+
+static ZkdocSlice str_slice(const char *str) {
+  ZkdocSlice slice = {(const uint8_t *)str, strlen(str)};
+  return slice;
+}
+
+static ZkdocSlice buffer_slice(ZkdocBuffer buffer) {
+  ZkdocSlice slice = {buffer.data, buffer.len};
+  return slice;
+}
+
+int main(void) {
+  const char *titles[ROWS] = {"name", "age", "-", "-", "-", "-", "-", "-", "-", "-"};
+  const char *contents[ROWS] = {"alice", "42", "-", "-", "-", "-", "-", "-", "-", "-"};
+  const uint64_t selectors[ROWS] = {1, 0, 0, 0, 0, 0, 0, 0, 0, 0};
+  const ZkdocSlice none = {NULL, 0};
+
+  ZkdocSlice row_titles[ROWS];
+  ZkdocSlice row_contents[ROWS];
+  for (int i = 0; i < ROWS; i++) {
+    row_titles[i] = str_slice(titles[i]);
+    row_contents[i] = str_slice(contents[i]);
+  }
+
+  ZkdocBuffer commitment;
+  CHECK(zkdoc_get_commitment(row_titles, row_contents, ROWS, none, &commitment) == ZKDOC_STATUS_OK);
+  CHECK(commitment.len == 66);
+  CHECK(memcmp(commitment.data, "0x", 2) == 0);
+
+  ZkdocBuffer proof;
+  CHECK(zkdoc_generate_proof(row_titles, row_contents, selectors, ROWS, none, &proof) == ZKDOC_STATUS_OK);
+  CHECK(proof.len > 0);
+
+  ZkdocBuffer row_accumulator;
+  CHECK(zkdoc_get_selected_row(str_slice("name"), str_slice("alice"), &row_accumulator) == ZKDOC_STATUS_OK);
+
+  bool valid = false;
+  CHECK(zkdoc_verify_proof(buffer_slice(commitment), buffer_slice(row_accumulator),
+                           buffer_slice(proof), false, &valid) == ZKDOC_STATUS_OK);
+  CHECK(valid);
+
+  ZkdocBuffer other_row_accumulator;
+  CHECK(zkdoc_get_selected_row(str_slice("name"), str_slice("bob"), &other_row_accumulator) == ZKDOC_STATUS_OK);
+  CHECK(zkdoc_verify_proof(buffer_slice(commitment), buffer_slice(other_row_accumulator),
+                           buffer_slice(proof), false, &valid) == ZKDOC_STATUS_OK);
+  CHECK(!valid);
+
+  // errors are reported as status codes
+  ZkdocBuffer unused;
+  CHECK(zkdoc_get_commitment(row_titles, row_contents, 2, none, &unused) == ZKDOC_STATUS_INVALID_ARGUMENT);
+  CHECK(zkdoc_get_commitment(row_titles, row_contents, ROWS, none, NULL) == ZKDOC_STATUS_NULL_POINTER);
+  CHECK(zkdoc_verify_proof(str_slice("0x1234"), buffer_slice(row_accumulator), buffer_slice(proof), false,
+                           &valid) == ZKDOC_STATUS_INVALID_ARGUMENT);
+  const uint8_t invalid_utf8[] = {0xff, 0xfe};
+  ZkdocSlice invalid = {invalid_utf8, sizeof(invalid_utf8)};
+  CHECK(zkdoc_get_selected_row(invalid, str_slice("alice"), &unused) == ZKDOC_STATUS_INVALID_UTF8);
+
+  // holder bound commitments
+  ZkdocBuffer holder_secret;
+  ZkdocBuffer holder_public_key;
+  ZkdocBuffer holder_bound_commitment;
+  CHECK(zkdoc_generate_holder_key(&holder_secret) == ZKDOC_STATUS_OK);
+  CHECK(zkdoc_get_holder_public_key(buffer_slice(holder_secret), &holder_public_key) == ZKDOC_STATUS_OK);
+  CHECK(zkdoc_get_commitment(row_titles, row_contents, ROWS, buffer_slice(holder_public_key),
+                             &holder_bound_commitment) == ZKDOC_STATUS_OK);
+  CHECK(memcmp(holder_bound_commitment.data, commitment.data, commitment.len) != 0);
+  CHECK(zkdoc_get_holder_public_key(str_slice("not a key"), &unused) == ZKDOC_STATUS_INVALID_ARGUMENT);
+
+  zkdoc_buffer_free(commitment);
+  zkdoc_buffer_free(proof);
+  zkdoc_buffer_free(row_accumulator);
+  zkdoc_buffer_free(other_row_accumulator);
+  zkdoc_buffer_free(holder_secret);
+  zkdoc_buffer_free(holder_public_key);
+  zkdoc_buffer_free(holder_bound_commitment);
+
+  printf("ok\n");
+  return 0;
+}
@@ -0,0 +1,24 @@
+//! Checks that the committed header is the one cbindgen generates from the `ffi` module.
+#![cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_header_up_to_date() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(manifest_dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(manifest_dir)
+        .with_config(config)
+        .generate()
+        .expect("cbindgen should parse the crate")
+        .write(&mut generated);
+
+    let committed = fs::read_to_string(manifest_dir.join("include/zkdoc.h")).unwrap();
+    assert!(
+        String::from_utf8(generated).unwrap() == committed,
+        "include/zkdoc.h is stale, regenerate it with cbindgen --config cbindgen.toml --output include/zkdoc.h"
+    );
+}
@@ -0,0 +1,10 @@
+//! Generates the messages and the gRPC service of `proto/zkdoc.proto`. The file is parsed with
+//! `protox`, so that building does not need `protoc`.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto/zkdoc.proto");
+    let file_descriptors = protox::compile(["zkdoc.proto"], ["proto"])?;
+    tonic_build::configure().compile_fds(file_descriptors)?;
+    Ok(())
+}
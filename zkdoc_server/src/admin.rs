@@ -0,0 +1,57 @@
+//! Admin commands, run against the store instead of starting the server.
+
+use crate::auth::generate_key;
+use crate::config::{Command, Config, KeysCommand};
+use crate::store::{NewApiKey, Store};
+
+pub fn run(command: Command, config: &Config) -> Result<(), String> {
+    let path = config
+        .store_path
+        .as_ref()
+        .ok_or("API keys are kept in the store, set store_path")?;
+    let store = Store::open(path)
+        .map_err(|err| format!("failed to open the store at {}: {err}", path.display()))?;
+    let store_error = |err: rusqlite::Error| format!("store error: {err}");
+
+    match command {
+        Command::Keys(KeysCommand::Create {
+            name,
+            role,
+            issuer_id,
+        }) => {
+            let (key, key_hash) = generate_key();
+            let api_key = store
+                .create_api_key(NewApiKey {
+                    name,
+                    role,
+                    issuer_id,
+                    key_hash,
+                })
+                .map_err(store_error)?;
+            eprintln!(
+                "Created {role} key {}, store it now as it can not be shown again:",
+                api_key.id
+            );
+            println!("{key}");
+        }
+        Command::Keys(KeysCommand::List) => {
+            for api_key in store.api_keys().map_err(store_error)? {
+                println!("{}", serde_json::to_string(&api_key).unwrap());
+            }
+        }
+        Command::Keys(KeysCommand::Revoke { id }) => {
+            let api_key = store
+                .revoke_api_key(&id)
+                .map_err(store_error)?
+                .ok_or_else(|| format!("no key {id}"))?;
+            println!("{}", serde_json::to_string(&api_key).unwrap());
+        }
+        Command::Keys(KeysCommand::Audit { id, limit }) => {
+            for entry in store.audit_log(&id, limit).map_err(store_error)? {
+                println!("{}", serde_json::to_string(&entry).unwrap());
+            }
+        }
+    }
+
+    Ok(())
+}
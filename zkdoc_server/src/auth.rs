@@ -0,0 +1,323 @@
+//! API key authentication. Keys are given a role when created through the admin commands, only
+//! their SHA-256 is stored, and every request made with a key is recorded in the audit log.
+
+use std::fmt;
+use std::future::{ready, Ready};
+use std::str::FromStr;
+
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::store::Store;
+
+pub type ApiKeyId = Uuid;
+
+/// Prefix of the keys, to tell them apart from other secrets
+const KEY_PREFIX: &str = "zkdoc_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Registers and revokes documents
+    Issuer,
+    /// Requests proofs
+    Holder,
+    /// Verifies proofs
+    Verifier,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Issuer => "issuer",
+            Self::Holder => "holder",
+            Self::Verifier => "verifier",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "issuer" => Ok(Self::Issuer),
+            "holder" => Ok(Self::Holder),
+            "verifier" => Ok(Self::Verifier),
+            _ => Err(format!("unknown role {s:?}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ApiKey {
+    pub id: ApiKeyId,
+    /// Who the key was given to
+    pub name: String,
+    pub role: Role,
+    /// Issuer the documents of an issuer key belong to
+    pub issuer_id: Option<String>,
+    /// Unix timestamps in milliseconds
+    pub created_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+/// Generates a new key, returning it along with the hash to store.
+pub fn generate_key() -> (String, String) {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let key = format!("{KEY_PREFIX}{}", hex::encode(bytes));
+    let hash = hash_key(&key);
+    (key, hash)
+}
+
+/// Keys are random, so a plain hash is enough to keep them secret at rest.
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Who made the request, available to handlers once [`authenticate`] ran.
+#[derive(Clone, Debug)]
+pub enum Caller {
+    /// Authentication is disabled
+    Anyone,
+    Key(ApiKey),
+}
+
+impl Caller {
+    /// Fails unless the caller has one of the `roles`.
+    pub fn require(&self, roles: &[Role]) -> Result<(), ApiError> {
+        match self {
+            Self::Anyone => Ok(()),
+            Self::Key(key) if roles.contains(&key.role) => Ok(()),
+            Self::Key(key) => Err(ApiError::new(
+                ErrorCode::Forbidden,
+                format!("{} keys can not use this route", key.role),
+            )),
+        }
+    }
+
+    /// Fails if the caller is an issuer other than `issuer_id`.
+    pub fn require_issuer(&self, issuer_id: &str) -> Result<(), ApiError> {
+        match self {
+            Self::Key(key) if key.issuer_id.as_deref().is_some_and(|id| id != issuer_id) => Err(
+                ApiError::new(ErrorCode::Forbidden, "documents belong to another issuer"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Outcome of [`authenticate`], `None` when the request has no key.
+#[derive(Clone)]
+struct Authentication(Option<Result<Caller, ApiError>>);
+
+impl FromRequest for Caller {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let authentication = req.extensions().get::<Authentication>().cloned();
+        ready(
+            authentication
+                .and_then(|authentication| authentication.0)
+                .unwrap_or_else(|| Err(missing_key())),
+        )
+    }
+}
+
+pub fn missing_key() -> ApiError {
+    ApiError::new(
+        ErrorCode::Unauthorized,
+        "expected an API key in the Authorization or X-API-Key header",
+    )
+}
+
+/// Identifies the caller holding `key`, `None` when authentication is enabled and there is no
+/// key.
+pub fn identify(
+    store: &Store,
+    enabled: bool,
+    key: Option<&str>,
+) -> Option<Result<Caller, ApiError>> {
+    match enabled {
+        false => Some(Ok(Caller::Anyone)),
+        true => key.map(|key| match store.api_key_by_hash(&hash_key(key)) {
+            Ok(Some(key)) if key.revoked_at.is_none() => Ok(Caller::Key(key)),
+            Ok(_) => Err(ApiError::new(
+                ErrorCode::Unauthorized,
+                "unknown or revoked API key",
+            )),
+            Err(err) => Err(err.into()),
+        }),
+    }
+}
+
+/// Id of the valid key a request was made with, once [`authenticate`] ran.
+pub fn request_key_id(req: &ServiceRequest) -> Option<ApiKeyId> {
+    match req.extensions().get::<Authentication>()?.0 {
+        Some(Ok(Caller::Key(ref key))) => Some(key.id),
+        _ => None,
+    }
+}
+
+/// The key of a request, either `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+fn request_key(req: &ServiceRequest) -> Option<&str> {
+    let headers = req.headers();
+    if let Some(value) = headers.get(header::AUTHORIZATION) {
+        return value.to_str().ok()?.strip_prefix("Bearer ");
+    }
+    headers.get("x-api-key")?.to_str().ok()
+}
+
+/// Middleware identifying the [`Caller`] of every request, which handlers then extract to
+/// check its role. Unknown or revoked keys are only rejected by the handlers, so that public
+/// routes ignore them. Requests made with a valid key are audited.
+pub fn authenticate<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    let store = req
+        .app_data::<web::Data<Store>>()
+        .expect("the store should be registered")
+        .clone();
+    let enabled = req
+        .app_data::<web::Data<AuthEnabled>>()
+        .is_some_and(|enabled| enabled.0);
+
+    let authentication = identify(&store, enabled, request_key(&req));
+    let audited = match &authentication {
+        Some(Ok(Caller::Key(key))) => Some(key.id),
+        _ => None,
+    };
+    let method = req.method().to_string();
+    let path = req.path().to_owned();
+    req.extensions_mut().insert(Authentication(authentication));
+    let response = service.call(req);
+
+    Box::pin(async move {
+        let response = response.await;
+        if let Some(key_id) = audited {
+            let status = match &response {
+                Ok(response) => response.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            if let Err(err) = store.audit(&key_id, &method, &path, status.as_u16()) {
+                tracing::error!("failed to audit {method} {path} of key {key_id}: {err}");
+            }
+        }
+        response
+    })
+}
+
+/// Whether requests need an API key, registered as app data.
+pub struct AuthEnabled(pub bool);
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{web, App, HttpResponse};
+
+    use super::{authenticate, generate_key, hash_key, AuthEnabled, Caller, Role};
+    use crate::error::ApiError;
+    use crate::store::{NewApiKey, Store};
+
+    async fn verify(caller: Caller) -> Result<HttpResponse, ApiError> {
+        caller.require(&[Role::Verifier])?;
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let (key, hash) = generate_key();
+        assert!(key.starts_with("zkdoc_"));
+        assert_eq!(key.len(), 6 + 64);
+        assert_eq!(hash, hash_key(&key));
+        assert_ne!(generate_key().0, key);
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate() {
+        let store = web::Data::new(Store::open_in_memory().unwrap());
+        let mut keys = Vec::new();
+        for role in [Role::Verifier, Role::Holder] {
+            let (key, key_hash) = generate_key();
+            let api_key = store
+                .create_api_key(NewApiKey {
+                    name: format!("{role} key"),
+                    role,
+                    issuer_id: None,
+                    key_hash,
+                })
+                .unwrap();
+            keys.push((key, api_key));
+        }
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(web::Data::new(AuthEnabled(true)))
+                .wrap_fn(authenticate)
+                .route("/verify", web::post().to(verify)),
+        )
+        .await;
+
+        let call = |header: Option<(&'static str, String)>| {
+            let mut req = actix_web::test::TestRequest::post().uri("/verify");
+            if let Some(header) = header {
+                req = req.insert_header(header);
+            }
+            actix_web::test::try_call_service(&app, req.to_request())
+        };
+        let status =
+            |response: Result<actix_web::dev::ServiceResponse, actix_web::Error>| match response {
+                Ok(response) => response.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+
+        let (verifier_key, verifier) = &keys[0];
+        let (holder_key, _) = &keys[1];
+        let bearer = format!("Bearer {verifier_key}");
+        assert_eq!(status(call(None).await), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(call(Some(("x-api-key", "zkdoc_nope".to_owned()))).await),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(call(Some(("authorization", bearer.clone()))).await),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(call(Some(("x-api-key", holder_key.clone()))).await),
+            StatusCode::FORBIDDEN
+        );
+
+        let audit_log = store.audit_log(&verifier.id, 10).unwrap();
+        assert_eq!(audit_log.len(), 1);
+        assert_eq!(audit_log[0].path, "/verify");
+        assert_eq!(audit_log[0].status, 200);
+
+        store.revoke_api_key(&verifier.id).unwrap();
+        assert_eq!(
+            status(call(Some(("authorization", bearer))).await),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
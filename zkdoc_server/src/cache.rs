@@ -0,0 +1,223 @@
+//! Cache of generated proofs, so that holders asking again for the proof of the same disclosure
+//! do not wait for proving again. Proofs embed fresh randomness, and handing the same proof out
+//! twice links the presentations made with it: only the proofs of requests opting in with
+//! `cache` are stored, and only those requests are answered from the cache.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::telemetry::{PROOF_CACHE, PROOF_CACHE_BYTES};
+use crate::ROW;
+
+pub type CacheKey = [u8; 32];
+
+/// Hash of what a proof depends on besides its randomness: the commitment of the document, which
+/// binds the holder key of holder bound documents, the disclosed rows and the circuit. The
+/// proving parameters are those of the process, so they are left out.
+pub fn cache_key(commitment: &str, row_selectors: &[u64; ROW], holder_bound: bool) -> CacheKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zkdoc proof cache v1\0");
+    hasher.update([holder_bound as u8]);
+    for selector in row_selectors {
+        hasher.update(selector.to_le_bytes());
+    }
+    hasher.update(commitment.as_bytes());
+    hasher.finalize().into()
+}
+
+struct Entry {
+    proof: Vec<u8>,
+    stored_at: Instant,
+    /// Tick of the last use, the least recently used proofs being evicted first
+    used: u64,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<CacheKey, Entry>,
+    /// Total size of the proofs
+    bytes: usize,
+    ticks: u64,
+}
+
+impl State {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.proof.len();
+        }
+    }
+
+    fn record_size(&self) {
+        metrics::gauge!(PROOF_CACHE_BYTES).set(self.bytes as f64);
+    }
+}
+
+pub struct ProofCache {
+    config: config::ProofCache,
+    state: Mutex<State>,
+}
+
+impl ProofCache {
+    pub fn new(config: config::ProofCache) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl_secs)
+    }
+
+    /// The proof stored under `key`, unless it expired.
+    pub fn get(&self, key: &CacheKey, now: Instant) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let expired = state
+            .entries
+            .get(key)
+            .is_some_and(|entry| now.duration_since(entry.stored_at) >= self.ttl());
+        if expired {
+            state.remove(key);
+            state.record_size();
+        }
+
+        state.ticks += 1;
+        let tick = state.ticks;
+        let proof = state.entries.get_mut(key).map(|entry| {
+            entry.used = tick;
+            entry.proof.clone()
+        });
+        let result = if proof.is_some() { "hit" } else { "miss" };
+        metrics::counter!(PROOF_CACHE, "result" => result).increment(1);
+        proof
+    }
+
+    /// Stores a proof, evicting the expired proofs then the least recently used ones beyond the
+    /// limits. Proofs larger than the whole cache are not stored.
+    pub fn insert(&self, key: CacheKey, proof: Vec<u8>, now: Instant) {
+        if proof.len() > self.config.max_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(&key);
+        let ttl = self.ttl();
+        let expired = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.stored_at) >= ttl)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in &expired {
+            state.remove(key);
+        }
+        while state.entries.len() >= self.config.max_entries
+            || state.bytes + proof.len() > self.config.max_bytes
+        {
+            let Some(lru) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            state.remove(&lru);
+        }
+
+        state.ticks += 1;
+        state.bytes += proof.len();
+        let used = state.ticks;
+        state.entries.insert(
+            key,
+            Entry {
+                proof,
+                stored_at: now,
+                used,
+            },
+        );
+        state.record_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{cache_key, ProofCache};
+    use crate::config;
+
+    fn cache(max_entries: usize, max_bytes: usize) -> ProofCache {
+        ProofCache::new(config::ProofCache {
+            enabled: true,
+            ttl_secs: 60,
+            max_entries,
+            max_bytes,
+        })
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let selectors = [0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let key = cache_key("0x01", &selectors, false);
+        assert_eq!(key, cache_key("0x01", &selectors, false));
+        assert_ne!(key, cache_key("0x02", &selectors, false));
+        assert_ne!(key, cache_key("0x01", &selectors, true));
+        assert_ne!(
+            key,
+            cache_key("0x01", &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0], false)
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let cache = cache(4, 1024);
+        let now = Instant::now();
+        let key = [1; 32];
+
+        assert_eq!(cache.get(&key, now), None);
+        cache.insert(key, vec![1, 2, 3], now);
+        assert_eq!(cache.get(&key, now), Some(vec![1, 2, 3]));
+        assert_eq!(
+            cache.get(&key, now + Duration::from_secs(59)),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(cache.get(&key, now + Duration::from_secs(60)), None);
+        assert_eq!(cache.state.lock().unwrap().bytes, 0);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = cache(2, 10);
+        let now = Instant::now();
+
+        cache.insert([1; 32], vec![1; 4], now);
+        cache.insert([2; 32], vec![2; 4], now);
+        // 1 is now more recently used than 2
+        assert!(cache.get(&[1; 32], now).is_some());
+        cache.insert([3; 32], vec![3; 4], now);
+        assert!(cache.get(&[2; 32], now).is_none());
+        assert!(cache.get(&[1; 32], now).is_some());
+        assert!(cache.get(&[3; 32], now).is_some());
+
+        // too large for the 10 bytes along with the others
+        cache.insert([4; 32], vec![4; 8], now);
+        assert!(cache.get(&[1; 32], now).is_none());
+        assert!(cache.get(&[3; 32], now).is_none());
+        assert_eq!(cache.get(&[4; 32], now), Some(vec![4; 8]));
+
+        // larger than the whole cache
+        cache.insert([5; 32], vec![5; 11], now);
+        assert!(cache.get(&[5; 32], now).is_none());
+        assert!(cache.get(&[4; 32], now).is_some());
+        assert_eq!(cache.state.lock().unwrap().bytes, 8);
+    }
+}
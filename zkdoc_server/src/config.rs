@@ -0,0 +1,797 @@
+//! Server configuration, read from a TOML file then overridden by environment variables and
+//! command line flags.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use crate::auth::{ApiKeyId, Role};
+
+/// Command line flags, each of which can also be set through its environment variable.
+#[derive(Debug, Default, Parser)]
+#[command(about = "REST API for generating and verifying zkdoc proofs")]
+pub struct Args {
+    /// Runs an admin command instead of the server
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML configuration file
+    #[arg(short, long, env = "ZKDOC_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long, env = "ZKDOC_BIND")]
+    pub bind: Option<SocketAddr>,
+    /// Routes to serve
+    #[arg(long, env = "ZKDOC_MODE", value_enum)]
+    pub mode: Option<Mode>,
+    /// Address to serve the gRPC API on
+    #[arg(long, env = "ZKDOC_GRPC_BIND")]
+    pub grpc_bind: Option<SocketAddr>,
+    /// HTTP worker threads
+    #[arg(long, env = "ZKDOC_WORKERS")]
+    pub workers: Option<usize>,
+    /// Comma separated origins allowed to call the API from a browser, `*` allows any
+    #[arg(long, env = "ZKDOC_CORS_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// Maximum JSON body size in bytes
+    #[arg(long, env = "ZKDOC_JSON_BODY_LIMIT")]
+    pub json_body_limit: Option<usize>,
+    /// Maximum JSON body size of batch verifications in bytes
+    #[arg(long, env = "ZKDOC_BATCH_JSON_BODY_LIMIT")]
+    pub batch_json_body_limit: Option<usize>,
+    /// Maximum number of proofs in a batch verification
+    #[arg(long, env = "ZKDOC_BATCH_ITEMS_LIMIT")]
+    pub batch_items_limit: Option<usize>,
+    /// Maximum size of a proof to verify in bytes
+    #[arg(long, env = "ZKDOC_PROOF_SIZE_LIMIT")]
+    pub proof_size_limit: Option<usize>,
+    /// Proofs generated at once
+    #[arg(long, env = "ZKDOC_PROVING_WORKERS")]
+    pub proving_workers: Option<usize>,
+    /// Proof jobs waiting for a proving worker
+    #[arg(long, env = "ZKDOC_MAX_QUEUED_JOBS")]
+    pub max_queued_jobs: Option<usize>,
+    /// Seconds after which a proof job fails
+    #[arg(long, env = "ZKDOC_PROOF_TIMEOUT_SECS")]
+    pub proof_timeout_secs: Option<u64>,
+    /// Seconds the running proof jobs are given to finish on shutdown
+    #[arg(long, env = "ZKDOC_DRAIN_TIMEOUT_SECS")]
+    pub drain_timeout_secs: Option<u64>,
+    /// Proving parameters file, generated on first start if missing
+    #[arg(long, env = "ZKDOC_PARAMS_PATH")]
+    pub params_path: Option<PathBuf>,
+    /// SQLite database of the registered documents, created if missing
+    #[arg(long, env = "ZKDOC_STORE_PATH")]
+    pub store_path: Option<PathBuf>,
+    #[arg(long, env = "ZKDOC_LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Log filter in the `tracing_subscriber::EnvFilter` syntax, e.g. `actix_web=info`
+    #[arg(long, env = "RUST_LOG")]
+    pub log_filter: Option<String>,
+    /// Whether requests need an API key
+    #[arg(long, env = "ZKDOC_AUTH_ENABLED")]
+    pub auth_enabled: Option<bool>,
+    /// Whether requests are rate limited
+    #[arg(long, env = "ZKDOC_RATE_LIMIT_ENABLED")]
+    pub rate_limit_enabled: Option<bool>,
+    /// Tokens a client can spend at once
+    #[arg(long, env = "ZKDOC_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+    /// Tokens given back to a client every minute
+    #[arg(long, env = "ZKDOC_RATE_LIMIT_PER_MINUTE")]
+    pub rate_limit_per_minute: Option<u32>,
+    /// Whether proofs are cached for the requests opting in
+    #[arg(long, env = "ZKDOC_PROOF_CACHE_ENABLED")]
+    pub proof_cache_enabled: Option<bool>,
+    /// Seconds a cached proof is reused for
+    #[arg(long, env = "ZKDOC_PROOF_CACHE_TTL_SECS")]
+    pub proof_cache_ttl_secs: Option<u64>,
+    /// Proofs kept in the cache
+    #[arg(long, env = "ZKDOC_PROOF_CACHE_MAX_ENTRIES")]
+    pub proof_cache_max_entries: Option<usize>,
+    /// Total size of the cached proofs in bytes
+    #[arg(long, env = "ZKDOC_PROOF_CACHE_MAX_BYTES")]
+    pub proof_cache_max_bytes: Option<usize>,
+    /// Seconds a presentation can be fetched for, unless the holder asks otherwise
+    #[arg(long, env = "ZKDOC_PRESENTATION_TTL_SECS")]
+    pub presentation_ttl_secs: Option<u64>,
+    /// Longest lifetime a holder can ask for a presentation, in seconds
+    #[arg(long, env = "ZKDOC_PRESENTATION_MAX_TTL_SECS")]
+    pub presentation_max_ttl_secs: Option<u64>,
+    /// Key signing the webhook payloads, proof jobs only take a callback URL when set
+    #[arg(long, env = "ZKDOC_WEBHOOK_SECRET", hide_env_values = true)]
+    pub webhook_secret: Option<String>,
+    /// Deliveries of a webhook before giving up
+    #[arg(long, env = "ZKDOC_WEBHOOK_MAX_ATTEMPTS")]
+    pub webhook_max_attempts: Option<u32>,
+    /// Comma separated hosts webhooks may reach even on internal addresses
+    #[arg(long, env = "ZKDOC_WEBHOOK_ALLOWED_HOSTS", value_delimiter = ',')]
+    pub webhook_allowed_hosts: Option<Vec<String>>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Manages the API keys kept in the store
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Creates a key and prints it, it can not be shown again
+    Create {
+        /// Who the key is given to
+        #[arg(long)]
+        name: String,
+        #[arg(long, value_enum)]
+        role: Role,
+        /// Issuer whose documents an issuer key manages
+        #[arg(long, required_if_eq("role", "issuer"))]
+        issuer_id: Option<String>,
+    },
+    /// Lists the keys, one JSON object per line
+    List,
+    /// Revokes a key
+    Revoke { id: ApiKeyId },
+    /// Shows the requests made with a key, most recent first
+    Audit {
+        id: ApiKeyId,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub mode: Mode,
+    /// Address of the gRPC API, which is only served when set
+    pub grpc_bind: Option<SocketAddr>,
+    /// HTTP worker threads, the number of cores when unset
+    pub workers: Option<usize>,
+    pub cors: Cors,
+    pub limits: Limits,
+    pub proving: Proving,
+    /// Proving parameters file, generated on first start if missing. Parameters are generated
+    /// on every start when unset.
+    pub params_path: Option<PathBuf>,
+    /// SQLite database of the registered documents, created if missing. Documents are only
+    /// kept in memory when unset.
+    pub store_path: Option<PathBuf>,
+    pub log: Log,
+    pub auth: Auth,
+    pub rate_limit: RateLimit,
+    pub proof_cache: ProofCache,
+    pub presentations: Presentations,
+    pub webhooks: Webhooks,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cors {
+    /// Origins allowed to call the API from a browser, `*` allows any
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Maximum JSON body size in bytes
+    pub json_body: usize,
+    /// A single proof is ~15kb of JSON, so batches need a larger limit
+    pub batch_json_body: usize,
+    /// Maximum number of proofs in a batch, which is verified as a whole before answering
+    pub batch_items: usize,
+    /// Maximum size of a proof to verify in bytes, proofs are ~4kb
+    pub proof_bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Proving {
+    /// Proving is already parallelized, so only a few proofs should run at once
+    pub workers: usize,
+    /// Jobs waiting for a proving worker, further requests get a 429
+    pub max_queued_jobs: usize,
+    /// Proofs taking longer than this are abandoned, the job fails
+    pub timeout_secs: u64,
+    /// Running proofs still unfinished this long after SIGTERM are abandoned
+    pub drain_timeout_secs: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    pub format: LogFormat,
+    /// Log filter in the `tracing_subscriber::EnvFilter` syntax
+    pub filter: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// Whether requests need an API key, see the `keys` admin commands
+    pub enabled: bool,
+}
+
+/// Token buckets of each API key, or of each client IP for requests without one.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub enabled: bool,
+    /// Tokens a client can spend at once
+    pub burst: u32,
+    /// Tokens given back to a client every minute
+    pub per_minute: u32,
+    /// Tokens taken by a request to each route, without the `/v1` prefix. Other routes take one
+    /// token, and routes taking none are not limited.
+    pub routes: BTreeMap<String, u32>,
+}
+
+/// Proofs kept for the requests opting in with `cache`, see [`crate::cache`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProofCache {
+    pub enabled: bool,
+    /// Proofs are generated again once cached for this long
+    pub ttl_secs: u64,
+    /// The least recently used proofs are evicted beyond either limit
+    pub max_entries: usize,
+    pub max_bytes: usize,
+}
+
+/// Lifetime of the presentations holders post for verifiers to fetch.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Presentations {
+    /// Used when the holder does not give one
+    pub ttl_secs: u64,
+    pub max_ttl_secs: u64,
+}
+
+/// Callbacks of the proof jobs, see [`crate::webhooks`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Webhooks {
+    /// Key of the HMAC-SHA256 signature of the payloads, webhooks are disabled when unset
+    pub secret: Option<String>,
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following one
+    pub backoff_secs: u64,
+    pub timeout_secs: u64,
+    /// Hosts of callback URLs allowed to resolve to loopback, private or link-local addresses,
+    /// which are rejected otherwise
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Routes served by a deployment, e.g. so that verifiers never carry proving keys nor accept
+/// documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Every route
+    All,
+    /// Proof and batch verification, and document lookups
+    Verifier,
+    /// Proof generation and jobs
+    Prover,
+    /// Commitments and documents
+    Issuer,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Verifier => "verifier",
+            Self::Prover => "prover",
+            Self::Issuer => "issuer",
+        }
+    }
+
+    /// Whether the routes used by `role` keys are served.
+    pub fn serves(self, role: Role) -> bool {
+        matches!(
+            (self, role),
+            (Self::All, _)
+                | (Self::Verifier, Role::Verifier)
+                | (Self::Prover, Role::Holder)
+                | (Self::Issuer, Role::Issuer)
+        )
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            mode: Mode::All,
+            grpc_bind: None,
+            workers: None,
+            cors: Cors::default(),
+            limits: Limits::default(),
+            proving: Proving::default(),
+            params_path: None,
+            store_path: None,
+            log: Log::default(),
+            auth: Auth::default(),
+            rate_limit: RateLimit::default(),
+            proof_cache: ProofCache::default(),
+            presentations: Presentations::default(),
+            webhooks: Webhooks::default(),
+        }
+    }
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            max_attempts: 5,
+            backoff_secs: 2,
+            timeout_secs: 10,
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+impl Default for Presentations {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 10 * 60,
+            max_ttl_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for ProofCache {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 60 * 60,
+            max_entries: 1024,
+            max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_owned()],
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            json_body: 2 * 1024 * 1024,
+            batch_json_body: 32 * 1024 * 1024,
+            batch_items: 256,
+            proof_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        let routes = [
+            ("/", 0),
+            ("/ready", 0),
+            ("/generate-proof", 30),
+            ("/generate-commitment", 2),
+            ("/verify-batch", 10),
+        ];
+        Self {
+            enabled: true,
+            burst: 60,
+            per_minute: 60,
+            routes: routes
+                .into_iter()
+                .map(|(route, cost)| (route.to_owned(), cost))
+                .collect(),
+        }
+    }
+}
+
+impl Default for Proving {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            max_queued_jobs: 32,
+            timeout_secs: 120,
+            drain_timeout_secs: 60,
+        }
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filter: "zkdoc_server=info,zkdoc_sdk=info,actix_web=info".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads the configuration file if any, applies the flags and validates the result.
+    pub fn load(args: Args) -> Result<Self, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => {
+                let contents = fs::read_to_string(path).map_err(|err| {
+                    ConfigError(format!("failed to read {}: {err}", path.display()))
+                })?;
+                toml::from_str(&contents)
+                    .map_err(|err| ConfigError(format!("invalid {}: {err}", path.display())))?
+            }
+            None => Config::default(),
+        };
+
+        let Args {
+            command: _,
+            config: _,
+            bind,
+            mode,
+            grpc_bind,
+            workers,
+            cors_allowed_origins,
+            json_body_limit,
+            batch_json_body_limit,
+            batch_items_limit,
+            proof_size_limit,
+            proving_workers,
+            max_queued_jobs,
+            proof_timeout_secs,
+            drain_timeout_secs,
+            params_path,
+            store_path,
+            log_format,
+            log_filter,
+            auth_enabled,
+            rate_limit_enabled,
+            rate_limit_burst,
+            rate_limit_per_minute,
+            proof_cache_enabled,
+            proof_cache_ttl_secs,
+            proof_cache_max_entries,
+            proof_cache_max_bytes,
+            presentation_ttl_secs,
+            presentation_max_ttl_secs,
+            webhook_secret,
+            webhook_max_attempts,
+            webhook_allowed_hosts,
+        } = args;
+        override_with(&mut config.bind, bind);
+        override_with(&mut config.mode, mode);
+        config.grpc_bind = grpc_bind.or(config.grpc_bind);
+        config.workers = workers.or(config.workers);
+        override_with(&mut config.cors.allowed_origins, cors_allowed_origins);
+        override_with(&mut config.limits.json_body, json_body_limit);
+        override_with(&mut config.limits.batch_json_body, batch_json_body_limit);
+        override_with(&mut config.limits.batch_items, batch_items_limit);
+        override_with(&mut config.limits.proof_bytes, proof_size_limit);
+        override_with(&mut config.proving.workers, proving_workers);
+        override_with(&mut config.proving.max_queued_jobs, max_queued_jobs);
+        override_with(&mut config.proving.timeout_secs, proof_timeout_secs);
+        override_with(&mut config.proving.drain_timeout_secs, drain_timeout_secs);
+        config.params_path = params_path.or(config.params_path);
+        config.store_path = store_path.or(config.store_path);
+        override_with(&mut config.log.format, log_format);
+        override_with(&mut config.log.filter, log_filter);
+        override_with(&mut config.auth.enabled, auth_enabled);
+        override_with(&mut config.rate_limit.enabled, rate_limit_enabled);
+        override_with(&mut config.rate_limit.burst, rate_limit_burst);
+        override_with(&mut config.rate_limit.per_minute, rate_limit_per_minute);
+        override_with(&mut config.proof_cache.enabled, proof_cache_enabled);
+        override_with(&mut config.proof_cache.ttl_secs, proof_cache_ttl_secs);
+        override_with(&mut config.proof_cache.max_entries, proof_cache_max_entries);
+        override_with(&mut config.proof_cache.max_bytes, proof_cache_max_bytes);
+        override_with(&mut config.presentations.ttl_secs, presentation_ttl_secs);
+        override_with(
+            &mut config.presentations.max_ttl_secs,
+            presentation_max_ttl_secs,
+        );
+        config.webhooks.secret = webhook_secret.or(config.webhooks.secret);
+        override_with(&mut config.webhooks.max_attempts, webhook_max_attempts);
+        override_with(&mut config.webhooks.allowed_hosts, webhook_allowed_hosts);
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn proof_timeout(&self) -> Duration {
+        Duration::from_secs(self.proving.timeout_secs)
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.proving.drain_timeout_secs)
+    }
+
+    /// Whether any origin may call the API.
+    pub fn allows_any_origin(&self) -> bool {
+        self.cors.allowed_origins == ["*"]
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let positive = [
+            ("workers", self.workers.unwrap_or(1)),
+            ("limits.json_body", self.limits.json_body),
+            ("limits.batch_json_body", self.limits.batch_json_body),
+            ("limits.batch_items", self.limits.batch_items),
+            ("limits.proof_bytes", self.limits.proof_bytes),
+            ("rate_limit.burst", self.rate_limit.burst as usize),
+            ("rate_limit.per_minute", self.rate_limit.per_minute as usize),
+            ("proving.workers", self.proving.workers),
+            ("proving.max_queued_jobs", self.proving.max_queued_jobs),
+            ("proving.timeout_secs", self.proving.timeout_secs as usize),
+            ("proof_cache.ttl_secs", self.proof_cache.ttl_secs as usize),
+            ("proof_cache.max_entries", self.proof_cache.max_entries),
+            ("proof_cache.max_bytes", self.proof_cache.max_bytes),
+            (
+                "presentations.ttl_secs",
+                self.presentations.ttl_secs as usize,
+            ),
+            ("webhooks.max_attempts", self.webhooks.max_attempts as usize),
+            ("webhooks.backoff_secs", self.webhooks.backoff_secs as usize),
+            ("webhooks.timeout_secs", self.webhooks.timeout_secs as usize),
+        ];
+        for (name, value) in positive {
+            if value == 0 {
+                return Err(ConfigError(format!("{name} should be greater than 0")));
+            }
+        }
+
+        for (route, &cost) in &self.rate_limit.routes {
+            if !route.starts_with('/') {
+                return Err(ConfigError(format!(
+                    "rate_limit.routes should be paths such as /generate-proof, got {route:?}"
+                )));
+            }
+            if cost > self.rate_limit.burst {
+                return Err(ConfigError(format!(
+                    "rate_limit.routes.{route:?} costs more than the rate_limit.burst of {}",
+                    self.rate_limit.burst
+                )));
+            }
+        }
+
+        if self.presentations.ttl_secs > self.presentations.max_ttl_secs {
+            return Err(ConfigError(
+                "presentations.ttl_secs should not exceed presentations.max_ttl_secs".to_owned(),
+            ));
+        }
+
+        if self.webhooks.secret.as_deref() == Some("") {
+            return Err(ConfigError(
+                "webhooks.secret should not be empty, leave it unset to disable webhooks"
+                    .to_owned(),
+            ));
+        }
+
+        if self.grpc_bind == Some(self.bind) {
+            return Err(ConfigError(
+                "grpc_bind should be another address than bind".to_owned(),
+            ));
+        }
+
+        if self.auth.enabled && self.store_path.is_none() {
+            return Err(ConfigError(
+                "API keys are kept in the store, set store_path or disable auth.enabled".to_owned(),
+            ));
+        }
+
+        if !self.allows_any_origin() {
+            for origin in &self.cors.allowed_origins {
+                let valid = origin
+                    .strip_prefix("https://")
+                    .or_else(|| origin.strip_prefix("http://"))
+                    .is_some_and(|host| !host.is_empty() && !host.contains(['/', ' ', '*']));
+                if !valid {
+                    return Err(ConfigError(format!(
+                        "invalid CORS origin {origin:?}, expected e.g. https://example.com or a single *"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn override_with<T>(value: &mut T, flag: Option<T>) {
+    if let Some(flag) = flag {
+        *value = flag;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use clap::Parser;
+
+    use super::{Args, Auth, Config, LogFormat, Mode};
+    use crate::auth::Role;
+
+    fn load(config: &str, flags: &[&str]) -> Result<Config, String> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "zkdoc-server-{}-{}.toml",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, config).unwrap();
+        let path = path.to_str().unwrap();
+
+        let args = Args::try_parse_from(
+            ["zkdoc_server", "--config", path]
+                .iter()
+                .chain(flags)
+                .copied(),
+        )
+        .map_err(|err| err.to_string())?;
+        let config = Config::load(args).map_err(|err| err.to_string());
+        std::fs::remove_file(path).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_load() {
+        let open = Config {
+            auth: Auth { enabled: false },
+            ..Config::default()
+        };
+        assert_eq!(load("", &["--auth-enabled", "false"]).unwrap(), open);
+
+        let config = load(
+            r#"
+            bind = "127.0.0.1:3000"
+            mode = "prover"
+            params_path = "params.bin"
+            store_path = "zkdoc.db"
+
+            [cors]
+            allowed_origins = ["https://example.com"]
+
+            [proving]
+            workers = 4
+            timeout_secs = 60
+
+            [log]
+            format = "json"
+
+            [rate_limit.routes]
+            "/generate-proof" = 20
+
+            [proof_cache]
+            enabled = true
+            ttl_secs = 600
+
+            [presentations]
+            ttl_secs = 60
+
+            [webhooks]
+            max_attempts = 3
+            allowed_hosts = ["hooks.internal"]
+            "#,
+            &[
+                "--proving-workers",
+                "1",
+                "--bind",
+                "127.0.0.1:3001",
+                "--mode",
+                "verifier",
+                "--rate-limit-burst",
+                "40",
+                "--proof-cache-max-entries",
+                "10",
+                "--webhook-secret",
+                "shh",
+                "--batch-items-limit",
+                "100",
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.bind.to_string(), "127.0.0.1:3001");
+        assert_eq!(config.mode, Mode::Verifier);
+        assert!(config.mode.serves(Role::Verifier));
+        assert!(!config.mode.serves(Role::Holder));
+        assert!(Mode::All.serves(Role::Issuer));
+        assert_eq!(config.cors.allowed_origins, ["https://example.com"]);
+        assert!(!config.allows_any_origin());
+        assert_eq!(config.limits.batch_items, 100);
+        assert_eq!(config.proving.workers, 1);
+        assert_eq!(config.proving.max_queued_jobs, 32);
+        assert_eq!(config.proof_timeout().as_secs(), 60);
+        assert_eq!(config.drain_timeout().as_secs(), 60);
+        assert_eq!(config.params_path.unwrap().to_str(), Some("params.bin"));
+        assert_eq!(config.store_path.unwrap().to_str(), Some("zkdoc.db"));
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.rate_limit.burst, 40);
+        assert_eq!(config.rate_limit.per_minute, 60);
+        // the costs replace the default ones
+        assert_eq!(config.rate_limit.routes.len(), 1);
+        assert_eq!(config.rate_limit.routes["/generate-proof"], 20);
+        assert!(config.proof_cache.enabled);
+        assert_eq!(config.proof_cache.ttl_secs, 600);
+        assert_eq!(config.proof_cache.max_entries, 10);
+        assert_eq!(config.proof_cache.max_bytes, 16 * 1024 * 1024);
+        assert_eq!(config.presentations.ttl_secs, 60);
+        assert_eq!(config.presentations.max_ttl_secs, 24 * 60 * 60);
+        assert_eq!(config.webhooks.secret.as_deref(), Some("shh"));
+        assert_eq!(config.webhooks.max_attempts, 3);
+        assert_eq!(config.webhooks.backoff_secs, 2);
+        assert_eq!(config.webhooks.allowed_hosts, ["hooks.internal"]);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(load("bind = \"localhost\"", &[]).is_err());
+        assert!(load("unknown = 1", &[]).is_err());
+        assert!(load("[proving]\nworkers = 0", &[]).is_err());
+        assert!(load("", &["--max-queued-jobs", "0"]).is_err());
+        assert!(load("[limits]\nbatch_items = 0", &[]).is_err());
+        assert!(load("", &["--log-format", "xml"]).is_err());
+        assert!(load("mode = \"holder\"", &[]).is_err());
+        assert!(load("", &["--rate-limit-per-minute", "0"]).is_err());
+        assert!(load("", &["--proof-cache-ttl-secs", "0"]).is_err());
+        assert!(load("", &["--presentation-max-ttl-secs", "60"]).is_err());
+        assert!(load("", &["--webhook-secret", ""]).is_err());
+        assert!(load("[rate_limit.routes]\n\"generate-proof\" = 1", &[]).is_err());
+        assert!(load("[rate_limit.routes]\n\"/generate-proof\" = 100", &[]).is_err());
+        assert!(load("", &["--cors-allowed-origins", "example.com"]).is_err());
+        assert!(load("", &["--cors-allowed-origins", "*,https://example.com"]).is_err());
+        assert!(load("", &[]).is_err());
+        assert!(load("", &["--store-path", "zkdoc.db"]).is_ok());
+        assert!(load("[auth]\nenabled = false", &[]).is_ok());
+        assert!(load(
+            "",
+            &[
+                "--auth-enabled",
+                "false",
+                "--cors-allowed-origins",
+                "https://a.com,http://b.com:8080"
+            ]
+        )
+        .is_ok());
+    }
+}
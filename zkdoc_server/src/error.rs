@@ -0,0 +1,323 @@
+//! Error responses. Every error, whether raised by a handler, by actix while extracting the
+//! request or by a panic, is answered with a JSON body `{code, message, details}` whose `code`
+//! is stable and safe to match on.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::error::{BlockingError, JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{Error, HttpRequest, HttpResponse, ResponseError};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The body is not JSON of the expected shape, or a path parameter is malformed
+    InvalidRequest,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    /// A commitment is not a hex encoded field element
+    InvalidCommitment,
+    /// A row selector is neither 0 nor 1
+    InvalidSelector,
+    /// A holder public key or secret is not a hex encoded field element
+    InvalidHolderKey,
+    /// A proof is truncated or holds invalid curve points
+    ProofMalformed,
+    /// The API key is missing, unknown or revoked
+    Unauthorized,
+    /// The API key's role does not allow the route
+    Forbidden,
+    NotFound,
+    JobNotFound,
+    DocumentNotFound,
+    /// No such presentation, or it expired or was single use and already fetched
+    PresentationNotFound,
+    QueueFull,
+    /// The client made too many requests, see the `Retry-After` header
+    RateLimited,
+    /// Proof generation failed, see the job error
+    ProvingFailed,
+    /// Proof generation ran past the configured timeout
+    ProvingTimeout,
+    /// The server is not ready, e.g. while loading keys
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::InvalidRequest
+            | Self::InvalidCommitment
+            | Self::InvalidSelector
+            | Self::InvalidHolderKey
+            | Self::ProofMalformed => StatusCode::BAD_REQUEST,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::NotFound
+            | Self::JobNotFound
+            | Self::DocumentNotFound
+            | Self::PresentationNotFound => StatusCode::NOT_FOUND,
+            Self::QueueFull | Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::ProvingFailed | Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ProvingTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Context about the error, e.g. the index of the offending item
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+    /// Seconds to send in a `Retry-After` header
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            retry_after: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let Some(secs) = self.retry_after {
+            response.insert_header((header::RETRY_AFTER, secs));
+        }
+        response.json(self)
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(err: BlockingError) -> Self {
+        tracing::error!("blocking task failed: {err}");
+        ApiError::new(ErrorCode::Internal, "internal server error")
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(err: rusqlite::Error) -> Self {
+        tracing::error!("store error: {err}");
+        ApiError::new(ErrorCode::Internal, "internal server error")
+    }
+}
+
+/// Error handler of [`actix_web::web::JsonConfig`].
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> Error {
+    let error = match &err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ApiError::new(ErrorCode::PayloadTooLarge, err.to_string())
+        }
+        JsonPayloadError::ContentType => ApiError::new(
+            ErrorCode::UnsupportedMediaType,
+            "expected an application/json body",
+        ),
+        _ => ApiError::new(ErrorCode::InvalidRequest, err.to_string()),
+    };
+    error.into()
+}
+
+/// Error handler of [`actix_web::web::QueryConfig`].
+pub fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> Error {
+    ApiError::new(ErrorCode::InvalidRequest, err.to_string()).into()
+}
+
+/// Error handler of [`actix_web::web::PathConfig`].
+pub fn path_error(err: PathError, _req: &HttpRequest) -> Error {
+    ApiError::new(ErrorCode::InvalidRequest, err.to_string()).into()
+}
+
+/// Default service, answering requests to unknown routes.
+pub async fn not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::new(ErrorCode::NotFound, "no such route"))
+}
+
+/// Middleware answering `500 internal` when a handler panics, rather than dropping the
+/// connection. The error skips the middlewares it is wrapped in, so it is logged here.
+pub fn catch_panic<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    // the request can not be cloned before it is routed
+    let route = format!("{} {}", req.method(), req.path());
+    let on_panic = move |err: Box<dyn std::any::Any + Send>| {
+        tracing::error!("{route} panicked: {}", panic_message(&*err));
+        Err(ApiError::new(ErrorCode::Internal, "internal server error").into())
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| service.call(req))) {
+        Ok(response) => AssertUnwindSafe(response)
+            .catch_unwind()
+            .map(|result| result.unwrap_or_else(on_panic))
+            .boxed_local(),
+        Err(err) => Box::pin(async move { on_panic(err) }),
+    }
+}
+
+pub fn panic_message(err: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = err.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = err.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{body, test, web, App, HttpResponse};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::{catch_panic, json_error, not_found, path_error, query_error};
+
+    #[derive(Deserialize)]
+    struct Request {
+        #[allow(dead_code)]
+        value: u64,
+    }
+
+    async fn call(req: test::TestRequest) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(64)
+                        .error_handler(json_error),
+                )
+                .app_data(web::PathConfig::default().error_handler(path_error))
+                .app_data(web::QueryConfig::default().error_handler(query_error))
+                .wrap_fn(catch_panic)
+                .route(
+                    "/json",
+                    web::post().to(|_: web::Json<Request>| async { HttpResponse::Ok().finish() }),
+                )
+                .route(
+                    "/query",
+                    web::get().to(|_: web::Query<Request>| async { HttpResponse::Ok().finish() }),
+                )
+                .route(
+                    "/path/{id}",
+                    web::get().to(|_: web::Path<u64>| async { HttpResponse::Ok().finish() }),
+                )
+                .route(
+                    "/panic",
+                    web::get().to(|| async {
+                        panic!("boom");
+                        #[allow(unreachable_code)]
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .default_service(web::to(not_found)),
+        )
+        .await;
+        // errors returned by middlewares are rendered by the server
+        let response = match test::try_call_service(&app, req.to_request()).await {
+            Ok(response) => response.into_parts().1,
+            Err(err) => err.error_response(),
+        };
+        let status = response.status();
+        let body = body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_error_responses() {
+        let (status, body) = call(
+            test::TestRequest::post()
+                .uri("/json")
+                .set_json(json!({ "value": "one" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["message"].as_str().unwrap().contains("invalid type"));
+        assert_eq!(body["details"], Value::Null);
+
+        let (status, body) = call(
+            test::TestRequest::post()
+                .uri("/json")
+                .set_json(json!({ "value": 1, "padding": "-".repeat(64) })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "payload_too_large");
+
+        let (status, body) = call(
+            test::TestRequest::post()
+                .uri("/json")
+                .set_payload("value=1"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], "unsupported_media_type");
+
+        let (status, body) = call(test::TestRequest::get().uri("/path/abc")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+
+        let (status, body) = call(test::TestRequest::get().uri("/query?value=-1")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+
+        let (status, body) = call(test::TestRequest::get().uri("/unknown")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+    }
+
+    #[actix_web::test]
+    async fn test_catch_panic() {
+        let (status, body) = call(test::TestRequest::get().uri("/panic")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({ "code": "internal", "message": "internal server error", "details": null })
+        );
+    }
+}
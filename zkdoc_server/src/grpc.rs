@@ -0,0 +1,606 @@
+//! gRPC API of `proto/zkdoc.proto`, served next to the REST API for services talking gRPC.
+//! Calls share the context, job queue, proof cache, store and rate limiter of the REST routes,
+//! are authenticated with the same API keys and are answered with the same errors, their code
+//! being sent in the `zkdoc-error-code` metadata entry.
+
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+use actix_web::http::StatusCode;
+use actix_web::web;
+use futures_util::{Stream, StreamExt};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+use uuid::Uuid;
+use zkdoc_sdk::services::ZkdocContext;
+
+use crate::auth::{self, Caller, Role};
+use crate::cache::ProofCache;
+use crate::config::{Limits, Mode};
+use crate::error::{panic_message, ApiError, ErrorCode};
+use crate::jobs::{Job, JobId, JobQueue, JobStatus};
+use crate::ratelimit::{Client, RateLimiter};
+use crate::store::Store;
+use crate::telemetry::RequestId;
+use crate::webhooks::Webhooks;
+use crate::{
+    generate_commitment, job_updates, submit_proof, verify_proof,
+    GenerateCommitmentAndProofRequest, ProofVerificationRequest, ROW,
+};
+
+/// Messages and service generated from `proto/zkdoc.proto`.
+pub mod proto {
+    tonic::include_proto!("zkdoc.v1");
+}
+
+use proto::verify_proof_request::Against;
+#[cfg(test)]
+use proto::zkdoc_client::ZkdocClient;
+use proto::zkdoc_server::{Zkdoc, ZkdocServer};
+
+fn error_code_name(code: ErrorCode) -> String {
+    match serde_json::to_value(code) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!("error codes serialize to strings"),
+    }
+}
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let code = match error.code {
+            ErrorCode::InvalidRequest
+            | ErrorCode::PayloadTooLarge
+            | ErrorCode::UnsupportedMediaType
+            | ErrorCode::InvalidCommitment
+            | ErrorCode::InvalidSelector
+            | ErrorCode::InvalidHolderKey
+            | ErrorCode::ProofMalformed => Code::InvalidArgument,
+            ErrorCode::Unauthorized => Code::Unauthenticated,
+            ErrorCode::Forbidden => Code::PermissionDenied,
+            ErrorCode::NotFound
+            | ErrorCode::JobNotFound
+            | ErrorCode::DocumentNotFound
+            | ErrorCode::PresentationNotFound => Code::NotFound,
+            ErrorCode::QueueFull | ErrorCode::RateLimited => Code::ResourceExhausted,
+            ErrorCode::ProvingTimeout => Code::DeadlineExceeded,
+            ErrorCode::Unavailable => Code::Unavailable,
+            ErrorCode::ProvingFailed | ErrorCode::Internal => Code::Internal,
+        };
+        let mut metadata = MetadataMap::new();
+        let name = error_code_name(error.code);
+        metadata.insert(
+            "zkdoc-error-code",
+            name.parse().expect("error codes are valid metadata"),
+        );
+        if let Some(secs) = error.retry_after {
+            metadata.insert("retry-after", MetadataValue::from(secs));
+        }
+        Status::with_metadata(code, error.message, metadata)
+    }
+}
+
+impl TryFrom<proto::DocumentRequest> for GenerateCommitmentAndProofRequest {
+    type Error = ApiError;
+
+    fn try_from(req: proto::DocumentRequest) -> Result<Self, ApiError> {
+        let wrong_length = |name: &str, len: usize| {
+            ApiError::new(
+                ErrorCode::InvalidRequest,
+                format!("expected {ROW} {name}, got {len}"),
+            )
+        };
+        Ok(Self {
+            row_titles: req
+                .row_titles
+                .try_into()
+                .map_err(|rows: Vec<_>| wrong_length("row_titles", rows.len()))?,
+            row_contents: req
+                .row_contents
+                .try_into()
+                .map_err(|rows: Vec<_>| wrong_length("row_contents", rows.len()))?,
+            row_selectors: req
+                .row_selectors
+                .try_into()
+                .map_err(|rows: Vec<_>| wrong_length("row_selectors", rows.len()))?,
+            holder_public_key: req.holder_public_key,
+            holder_secret: req.holder_secret,
+            cache: req.cache,
+            callback_url: req.callback_url,
+        })
+    }
+}
+
+impl TryFrom<proto::VerifyProofRequest> for ProofVerificationRequest {
+    type Error = ApiError;
+
+    fn try_from(req: proto::VerifyProofRequest) -> Result<Self, ApiError> {
+        let (commitment, document_id) = match req.against {
+            Some(Against::Commitment(commitment)) => (Some(commitment), None),
+            Some(Against::DocumentId(id)) => {
+                let id = Uuid::parse_str(&id).map_err(|_| {
+                    ApiError::new(ErrorCode::InvalidRequest, "document_id should be a UUID")
+                })?;
+                (None, Some(id))
+            }
+            None => (None, None),
+        };
+        Ok(Self {
+            proof: req.proof,
+            row_title: req.row_title,
+            row_content: req.row_content,
+            commitment,
+            document_id,
+            holder_bound: req.holder_bound,
+        })
+    }
+}
+
+impl From<Job> for proto::ProofUpdate {
+    fn from(job: Job) -> Self {
+        let mut update = Self {
+            job_id: job.id.to_string(),
+            phase: job.phase.map(str::to_owned),
+            cached: job.cached,
+            ..Self::default()
+        };
+        let status = match job.status {
+            JobStatus::Queued => proto::JobStatus::Queued,
+            JobStatus::Running => proto::JobStatus::Running,
+            JobStatus::Done { proof } => {
+                update.proof = proof;
+                proto::JobStatus::Done
+            }
+            JobStatus::Failed { error } => {
+                update.error = Some(proto::Error {
+                    code: error_code_name(error.code),
+                    message: error.message,
+                });
+                proto::JobStatus::Failed
+            }
+            JobStatus::Cancelled => proto::JobStatus::Cancelled,
+        };
+        update.set_status(status);
+        update
+    }
+}
+
+/// Updates of a proof job, up to the finished job. The job is cancelled once the call is
+/// dropped, e.g. when the client goes away, as nobody would get the proof.
+pub struct ProofUpdates {
+    updates: Pin<Box<dyn Stream<Item = Job> + Send>>,
+    queue: web::Data<JobQueue>,
+    job_id: JobId,
+}
+
+impl Stream for ProofUpdates {
+    type Item = Result<proto::ProofUpdate, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.updates
+            .poll_next_unpin(cx)
+            .map(|job| job.map(proto::ProofUpdate::from).map(Ok))
+    }
+}
+
+impl Drop for ProofUpdates {
+    fn drop(&mut self) {
+        // finished jobs are left as is
+        self.queue.cancel(&self.job_id);
+    }
+}
+
+/// The state shared with the REST routes.
+#[derive(Clone)]
+pub struct ZkdocService {
+    pub context: web::Data<ZkdocContext>,
+    pub queue: web::Data<JobQueue>,
+    pub cache: web::Data<ProofCache>,
+    pub webhooks: web::Data<Webhooks>,
+    pub store: web::Data<Store>,
+    pub rate_limiter: web::Data<RateLimiter>,
+    pub limits: Limits,
+    pub auth_enabled: bool,
+    pub mode: Mode,
+}
+
+/// The key of a call, either `authorization: Bearer <key>` or `x-api-key: <key>`.
+fn request_key(metadata: &MetadataMap) -> Option<&str> {
+    if let Some(value) = metadata.get("authorization") {
+        return value.to_str().ok()?.strip_prefix("Bearer ");
+    }
+    metadata.get("x-api-key")?.to_str().ok()
+}
+
+impl ZkdocService {
+    /// Runs a call of `method`, rate limited like the REST `route` and restricted to `roles`,
+    /// within a `grpc` span. Calls made with a valid key are audited, and methods of other
+    /// modes are unimplemented. A panicking call is answered with an `internal` error, like
+    /// REST routes are. The status is boxed, as it is large.
+    fn handle<T, R>(
+        &self,
+        request: Request<T>,
+        method: &'static str,
+        route: &str,
+        roles: &[Role],
+        f: impl FnOnce(T, RequestId) -> Result<R, ApiError>,
+    ) -> Result<Response<R>, Box<Status>> {
+        let request_id = request
+            .metadata()
+            .get("x-request-id")
+            .and_then(|value| RequestId::parse(value.to_str().ok()?))
+            .unwrap_or_else(RequestId::generate);
+        let peer = request.remote_addr();
+        let span = tracing::info_span!(
+            "grpc",
+            %request_id,
+            method,
+            peer = peer.map_or_else(|| "-".to_owned(), |addr| addr.ip().to_string()),
+            code = tracing::field::Empty,
+        );
+        let _span = span.enter();
+        if !roles.iter().any(|&role| self.mode.serves(role)) {
+            let message = format!("{method} is not served in {} mode", self.mode);
+            return Err(Box::new(Status::unimplemented(message)));
+        }
+
+        let authentication = auth::identify(
+            &self.store,
+            self.auth_enabled,
+            request_key(request.metadata()),
+        );
+        let key_id = match &authentication {
+            Some(Ok(Caller::Key(key))) => Some(key.id),
+            _ => None,
+        };
+        let client = match (key_id, peer) {
+            (Some(key_id), _) => Some(Client::Key(key_id)),
+            (None, Some(addr)) => Some(Client::Ip(addr.ip())),
+            (None, None) => None,
+        };
+        let result = client
+            .map_or(Ok(()), |client| self.rate_limiter.check(client, route))
+            .and_then(|()| authentication.unwrap_or_else(|| Err(auth::missing_key())))
+            .and_then(|caller| caller.require(roles))
+            .and_then(|()| {
+                let request = request.into_inner();
+                panic::catch_unwind(AssertUnwindSafe(|| f(request, request_id.clone())))
+                    .unwrap_or_else(|err| {
+                        tracing::error!("{method} panicked: {}", panic_message(&*err));
+                        Err(ApiError::new(ErrorCode::Internal, "internal server error"))
+                    })
+            });
+
+        if let Some(key_id) = key_id {
+            let status = match &result {
+                Ok(_) => StatusCode::OK,
+                Err(err) => err.code.status(),
+            };
+            let path = format!("/zkdoc.v1.Zkdoc/{method}");
+            if let Err(err) = self.store.audit(&key_id, "POST", &path, status.as_u16()) {
+                tracing::error!("failed to audit {path} of key {key_id}: {err}");
+            }
+        }
+
+        let request_id =
+            MetadataValue::try_from(request_id.to_string()).expect("ids are valid metadata");
+        match result {
+            Ok(response) => {
+                span.record("code", tracing::field::debug(Code::Ok));
+                let mut response = Response::new(response);
+                response.metadata_mut().insert("x-request-id", request_id);
+                Ok(response)
+            }
+            Err(err) => {
+                let mut status = Status::from(err);
+                span.record("code", tracing::field::debug(status.code()));
+                status.metadata_mut().insert("x-request-id", request_id);
+                Err(Box::new(status))
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Zkdoc for ZkdocService {
+    async fn generate_commitment(
+        &self,
+        request: Request<proto::DocumentRequest>,
+    ) -> Result<Response<proto::CommitmentResponse>, Status> {
+        self.handle(
+            request,
+            "GenerateCommitment",
+            "/generate-commitment",
+            &[Role::Issuer],
+            |req, _| {
+                let commitment = generate_commitment(&req.try_into()?)?;
+                Ok(proto::CommitmentResponse { commitment })
+            },
+        )
+        .map_err(|status| *status)
+    }
+
+    type GenerateProofStream = ProofUpdates;
+
+    async fn generate_proof(
+        &self,
+        request: Request<proto::DocumentRequest>,
+    ) -> Result<Response<ProofUpdates>, Status> {
+        self.handle(
+            request,
+            "GenerateProof",
+            "/generate-proof",
+            &[Role::Holder],
+            |req, request_id| {
+                let job_id = submit_proof(
+                    req.try_into()?,
+                    &self.queue,
+                    self.cache.clone().into_inner(),
+                    self.webhooks.clone().into_inner(),
+                    self.context.clone().into_inner(),
+                    JobId::new_v4(),
+                    request_id,
+                )?;
+                let (job, updates) = self
+                    .queue
+                    .subscribe(&job_id)
+                    .expect("the job was just submitted");
+                Ok(ProofUpdates {
+                    updates: Box::pin(job_updates(job, updates)),
+                    queue: self.queue.clone(),
+                    job_id,
+                })
+            },
+        )
+        .map_err(|status| *status)
+    }
+
+    async fn verify_proof(
+        &self,
+        request: Request<proto::VerifyProofRequest>,
+    ) -> Result<Response<proto::VerifyProofResponse>, Status> {
+        // verification is CPU bound, so it runs on a blocking thread rather than a runtime one
+        let service = self.clone();
+        tokio::task::spawn_blocking(move || {
+            service.handle(
+                request,
+                "VerifyProof",
+                "/verify-proof",
+                &[Role::Verifier],
+                |req, _| {
+                    let response = verify_proof(
+                        &req.try_into()?,
+                        &service.context,
+                        &service.store,
+                        &service.limits,
+                    )?;
+                    Ok(proto::VerifyProofResponse {
+                        valid: response.valid,
+                        revoked: response.revoked,
+                    })
+                },
+            )
+        })
+        .await
+        .unwrap_or_else(|err| {
+            tracing::error!("VerifyProof failed to run: {err}");
+            Err(Box::new(
+                ApiError::new(ErrorCode::Internal, "internal server error").into(),
+            ))
+        })
+        .map_err(|status| *status)
+    }
+}
+
+async fn serve(
+    listener: tokio::net::TcpListener,
+    service: ZkdocService,
+) -> Result<(), tonic::transport::Error> {
+    let max_message_size = service.limits.json_body;
+    let service = ZkdocServer::new(service).max_decoding_message_size(max_message_size);
+    Server::builder()
+        .add_service(service)
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+}
+
+/// Serves the gRPC API on `listener` from a runtime of its own, next to the actix workers.
+pub fn spawn(listener: TcpListener, service: ZkdocService) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("grpc")
+        .enable_all()
+        .build()?;
+    thread::Builder::new()
+        .name("grpc".to_owned())
+        .spawn(move || {
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener)
+                    .expect("the listener is non blocking");
+                if let Err(err) = serve(listener, service).await {
+                    tracing::error!("gRPC server failed: {err}");
+                }
+            })
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use tonic::{Code, Request};
+    use zkdoc_sdk::services::{get_file_commitment_and_selected_row, ZkdocContext};
+
+    use super::proto::verify_proof_request::Against;
+    use super::proto::{self, DocumentRequest, VerifyProofRequest};
+    use super::{serve, ZkdocClient, ZkdocService};
+    use crate::auth::{generate_key, Role};
+    use crate::cache::ProofCache;
+    use crate::config::{self, Limits, Mode, RateLimit};
+    use crate::jobs::JobQueue;
+    use crate::ratelimit::RateLimiter;
+    use crate::store::{NewApiKey, Store};
+    use crate::webhooks::Webhooks;
+
+    fn create_key(store: &Store, role: Role) -> String {
+        let (key, key_hash) = generate_key();
+        store
+            .create_api_key(NewApiKey {
+                name: role.to_string(),
+                role,
+                issuer_id: (role == Role::Issuer).then(|| "issuer".to_owned()),
+                key_hash,
+            })
+            .unwrap();
+        key
+    }
+
+    fn with_key<T>(message: T, key: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        let value = format!("Bearer {key}").parse().unwrap();
+        request.metadata_mut().insert("authorization", value);
+        request
+    }
+
+    fn document() -> DocumentRequest {
+        let mut rows = vec!["-".to_owned(); 10];
+        rows[1] = "else".to_owned();
+        let mut contents = vec!["-".to_owned(); 10];
+        contents[1] = "down".to_owned();
+        DocumentRequest {
+            row_titles: rows,
+            row_contents: contents,
+            row_selectors: vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+            holder_public_key: None,
+            holder_secret: None,
+            cache: true,
+            callback_url: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_grpc() {
+        let store = Store::open_in_memory().unwrap();
+        let issuer = create_key(&store, Role::Issuer);
+        let holder = create_key(&store, Role::Holder);
+        let verifier = create_key(&store, Role::Verifier);
+        let store = web::Data::new(store);
+        let service = ZkdocService {
+            context: web::Data::new(ZkdocContext::new()),
+            queue: web::Data::new(JobQueue::new(1, 1, std::time::Duration::from_secs(600))),
+            cache: web::Data::new(ProofCache::new(config::ProofCache {
+                enabled: true,
+                ..Default::default()
+            })),
+            webhooks: web::Data::new(Webhooks::new(config::Webhooks::default(), store.clone())),
+            store,
+            // room for the 3 proof requests
+            rate_limiter: web::Data::new(RateLimiter::new(RateLimit {
+                burst: 90,
+                ..Default::default()
+            })),
+            limits: Limits::default(),
+            auth_enabled: true,
+            mode: Mode::All,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, service));
+        let mut client = ZkdocClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+
+        let status = client.generate_commitment(document()).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(
+            status.metadata().get("zkdoc-error-code").unwrap(),
+            "unauthorized"
+        );
+
+        let document = document();
+        let commitment = client
+            .generate_commitment(with_key(document.clone(), &issuer))
+            .await
+            .unwrap()
+            .into_inner()
+            .commitment;
+        let expected = get_file_commitment_and_selected_row(
+            document.row_titles.clone().try_into().unwrap(),
+            document.row_contents.clone().try_into().unwrap(),
+            document.row_selectors.clone().try_into().unwrap(),
+        );
+        assert_eq!(commitment, expected);
+
+        let mut truncated = document.clone();
+        truncated.row_titles.pop();
+        let status = client
+            .generate_proof(with_key(truncated, &holder))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "expected 10 row_titles, got 9");
+
+        let mut updates = client
+            .generate_proof(with_key(document.clone(), &holder))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut phases = Vec::new();
+        let proof = loop {
+            let update = updates.message().await.unwrap().unwrap();
+            match update.status() {
+                proto::JobStatus::Queued | proto::JobStatus::Running => phases.extend(update.phase),
+                proto::JobStatus::Done => break update.proof,
+                status => panic!("unexpected job status {status:?}: {:?}", update.error),
+            }
+        };
+        assert!(phases.contains(&"keygen".to_owned()));
+        assert_eq!(phases.last().unwrap(), "opening");
+        assert!(updates.message().await.unwrap().is_none());
+
+        // the proof was cached as the request opted in
+        let mut updates = client
+            .generate_proof(with_key(document, &holder))
+            .await
+            .unwrap()
+            .into_inner();
+        let update = updates.message().await.unwrap().unwrap();
+        assert_eq!(update.status(), proto::JobStatus::Done);
+        assert!(update.cached);
+        assert_eq!(update.proof, proof);
+        assert!(updates.message().await.unwrap().is_none());
+
+        let verify = |row_content: &str| VerifyProofRequest {
+            proof: proof.clone(),
+            row_title: "else".to_owned(),
+            row_content: row_content.to_owned(),
+            against: Some(Against::Commitment(commitment.clone())),
+            holder_bound: false,
+        };
+        let mut request = with_key(verify("down"), &verifier);
+        request
+            .metadata_mut()
+            .insert("x-request-id", "grpc-test".parse().unwrap());
+        let response = client.verify_proof(request).await.unwrap();
+        assert_eq!(
+            response.metadata().get("x-request-id").unwrap(),
+            "grpc-test"
+        );
+        assert!(response.into_inner().valid);
+        let response = client
+            .verify_proof(with_key(verify("up"), &verifier))
+            .await
+            .unwrap();
+        assert!(!response.into_inner().valid);
+
+        let status = client
+            .verify_proof(with_key(verify("down"), &holder))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+}
@@ -0,0 +1,665 @@
+//! Proof jobs, run on a fixed pool of worker threads so that the 30+ seconds of proving do not
+//! block the actix workers. Jobs are kept in memory, polled by id or followed through a stream
+//! of updates, and are cancelled when they run for too long. On shutdown the queue is closed:
+//! running jobs finish while queued jobs stay queued, their ids and requests being handed over
+//! to be persisted.
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use zkdoc_sdk::services::{CancellationToken, Cancelled, ProvingPhase};
+
+use crate::error::{panic_message, ApiError, ErrorCode};
+use crate::telemetry::{ACTIVE_JOBS, FINISHED_JOBS, QUEUE_DEPTH};
+
+pub type JobId = Uuid;
+
+/// Finished jobs are dropped after this long
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+/// A job goes through fewer updates than this, so subscribers never lag behind
+const JOB_UPDATES: usize = 16;
+
+type Task = Box<dyn FnOnce(&JobContext) -> Result<Vec<u8>, Cancelled> + Send + 'static>;
+type Listener = Box<dyn FnOnce(&Job) + Send + 'static>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done { proof: Vec<u8> },
+    Failed { error: ApiError },
+    Cancelled,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Job {
+    #[schema(value_type = String, format = Uuid)]
+    pub id: JobId,
+    #[serde(flatten)]
+    pub status: JobStatus,
+    /// Current proving phase while running, e.g. `keygen`
+    #[schema(value_type = Option<String>, example = "keygen")]
+    pub phase: Option<&'static str>,
+    /// Unix timestamps in milliseconds
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    /// Whether the proof was taken from the proof cache rather than generated for this job
+    pub cached: bool,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            JobStatus::Done { .. } | JobStatus::Failed { .. } | JobStatus::Cancelled
+        )
+    }
+
+    fn finish(&mut self, status: JobStatus) {
+        let name = match status {
+            JobStatus::Done { .. } => "done",
+            JobStatus::Failed { .. } => "failed",
+            _ => "cancelled",
+        };
+        metrics::counter!(FINISHED_JOBS, "status" => name).increment(1);
+        self.status = status;
+        self.phase = None;
+        self.finished_at = Some(now());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubmitError {
+    /// The queue already holds the maximum number of waiting jobs
+    QueueFull,
+    /// The queue was closed for shutdown
+    Closed,
+}
+
+struct Entry {
+    job: Job,
+    updates: broadcast::Sender<Job>,
+    /// Cancels the job on request, the deadline is only set once it runs
+    cancellation: CancellationToken,
+    /// Called once the job finishes, see [`JobQueue::on_finished`]
+    on_finished: Option<Listener>,
+    /// Serialized request the job was submitted for, to persist it while unfinished on shutdown.
+    /// Unset when it should not be persisted, e.g. as it holds a secret.
+    request: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    jobs: HashMap<JobId, Entry>,
+    /// Number of jobs waiting for a worker
+    queued: usize,
+    /// Number of jobs being run by a worker
+    running: usize,
+    /// Whether the queue was closed, workers no longer starting jobs
+    closed: bool,
+}
+
+impl State {
+    /// Updates a job and notifies its subscribers if it changed.
+    fn update<R>(&mut self, id: &JobId, f: impl FnOnce(&mut Entry) -> R) -> Option<R> {
+        let entry = self.jobs.get_mut(id)?;
+        let before = entry.job.clone();
+        let result = f(entry);
+        if entry.job != before {
+            // fails when nobody is subscribed
+            let _ = entry.updates.send(entry.job.clone());
+        }
+        if entry.job.is_finished() {
+            if let Some(listener) = entry.on_finished.take() {
+                listener(&entry.job);
+            }
+        }
+        Some(result)
+    }
+
+    /// Forgets the jobs finished for longer than [`JOB_RETENTION`].
+    fn prune(&mut self, now: u64) {
+        self.jobs.retain(|_, Entry { job, .. }| {
+            !job.is_finished()
+                || job.finished_at.unwrap_or(now) + JOB_RETENTION.as_millis() as u64 > now
+        });
+    }
+
+    /// Exports the number of queued and running jobs, once they changed.
+    fn record_counts(&self) {
+        metrics::gauge!(QUEUE_DEPTH).set(self.queued as f64);
+        metrics::gauge!(ACTIVE_JOBS).set(self.running as f64);
+    }
+}
+
+pub struct JobQueue {
+    state: Arc<Mutex<State>>,
+    sender: mpsc::Sender<(JobId, Task)>,
+    max_queued: usize,
+}
+
+impl JobQueue {
+    /// Spawns `workers` proving threads. At most `max_queued` jobs may wait for a free worker,
+    /// further submissions are rejected, and jobs running for longer than `timeout` fail.
+    pub fn new(workers: usize, max_queued: usize, timeout: Duration) -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (sender, receiver) = mpsc::channel::<(JobId, Task)>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers {
+            let state = state.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("prover-{i}"))
+                .spawn(move || loop {
+                    // the lock is released before running the task
+                    let next = receiver.lock().unwrap().recv();
+                    let Ok((id, task)) = next else {
+                        break;
+                    };
+                    run(&state, id, task, timeout);
+                })
+                .expect("spawning a proving thread should not fail");
+        }
+
+        Self {
+            state,
+            sender,
+            max_queued,
+        }
+    }
+
+    /// Enqueues a task under `id`, which should return early with [`Cancelled`] once
+    /// [`JobContext::cancellation`] is cancelled. `request` is what the task was made from,
+    /// returned by [`JobQueue::unfinished_requests`].
+    pub fn submit(
+        &self,
+        id: JobId,
+        request: Option<String>,
+        task: impl FnOnce(&JobContext) -> Result<Vec<u8>, Cancelled> + Send + 'static,
+    ) -> Result<JobId, SubmitError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(SubmitError::Closed);
+        }
+        if state.queued >= self.max_queued {
+            return Err(SubmitError::QueueFull);
+        }
+
+        let now = now();
+        state.prune(now);
+
+        state.jobs.insert(
+            id,
+            Entry {
+                job: Job {
+                    id,
+                    status: JobStatus::Queued,
+                    phase: None,
+                    created_at: now,
+                    started_at: None,
+                    finished_at: None,
+                    cached: false,
+                },
+                updates: broadcast::channel(JOB_UPDATES).0,
+                cancellation: CancellationToken::new(),
+                on_finished: None,
+                request,
+            },
+        );
+        state.queued += 1;
+        state.record_counts();
+        self.sender
+            .send((id, Box::new(task)))
+            .expect("proving threads should be running");
+
+        Ok(id)
+    }
+
+    /// Adds a job already done with a proof taken from the proof cache.
+    pub fn insert_cached(&self, id: JobId, proof: Vec<u8>) -> JobId {
+        self.insert_finished(id, JobStatus::Done { proof }, true)
+    }
+
+    /// Adds a job already failed, e.g. one that could not be resumed after a restart.
+    pub fn insert_failed(&self, id: JobId, error: ApiError) -> JobId {
+        self.insert_finished(id, JobStatus::Failed { error }, false)
+    }
+
+    fn insert_finished(&self, id: JobId, status: JobStatus, cached: bool) -> JobId {
+        let mut state = self.state.lock().unwrap();
+        let now = now();
+        state.prune(now);
+
+        let mut job = Job {
+            id,
+            status: JobStatus::Queued,
+            phase: None,
+            created_at: now,
+            started_at: Some(now),
+            finished_at: Some(now),
+            cached,
+        };
+        job.finish(status);
+        state.jobs.insert(
+            id,
+            Entry {
+                job,
+                updates: broadcast::channel(JOB_UPDATES).0,
+                cancellation: CancellationToken::new(),
+                on_finished: None,
+                request: None,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: &JobId) -> Option<Job> {
+        let state = self.state.lock().unwrap();
+        state.jobs.get(id).map(|entry| entry.job.clone())
+    }
+
+    /// Returns the job along with a receiver of its following updates, the last one being the
+    /// finished job.
+    pub fn subscribe(&self, id: &JobId) -> Option<(Job, broadcast::Receiver<Job>)> {
+        let state = self.state.lock().unwrap();
+        state
+            .jobs
+            .get(id)
+            .map(|entry| (entry.job.clone(), entry.updates.subscribe()))
+    }
+
+    /// Calls `listener` with the job once it is finished, right away if it already is. It is
+    /// called while the queue is locked, so it should only hand the job over, e.g. to a channel.
+    /// Unknown jobs are ignored.
+    pub fn on_finished(&self, id: &JobId, listener: impl FnOnce(&Job) + Send + 'static) {
+        let mut state = self.state.lock().unwrap();
+        state.update(id, |entry| entry.on_finished = Some(Box::new(listener)));
+    }
+
+    /// Cancels a job. A queued job is cancelled right away, while a running job is cancelled
+    /// once its task returns. Finished jobs are left as is.
+    pub fn cancel(&self, id: &JobId) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        let (job, was_queued) = state.update(id, |entry| {
+            let was_queued = entry.job.status == JobStatus::Queued;
+            if was_queued {
+                entry.job.finish(JobStatus::Cancelled);
+            }
+            entry.cancellation.cancel();
+            (entry.job.clone(), was_queued)
+        })?;
+        if was_queued {
+            state.queued -= 1;
+            state.record_counts();
+        }
+
+        Some(job)
+    }
+
+    /// Rejects further submissions with [`SubmitError::Closed`]. Running jobs go on, while
+    /// queued jobs are no longer started.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+    }
+
+    /// Number of jobs being run by a worker.
+    pub fn running(&self) -> usize {
+        self.state.lock().unwrap().running
+    }
+
+    /// Ids and requests of the jobs not finished yet, oldest first. The request is unset for
+    /// jobs submitted without one.
+    pub fn unfinished_requests(&self) -> Vec<(JobId, Option<String>)> {
+        let state = self.state.lock().unwrap();
+        let mut jobs = state
+            .jobs
+            .values()
+            .filter(|entry| !entry.job.is_finished())
+            .map(|entry| (entry.job.created_at, entry.job.id, entry.request.clone()))
+            .collect::<Vec<_>>();
+        jobs.sort();
+        jobs.into_iter()
+            .map(|(_, id, request)| (id, request))
+            .collect()
+    }
+}
+
+/// Handle passed to a running task to report its progress and check for cancellation.
+pub struct JobContext {
+    state: Arc<Mutex<State>>,
+    id: JobId,
+    cancellation: CancellationToken,
+}
+
+impl JobContext {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn phase(&self, phase: ProvingPhase) {
+        let mut state = self.state.lock().unwrap();
+        state.update(&self.id, |entry| entry.job.phase = Some(phase.name()));
+    }
+
+    /// Cancelled on request or once the job times out.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+}
+
+fn run(state: &Arc<Mutex<State>>, id: JobId, task: Task, timeout: Duration) {
+    let cancellation = {
+        let mut state = state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        let cancellation = state.update(&id, |entry| {
+            // cancelled while queued
+            if entry.job.is_finished() {
+                return None;
+            }
+            entry.job.status = JobStatus::Running;
+            entry.job.started_at = Some(now());
+            Some(entry.cancellation.clone())
+        });
+        match cancellation.flatten() {
+            Some(cancellation) => {
+                state.queued -= 1;
+                state.running += 1;
+                state.record_counts();
+                cancellation
+            }
+            None => return,
+        }
+    };
+
+    let context = JobContext {
+        state: state.clone(),
+        id,
+        cancellation: cancellation.clone().with_deadline(Instant::now() + timeout),
+    };
+    let status = match panic::catch_unwind(AssertUnwindSafe(|| task(&context))) {
+        Ok(Ok(proof)) => JobStatus::Done { proof },
+        // only the deadline is left when the job was not cancelled on request
+        Ok(Err(Cancelled)) if cancellation.is_cancelled() => JobStatus::Cancelled,
+        Ok(Err(Cancelled)) => JobStatus::Failed {
+            error: ApiError::new(
+                ErrorCode::ProvingTimeout,
+                format!("proof generation timed out after {timeout:?}"),
+            ),
+        },
+        Err(err) => JobStatus::Failed {
+            error: ApiError::new(ErrorCode::ProvingFailed, panic_message(&*err)),
+        },
+    };
+
+    let mut state = state.lock().unwrap();
+    state.update(&id, |entry| entry.job.finish(status));
+    state.running -= 1;
+    state.record_counts();
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time should be after the epoch")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use zkdoc_sdk::services::{Cancelled, ProvingPhase};
+
+    use super::{Job, JobContext, JobId, JobQueue, JobStatus, SubmitError};
+    use crate::error::{ApiError, ErrorCode};
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Stands in for proving, running until cancelled.
+    fn wait_until_cancelled(context: &JobContext) -> Result<Vec<u8>, Cancelled> {
+        while !context.cancellation().is_cancelled() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        Err(Cancelled)
+    }
+
+    fn submit(
+        queue: &JobQueue,
+        task: impl FnOnce(&JobContext) -> Result<Vec<u8>, Cancelled> + Send + 'static,
+    ) -> Result<JobId, SubmitError> {
+        queue.submit(JobId::new_v4(), None, task)
+    }
+
+    fn wait_for(queue: &JobQueue, id: &JobId, f: impl Fn(&Job) -> bool) -> Job {
+        for _ in 0..500 {
+            let job = queue.get(id).unwrap();
+            if f(&job) {
+                return job;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out waiting for job {id}");
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let queue = JobQueue::new(1, 4, TIMEOUT);
+        let id = submit(&queue, |_| Ok(vec![1, 2, 3])).unwrap();
+
+        let job = wait_for(&queue, &id, |job| job.finished_at.is_some());
+        assert_eq!(
+            job.status,
+            JobStatus::Done {
+                proof: vec![1, 2, 3]
+            }
+        );
+        assert!(job.created_at <= job.started_at.unwrap());
+        assert!(job.started_at <= job.finished_at);
+        assert!(!job.cached);
+
+        let id = submit(&queue, |_| panic!("invalid row")).unwrap();
+        let job = wait_for(&queue, &id, |job| job.finished_at.is_some());
+        assert_eq!(
+            job.status,
+            JobStatus::Failed {
+                error: ApiError::new(ErrorCode::ProvingFailed, "invalid row")
+            }
+        );
+
+        assert!(queue.get(&JobId::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_insert_cached() {
+        let queue = JobQueue::new(1, 4, TIMEOUT);
+        let id = queue.insert_cached(JobId::new_v4(), vec![1, 2, 3]);
+
+        let (job, _) = queue.subscribe(&id).unwrap();
+        assert!(job.cached);
+        assert!(job.is_finished());
+        assert_eq!(
+            job.status,
+            JobStatus::Done {
+                proof: vec![1, 2, 3]
+            }
+        );
+        assert_eq!(queue.cancel(&id).unwrap().status, job.status);
+
+        let error = ApiError::new(ErrorCode::Unavailable, "resubmit");
+        let id = queue.insert_failed(JobId::new_v4(), error.clone());
+        let job = queue.get(&id).unwrap();
+        assert!(!job.cached);
+        assert_eq!(job.status, JobStatus::Failed { error });
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let queue = JobQueue::new(1, 1, TIMEOUT);
+        let (release, blocked) = mpsc::channel::<()>();
+
+        let running = submit(&queue, move |_| {
+            blocked.recv().unwrap();
+            Ok(vec![])
+        })
+        .unwrap();
+        wait_for(&queue, &running, |job| job.status == JobStatus::Running);
+
+        let queued = submit(&queue, |_| Ok(vec![])).unwrap();
+        assert_eq!(queue.get(&queued).unwrap().status, JobStatus::Queued);
+        assert_eq!(submit(&queue, |_| Ok(vec![])), Err(SubmitError::QueueFull));
+
+        release.send(()).unwrap();
+        wait_for(&queue, &queued, |job| job.finished_at.is_some());
+        assert!(submit(&queue, |_| Ok(vec![])).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_job_updates() {
+        let queue = JobQueue::new(1, 1, TIMEOUT);
+        let (release, blocked) = mpsc::channel::<()>();
+
+        let id = submit(&queue, move |context| {
+            blocked.recv().unwrap();
+            context.phase(ProvingPhase::Keygen);
+            Ok(vec![1])
+        })
+        .unwrap();
+        let (job, mut updates) = queue.subscribe(&id).unwrap();
+        assert!(!job.is_finished());
+
+        release.send(()).unwrap();
+        let mut phases = Vec::new();
+        let job = loop {
+            let job = updates.recv().await.unwrap();
+            if job.is_finished() {
+                break job;
+            }
+            phases.extend(job.phase);
+        };
+        assert_eq!(phases, ["keygen"]);
+        assert_eq!(job.status, JobStatus::Done { proof: vec![1] });
+        assert_eq!(job.phase, None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let queue = JobQueue::new(1, 1, TIMEOUT);
+
+        let running = submit(&queue, wait_until_cancelled).unwrap();
+        wait_for(&queue, &running, |job| job.status == JobStatus::Running);
+        let queued = submit(&queue, |_| Ok(vec![])).unwrap();
+
+        let job = queue.cancel(&queued).unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+        // the queued job no longer counts towards the queue depth
+        let next = submit(&queue, |_| Ok(vec![1])).unwrap();
+
+        assert_eq!(queue.cancel(&running).unwrap().status, JobStatus::Running);
+        let job = wait_for(&queue, &running, |job| job.finished_at.is_some());
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        let job = wait_for(&queue, &next, |job| job.finished_at.is_some());
+        assert_eq!(job.status, JobStatus::Done { proof: vec![1] });
+        assert_eq!(queue.cancel(&next).unwrap().status, job.status);
+        assert!(queue.cancel(&JobId::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_on_finished() {
+        let queue = JobQueue::new(1, 4, TIMEOUT);
+        let (sender, receiver) = mpsc::channel();
+
+        let running = submit(&queue, wait_until_cancelled).unwrap();
+        let listener =
+            |sender: mpsc::Sender<Job>| move |job: &Job| sender.send(job.clone()).unwrap();
+        queue.on_finished(&running, listener(sender.clone()));
+        wait_for(&queue, &running, |job| job.status == JobStatus::Running);
+        assert!(receiver.try_recv().is_err());
+        queue.cancel(&running);
+        let job = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        // already finished
+        let cached = queue.insert_cached(JobId::new_v4(), vec![1]);
+        queue.on_finished(&cached, listener(sender));
+        let job = receiver.try_recv().unwrap();
+        assert_eq!(job.id, cached);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_close() {
+        let queue = JobQueue::new(1, 4, TIMEOUT);
+        let (release, blocked) = mpsc::channel::<()>();
+
+        let running = queue
+            .submit(JobId::new_v4(), Some("running".to_owned()), move |_| {
+                blocked.recv().unwrap();
+                Ok(vec![1])
+            })
+            .unwrap();
+        wait_for(&queue, &running, |job| job.status == JobStatus::Running);
+        let queued = queue
+            .submit(JobId::new_v4(), Some("queued".to_owned()), |_| Ok(vec![2]))
+            .unwrap();
+        let without_request = submit(&queue, |_| Ok(vec![3])).unwrap();
+
+        queue.close();
+        assert_eq!(submit(&queue, |_| Ok(vec![])), Err(SubmitError::Closed));
+        assert_eq!(queue.running(), 1);
+        // all may have been submitted within the same millisecond
+        let mut requests = queue.unfinished_requests();
+        requests.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            requests,
+            [
+                (without_request, None),
+                (queued, Some("queued".to_owned())),
+                (running, Some("running".to_owned()))
+            ]
+        );
+
+        // the running job finishes, the queued one is not started
+        release.send(()).unwrap();
+        wait_for(&queue, &running, |job| job.finished_at.is_some());
+        assert_eq!(queue.running(), 0);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.get(&queued).unwrap().status, JobStatus::Queued);
+        assert_eq!(queue.unfinished_requests().len(), 2);
+        assert!(queue
+            .unfinished_requests()
+            .contains(&(queued, Some("queued".to_owned()))));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let queue = JobQueue::new(1, 1, Duration::from_millis(50));
+
+        let id = submit(&queue, wait_until_cancelled).unwrap();
+        let job = wait_for(&queue, &id, |job| job.finished_at.is_some());
+        assert_eq!(
+            job.status,
+            JobStatus::Failed {
+                error: ApiError::new(
+                    ErrorCode::ProvingTimeout,
+                    "proof generation timed out after 50ms"
+                )
+            }
+        );
+    }
+}
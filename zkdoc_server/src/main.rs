@@ -1,40 +1,197 @@
+mod admin;
+mod auth;
+mod cache;
+mod config;
+mod error;
+mod grpc;
+mod jobs;
+mod openapi;
+mod ratelimit;
+mod shutdown;
+mod store;
+mod telemetry;
+mod webhooks;
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
 use actix_cors::Cors;
-use actix_web::{get, middleware, post, web, App, HttpResponse, HttpServer, Responder, Result};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{delete, get, post, web, App, HttpResponse, HttpServer, Responder};
+use clap::Parser;
+use futures_util::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use utoipa::{IntoParams, ToSchema};
+use zkdoc_sdk::holder::HolderKey;
+use zkdoc_sdk::services::telemetry::VERIFICATIONS;
 use zkdoc_sdk::services::{
-    generate_proof, get_file_commitment_and_selected_row, get_selected_row, verify_correct_selector,
+    get_file_commitment_and_selected_row, get_holder_bound_commitment, get_selected_row,
+    BatchVerificationItem, VerificationError, ZkdocContext,
+};
+use zkdoc_sdk::utils::conversion::fp_from_hex;
+
+use crate::auth::{AuthEnabled, Caller, Role};
+use crate::cache::ProofCache;
+use crate::config::{Args, Config, Limits, LogFormat, Mode, Presentations};
+use crate::error::{ApiError, ErrorCode};
+use crate::jobs::{Job, JobId, JobQueue, SubmitError};
+use crate::ratelimit::RateLimiter;
+use crate::store::{
+    Delivery, Document, DocumentId, NewDocument, NewPresentation, Presentation, PresentationId,
+    Store,
 };
+use crate::telemetry::RequestId;
+use crate::webhooks::Webhooks;
 
 const ROW: usize = 10;
+/// Documents listed per page unless the request asks for fewer
+const MAX_PAGE_SIZE: u32 = 100;
+/// Expired presentations are no longer served, and deleted this often
+const PRESENTATION_SWEEP: Duration = Duration::from_secs(60);
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct GenerateCommitmentAndProofRequest {
+    #[schema(min_items = 10, max_items = 10)]
     row_titles: [String; ROW],
+    #[schema(min_items = 10, max_items = 10)]
     row_contents: [String; ROW],
+    /// 1 for the rows to disclose, 0 otherwise
+    #[schema(min_items = 10, max_items = 10)]
     row_selectors: [u64; ROW],
+    /// Holder public key to bind the document to when generating the commitment
+    holder_public_key: Option<String>,
+    /// Holder secret, required to generate proofs for holder bound documents. Sending it trusts
+    /// the server with it, holders who keep it to themselves prove on their own device.
+    holder_secret: Option<String>,
+    /// Reuse a proof of the same disclosure generated for an earlier request that also set
+    /// `cache`, when the server caches proofs. Presentations using the same proof are linkable.
+    #[serde(default)]
+    cache: bool,
+    /// URL the job is posted to once finished, when the server has webhooks enabled
+    callback_url: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct GenerateCommitmentResponse {
     commitment: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct GenerateProofResponse {
-    proof: Vec<u8>,
+    #[schema(value_type = String, format = Uuid)]
+    job_id: JobId,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeliveriesResponse {
+    /// Attempts in order, the last one telling whether the job was delivered
+    deliveries: Vec<Delivery>,
 }
 
-#[derive(Deserialize)]
+/// Proofs are verified against either a `commitment` or a registered document
+#[derive(Deserialize, ToSchema)]
 struct ProofVerificationRequest {
     proof: Vec<u8>,
     row_title: String,
     row_content: String,
-    commitment: String,
+    commitment: Option<String>,
+    #[schema(value_type = Option<String>, format = Uuid)]
+    document_id: Option<DocumentId>,
+    #[serde(default)]
+    holder_bound: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ProofVerificationResponse {
     valid: bool,
+    /// Whether the document was revoked, when verifying against a document. Proofs of revoked
+    /// documents are not valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revoked: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct BatchVerificationRequest {
+    items: Vec<ProofVerificationRequest>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchVerificationResponse {
+    valid: bool,
+    /// Indexes of the items that failed verification
+    failed: Vec<usize>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreatePresentationRequest {
+    #[serde(flatten)]
+    presentation: ProofVerificationRequest,
+    /// Seconds the presentation can be fetched for, 10 minutes by default
+    ttl_secs: Option<u64>,
+    /// Delete the presentation once fetched
+    #[serde(default)]
+    single_use: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PresentationResponse {
+    id: PresentationId,
+    /// Path of the presentation, for the verifier to fetch
+    url: String,
+    /// Unix timestamp in milliseconds
+    expires_at: u64,
+    single_use: bool,
+}
+
+/// The disclosed row of a presentation along with the verification of its proof
+#[derive(Serialize, ToSchema)]
+struct PresentationVerificationResponse {
+    row_title: String,
+    row_content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commitment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    document_id: Option<DocumentId>,
+    holder_bound: bool,
+    #[serde(flatten)]
+    verification: ProofVerificationResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RegisterDocumentRequest {
+    commitment: String,
+    issuer_id: String,
+    /// Kind of document, e.g. `passport`
+    document_type: String,
+    /// Unix timestamp in milliseconds, the registration time when unset
+    issued_at: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DocumentsQuery {
+    issuer_id: String,
+    /// Documents per page, at most 100
+    limit: Option<u32>,
+    /// Documents to skip, see `next_offset`
+    #[serde(default)]
+    offset: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DocumentsResponse {
+    /// Documents in registration order
+    documents: Vec<Document>,
+    /// Offset of the next page, if any
+    next_offset: Option<u32>,
 }
 
 /// This is for health check
@@ -43,67 +200,1151 @@ async fn hello() -> impl Responder {
     HttpResponse::Ok().body("Rusty is fine!")
 }
 
+/// Whether the keys of the mode are loaded and whether the server is shutting down, registered
+/// as app data.
+#[derive(Default)]
+struct Readiness {
+    keys_loaded: AtomicBool,
+    draining: AtomicBool,
+}
+
+/// This is for readiness check, failing with a 503 until the keys are loaded and once shutting
+/// down
+#[get("/ready")]
+async fn ready(readiness: web::Data<Readiness>) -> Result<HttpResponse, ApiError> {
+    if readiness.draining.load(Ordering::Acquire) {
+        return Err(ApiError::new(ErrorCode::Unavailable, "shutting down"));
+    }
+    match readiness.keys_loaded.load(Ordering::Acquire) {
+        true => Ok(HttpResponse::Ok().body("ready")),
+        false => Err(ApiError::new(ErrorCode::Unavailable, "loading keys")),
+    }
+}
+
+/// Derives the keys the mode needs from a thread of its own, then reports ready. Verifiers only
+/// need the verifying keys, and issuers none.
+fn load_keys(mode: Mode, context: web::Data<ZkdocContext>, readiness: web::Data<Readiness>) {
+    let spawned = thread::Builder::new()
+        .name("keygen".to_owned())
+        .spawn(move || {
+            if mode.serves(Role::Holder) {
+                context.load_proving_keys();
+            } else if mode.serves(Role::Verifier) {
+                context.load_verifying_keys();
+            }
+            readiness.keys_loaded.store(true, Ordering::Release);
+            tracing::info!("ready");
+        });
+    spawned.expect("spawning the keygen thread should not fail");
+}
+
+/// Deletes the expired presentations from a thread of its own.
+fn sweep_presentations(store: web::Data<Store>) {
+    let spawned = thread::Builder::new()
+        .name("presentations".to_owned())
+        .spawn(move || loop {
+            thread::sleep(PRESENTATION_SWEEP);
+            match store.delete_expired_presentations() {
+                Ok(0) => {}
+                Ok(deleted) => tracing::debug!(deleted, "deleted expired presentations"),
+                Err(err) => tracing::error!("failed to delete expired presentations: {err}"),
+            }
+        });
+    spawned.expect("spawning the presentations thread should not fail");
+}
+
+/// Row selectors are either 0 or 1, anything else yields proofs that do not verify
+fn validate_selectors(row_selectors: &[u64; ROW]) -> Result<(), ApiError> {
+    match row_selectors.iter().position(|selector| *selector > 1) {
+        Some(index) => Err(ApiError::new(
+            ErrorCode::InvalidSelector,
+            "row selectors should be 0 or 1",
+        )
+        .with_details(json!({ "index": index, "value": row_selectors[index] }))),
+        None => Ok(()),
+    }
+}
+
+fn invalid_commitment() -> ApiError {
+    ApiError::new(
+        ErrorCode::InvalidCommitment,
+        "commitment should be a 0x prefixed hex field element",
+    )
+}
+
+/// The body limit leaves room for proofs far larger than real ones, which are ~4kb
+fn check_proof_size(proof: &[u8], limits: &Limits) -> Result<(), ApiError> {
+    if proof.len() > limits.proof_bytes {
+        return Err(ApiError::new(
+            ErrorCode::PayloadTooLarge,
+            format!("proof is over the {} bytes limit", limits.proof_bytes),
+        ));
+    }
+    Ok(())
+}
+
+fn job_not_found() -> ApiError {
+    ApiError::new(ErrorCode::JobNotFound, "job not found")
+}
+
+fn document_not_found() -> ApiError {
+    ApiError::new(ErrorCode::DocumentNotFound, "document not found")
+}
+
+fn presentation_not_found() -> ApiError {
+    ApiError::new(ErrorCode::PresentationNotFound, "presentation not found")
+}
+
+/// The commitment a proof is verified against, and whether its document was revoked when
+/// given a document id
+fn resolve_commitment(
+    req: &ProofVerificationRequest,
+    store: &Store,
+) -> Result<(String, Option<bool>), ApiError> {
+    match (&req.commitment, &req.document_id) {
+        (Some(commitment), None) => Ok((commitment.to_owned(), None)),
+        (None, Some(document_id)) => {
+            let document = store
+                .document(document_id)?
+                .ok_or_else(document_not_found)?;
+            let revoked = document.is_revoked();
+            Ok((document.commitment, Some(revoked)))
+        }
+        _ => Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "expected either a commitment or a document_id",
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/generate-commitment",
+    request_body = GenerateCommitmentAndProofRequest,
+    responses(
+        (status = 200, body = GenerateCommitmentResponse),
+        (status = 400, description = "`invalid_request`, `invalid_selector` or `invalid_holder_key`", body = ApiError),
+    )
+)]
 #[post("/generate-commitment")]
 async fn generate_commitment_handler(
     req: web::Json<GenerateCommitmentAndProofRequest>,
-) -> Result<impl Responder> {
-    let commitment = get_file_commitment_and_selected_row(
-        req.row_titles.to_owned(),
-        req.row_contents.to_owned(),
-        req.row_selectors.to_owned(),
-    );
+    caller: Caller,
+) -> Result<impl Responder, ApiError> {
+    caller.require(&[Role::Issuer])?;
+    let commitment = generate_commitment(&req)?;
 
     Ok(web::Json(GenerateCommitmentResponse { commitment }))
 }
 
+fn generate_commitment(req: &GenerateCommitmentAndProofRequest) -> Result<String, ApiError> {
+    validate_selectors(&req.row_selectors)?;
+    let commitment = match &req.holder_public_key {
+        Some(holder_public_key) => get_holder_bound_commitment(
+            req.row_titles.to_owned(),
+            req.row_contents.to_owned(),
+            holder_public_key.to_owned(),
+        )
+        .ok_or_else(|| ApiError::new(ErrorCode::InvalidHolderKey, "invalid holder public key"))?,
+        None => get_file_commitment_and_selected_row(
+            req.row_titles.to_owned(),
+            req.row_contents.to_owned(),
+            req.row_selectors.to_owned(),
+        ),
+    };
+
+    Ok(commitment)
+}
+
+/// Enqueues a proof job, poll `/v1/jobs/{id}` for the proof
+#[utoipa::path(
+    post,
+    path = "/v1/generate-proof",
+    request_body = GenerateCommitmentAndProofRequest,
+    responses(
+        (status = 202, body = GenerateProofResponse, headers(("Location" = String, description = "URL of the job"))),
+        (status = 400, description = "`invalid_request`, `invalid_selector` or `invalid_holder_key`", body = ApiError),
+        (status = 429, description = "`queue_full` or `rate_limited`", body = ApiError),
+        (status = 503, description = "`unavailable` while shutting down", body = ApiError),
+    )
+)]
 #[post("/generate-proof")]
 async fn generate_proof_handler(
     req: web::Json<GenerateCommitmentAndProofRequest>,
-) -> Result<impl Responder> {
-    // FYI this runs for 30+ seconds
-    let proof = generate_proof(
-        req.row_titles.to_owned(),
-        req.row_contents.to_owned(),
-        req.row_selectors.to_owned(),
-    );
+    queue: web::Data<JobQueue>,
+    cache: web::Data<ProofCache>,
+    webhooks: web::Data<Webhooks>,
+    context: web::Data<ZkdocContext>,
+    caller: Caller,
+    request_id: RequestId,
+) -> Result<impl Responder, ApiError> {
+    caller.require(&[Role::Holder])?;
+    let job_id = submit_proof(
+        req.into_inner(),
+        &queue,
+        cache.into_inner(),
+        webhooks.into_inner(),
+        context.into_inner(),
+        JobId::new_v4(),
+        request_id,
+    )?;
+
+    Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/v1/jobs/{job_id}")))
+        .json(GenerateProofResponse { job_id }))
+}
+
+/// Submits a proof job under `job_id`, or answers from the proof cache. Requests without a holder
+/// secret are kept along with the job, to be saved if the server shuts down before the job
+/// finishes.
+fn submit_proof(
+    mut req: GenerateCommitmentAndProofRequest,
+    queue: &JobQueue,
+    cache: Arc<ProofCache>,
+    webhooks: Arc<Webhooks>,
+    context: Arc<ZkdocContext>,
+    job_id: JobId,
+    request_id: RequestId,
+) -> Result<JobId, ApiError> {
+    validate_selectors(&req.row_selectors)?;
+    // the holder secret is never written to disk, so holder bound jobs are not resumed
+    let request = req
+        .holder_secret
+        .is_none()
+        .then(|| serde_json::to_string(&req).expect("requests should serialize"));
+    let callback_url = req.callback_url.take();
+    if let Some(url) = &callback_url {
+        webhooks.check_url(url)?;
+    }
+    let holder_key = req
+        .holder_secret
+        .as_deref()
+        .map(|holder_secret| {
+            HolderKey::from_secret_hex(holder_secret)
+                .ok_or_else(|| ApiError::new(ErrorCode::InvalidHolderKey, "invalid holder secret"))
+        })
+        .transpose()?;
+
+    let cache_key = (req.cache && cache.enabled()).then(|| {
+        let commitment = match &holder_key {
+            Some(holder_key) => get_holder_bound_commitment(
+                req.row_titles.to_owned(),
+                req.row_contents.to_owned(),
+                holder_key.public_key(),
+            )
+            .expect("holder keys have valid public keys"),
+            None => get_file_commitment_and_selected_row(
+                req.row_titles.to_owned(),
+                req.row_contents.to_owned(),
+                req.row_selectors.to_owned(),
+            ),
+        };
+        cache::cache_key(&commitment, &req.row_selectors, holder_key.is_some())
+    });
+    let job_id = match cache_key.and_then(|key| cache.get(&key, Instant::now())) {
+        Some(proof) => {
+            let job_id = queue.insert_cached(job_id, proof);
+            tracing::info!(%job_id, "proof taken from the cache");
+            job_id
+        }
+        None => {
+            // FYI this runs for 30+ seconds
+            let job_id = queue
+                .submit(job_id, request, move |job| {
+                    // a span of its own, as a child would hold the request span open until the
+                    // proof
+                    let _span =
+                        tracing::info_span!("proof_job", %request_id, job_id = %job.id()).entered();
+                    let proof = context.generate_proof(
+                        req.row_titles,
+                        req.row_contents,
+                        req.row_selectors,
+                        holder_key.as_ref(),
+                        job.cancellation(),
+                        |phase| job.phase(phase),
+                    )?;
+                    if let Some(key) = cache_key {
+                        cache.insert(key, proof.clone(), Instant::now());
+                    }
+                    Ok(proof)
+                })
+                .map_err(|err| match err {
+                    SubmitError::QueueFull => {
+                        ApiError::new(ErrorCode::QueueFull, "proof queue is full")
+                    }
+                    SubmitError::Closed => ApiError::new(ErrorCode::Unavailable, "shutting down"),
+                })?;
+            tracing::info!(%job_id, "queued proof job");
+            job_id
+        }
+    };
+    if let Some(url) = callback_url {
+        queue.on_finished(&job_id, move |job| webhooks.notify(url, job));
+    }
+
+    Ok(job_id)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, body = Job),
+        (status = 404, description = "`job_not_found`", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}")]
+async fn job_handler(
+    id: web::Path<JobId>,
+    queue: web::Data<JobQueue>,
+    caller: Caller,
+) -> Result<web::Json<Job>, ApiError> {
+    caller.require(&[Role::Holder])?;
+    queue.get(&id).map(web::Json).ok_or_else(job_not_found)
+}
+
+/// Cancels a queued or running job
+#[utoipa::path(
+    delete,
+    path = "/v1/jobs/{id}",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, body = Job),
+        (status = 404, description = "`job_not_found`", body = ApiError),
+    )
+)]
+#[delete("/jobs/{id}")]
+async fn cancel_job_handler(
+    id: web::Path<JobId>,
+    queue: web::Data<JobQueue>,
+    caller: Caller,
+) -> Result<web::Json<Job>, ApiError> {
+    caller.require(&[Role::Holder])?;
+    queue.cancel(&id).map(web::Json).ok_or_else(job_not_found)
+}
 
-    Ok(web::Json(GenerateProofResponse { proof }))
+/// Lists the delivery attempts of the webhook of a job, kept after the job itself is dropped
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}/deliveries",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, body = DeliveriesResponse),
+        (status = 404, description = "`job_not_found`", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}/deliveries")]
+async fn deliveries_handler(
+    id: web::Path<JobId>,
+    queue: web::Data<JobQueue>,
+    store: web::Data<Store>,
+    caller: Caller,
+) -> Result<web::Json<DeliveriesResponse>, ApiError> {
+    caller.require(&[Role::Holder])?;
+    let deliveries = store.deliveries(&id)?;
+    if deliveries.is_empty() && queue.get(&id).is_none() {
+        return Err(job_not_found());
+    }
+
+    Ok(web::Json(DeliveriesResponse { deliveries }))
+}
+
+/// Streams the job as Server-Sent Events, one event per update named after the job status,
+/// until the job is finished
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}/events",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, description = "`event: <status>` and `data: <job>` per update", content_type = "text/event-stream", body = String),
+        (status = 404, description = "`job_not_found`", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}/events")]
+async fn job_events_handler(
+    id: web::Path<JobId>,
+    queue: web::Data<JobQueue>,
+    caller: Caller,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(&[Role::Holder])?;
+    let (job, updates) = queue.subscribe(&id).ok_or_else(job_not_found)?;
+    let events = job_updates(job, updates).map(|job| Ok::<_, actix_web::Error>(sse_event(&job)));
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+/// The job followed by its updates, up to the finished job.
+fn job_updates(job: Job, updates: broadcast::Receiver<Job>) -> impl Stream<Item = Job> {
+    stream::unfold(Some((Some(job), updates)), |state| async move {
+        let (next, mut updates) = state?;
+        let job = match next {
+            Some(job) => job,
+            None => loop {
+                match updates.recv().await {
+                    Ok(job) => break job,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            },
+        };
+        let state = (!job.is_finished()).then_some((None, updates));
+        Some((job, state))
+    })
+}
+
+fn sse_event(job: &Job) -> Bytes {
+    let event = serde_json::to_value(job).expect("jobs should serialize");
+    Bytes::from(format!(
+        "event: {}\ndata: {event}\n\n",
+        event["status"].as_str().unwrap()
+    ))
 }
 
+/// Answers `valid: false` for proofs that do not verify, and a 400 when the commitment or the
+/// proof can not be read at all
+#[utoipa::path(
+    post,
+    path = "/v1/verify-proof",
+    request_body = ProofVerificationRequest,
+    responses(
+        (status = 200, body = ProofVerificationResponse),
+        (status = 400, description = "`invalid_request`, `invalid_commitment` or `proof_malformed`", body = ApiError),
+        (status = 404, description = "`document_not_found`", body = ApiError),
+        (status = 413, description = "`payload_too_large`, the body or the proof is over the limit", body = ApiError),
+    )
+)]
 #[post("/verify-proof")]
-async fn verify_proof_handler(req: web::Json<ProofVerificationRequest>) -> Result<impl Responder> {
+async fn verify_proof_handler(
+    req: web::Json<ProofVerificationRequest>,
+    context: web::Data<ZkdocContext>,
+    store: web::Data<Store>,
+    limits: web::Data<Limits>,
+    caller: Caller,
+) -> Result<impl Responder, ApiError> {
+    caller.require(&[Role::Verifier])?;
+    verify_proof(&req, &context, &store, &limits).map(web::Json)
+}
+
+fn verify_proof(
+    req: &ProofVerificationRequest,
+    context: &ZkdocContext,
+    store: &Store,
+    limits: &Limits,
+) -> Result<ProofVerificationResponse, ApiError> {
+    check_proof_size(&req.proof, limits)?;
+    let (commitment, revoked) = resolve_commitment(req, store)?;
+    if revoked == Some(true) {
+        metrics::counter!(VERIFICATIONS, "result" => "revoked").increment(1);
+        return Ok(ProofVerificationResponse {
+            valid: false,
+            revoked,
+        });
+    }
+
     let row_accumulator = get_selected_row(req.row_title.to_owned(), req.row_content.to_owned());
-    let is_valid = verify_correct_selector(
-        req.commitment.to_owned(),
-        row_accumulator,
-        req.proof.to_owned(),
-    );
+    let is_valid = context
+        .verify_proof(&commitment, &row_accumulator, &req.proof, req.holder_bound)
+        .map_err(|err| match err {
+            VerificationError::InvalidCommitment => invalid_commitment(),
+            VerificationError::MalformedProof => ApiError::new(
+                ErrorCode::ProofMalformed,
+                "proof is truncated or holds invalid curve points",
+            ),
+            VerificationError::InvalidRowAccumulator => {
+                unreachable!("row accumulators are computed from the row")
+            }
+        })?;
+
+    Ok(ProofVerificationResponse {
+        valid: is_valid,
+        revoked,
+    })
+}
+
+/// Verifies many proofs at once, malformed proofs and revoked documents are reported as failed.
+/// Errors about an item give its `index` in `details`.
+#[utoipa::path(
+    post,
+    path = "/v1/verify-batch",
+    request_body = BatchVerificationRequest,
+    responses(
+        (status = 200, body = BatchVerificationResponse),
+        (status = 400, description = "`invalid_request` or `invalid_commitment`", body = ApiError),
+        (status = 404, description = "`document_not_found`", body = ApiError),
+        (status = 413, description = "`payload_too_large`, the body, a proof or the number of items is over the limit", body = ApiError),
+    )
+)]
+async fn verify_batch_handler(
+    req: web::Json<BatchVerificationRequest>,
+    store: web::Data<Store>,
+    limits: web::Data<Limits>,
+    context: web::Data<ZkdocContext>,
+    caller: Caller,
+) -> Result<impl Responder, ApiError> {
+    caller.require(&[Role::Verifier])?;
+    if req.items.len() > limits.batch_items {
+        return Err(ApiError::new(
+            ErrorCode::PayloadTooLarge,
+            format!("batch is over the {} items limit", limits.batch_items),
+        ));
+    }
+    let mut revoked = Vec::new();
+    let mut items = Vec::new();
+    for (index, item) in req.items.iter().enumerate() {
+        let with_index = |err: ApiError| err.with_details(json!({ "index": index }));
+        check_proof_size(&item.proof, &limits).map_err(with_index)?;
+        let (commitment, is_revoked) = resolve_commitment(item, &store).map_err(with_index)?;
+        fp_from_hex(&commitment).ok_or_else(|| with_index(invalid_commitment()))?;
+        if is_revoked == Some(true) {
+            metrics::counter!(VERIFICATIONS, "result" => "revoked").increment(1);
+            revoked.push(index);
+        } else {
+            items.push((index, item, commitment));
+        }
+    }
 
-    Ok(web::Json(ProofVerificationResponse { valid: is_valid }))
+    // indexes of the verified items in the request
+    let indexes = items.iter().map(|(index, ..)| *index).collect::<Vec<_>>();
+    let items = items
+        .into_iter()
+        .map(|(_, item, commitment)| BatchVerificationItem {
+            commitment,
+            row_accumulator: get_selected_row(
+                item.row_title.to_owned(),
+                item.row_content.to_owned(),
+            ),
+            proof: item.proof.to_owned(),
+            holder_bound: item.holder_bound,
+        })
+        .collect::<Vec<_>>();
+    let result = web::block(move || context.verify_batch(&items)).await?;
+    let mut failed = result
+        .failed
+        .into_iter()
+        .map(|i| indexes[i])
+        .collect::<Vec<_>>();
+    failed.extend(revoked);
+    failed.sort_unstable();
+
+    Ok(web::Json(BatchVerificationResponse {
+        valid: failed.is_empty(),
+        failed,
+    }))
+}
+
+/// Registers the commitment of an issued document, so that proofs can be verified against its
+/// id
+#[utoipa::path(
+    post,
+    path = "/v1/documents",
+    request_body = RegisterDocumentRequest,
+    responses(
+        (status = 201, body = Document, headers(("Location" = String, description = "URL of the document"))),
+        (status = 400, description = "`invalid_request` or `invalid_commitment`", body = ApiError),
+    )
+)]
+#[post("/documents")]
+async fn register_document_handler(
+    req: web::Json<RegisterDocumentRequest>,
+    store: web::Data<Store>,
+    caller: Caller,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(&[Role::Issuer])?;
+    caller.require_issuer(&req.issuer_id)?;
+    let req = req.into_inner();
+    fp_from_hex(&req.commitment).ok_or_else(invalid_commitment)?;
+    if req.issuer_id.is_empty() || req.document_type.is_empty() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "issuer_id and document_type should not be empty",
+        ));
+    }
+
+    let document = store.register_document(NewDocument {
+        commitment: req.commitment,
+        issuer_id: req.issuer_id,
+        document_type: req.document_type,
+        issued_at: req.issued_at,
+    })?;
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/v1/documents/{}", document.id)))
+        .json(document))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/documents/{id}",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, body = Document),
+        (status = 404, description = "`document_not_found`", body = ApiError),
+    )
+)]
+#[get("/documents/{id}")]
+async fn document_handler(
+    id: web::Path<DocumentId>,
+    store: web::Data<Store>,
+    caller: Caller,
+) -> Result<web::Json<Document>, ApiError> {
+    caller.require(&[Role::Issuer, Role::Verifier])?;
+    let document = store.document(&id)?.ok_or_else(document_not_found)?;
+    caller.require_issuer(&document.issuer_id)?;
+
+    Ok(web::Json(document))
+}
+
+/// Lists the documents of an issuer, a page at a time
+#[utoipa::path(
+    get,
+    path = "/v1/documents",
+    params(DocumentsQuery),
+    responses(
+        (status = 200, body = DocumentsResponse),
+        (status = 400, description = "`invalid_request`", body = ApiError),
+    )
+)]
+#[get("/documents")]
+async fn documents_handler(
+    query: web::Query<DocumentsQuery>,
+    store: web::Data<Store>,
+    caller: Caller,
+) -> Result<web::Json<DocumentsResponse>, ApiError> {
+    caller.require(&[Role::Issuer])?;
+    caller.require_issuer(&query.issuer_id)?;
+    let limit = query.limit.unwrap_or(MAX_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            format!("limit should be between 1 and {MAX_PAGE_SIZE}"),
+        ));
+    }
+
+    // one more document tells whether there is a next page
+    let mut documents = store.documents_by_issuer(&query.issuer_id, limit + 1, query.offset)?;
+    let next_offset = (documents.len() > limit as usize).then(|| query.offset + limit);
+    documents.truncate(limit as usize);
+
+    Ok(web::Json(DocumentsResponse {
+        documents,
+        next_offset,
+    }))
+}
+
+/// Marks a document revoked, proofs verified against it are no longer valid
+#[utoipa::path(
+    post,
+    path = "/v1/documents/{id}/revoke",
+    params(("id" = String, Path, format = Uuid)),
+    responses(
+        (status = 200, body = Document),
+        (status = 404, description = "`document_not_found`", body = ApiError),
+    )
+)]
+#[post("/documents/{id}/revoke")]
+async fn revoke_document_handler(
+    id: web::Path<DocumentId>,
+    store: web::Data<Store>,
+    caller: Caller,
+) -> Result<web::Json<Document>, ApiError> {
+    caller.require(&[Role::Issuer])?;
+    let document = store.document(&id)?.ok_or_else(document_not_found)?;
+    caller.require_issuer(&document.issuer_id)?;
+
+    store
+        .revoke_document(&id)?
+        .map(web::Json)
+        .ok_or_else(document_not_found)
+}
+
+/// Stores a presentation for a verifier to fetch by id, until it expires
+#[utoipa::path(
+    post,
+    path = "/v1/presentations",
+    request_body = CreatePresentationRequest,
+    responses(
+        (status = 201, body = PresentationResponse, headers(("Location" = String, description = "URL of the presentation"))),
+        (status = 400, description = "`invalid_request` or `invalid_commitment`", body = ApiError),
+        (status = 404, description = "`document_not_found`", body = ApiError),
+        (status = 413, description = "`payload_too_large`, the body or the proof is over the limit", body = ApiError),
+    )
+)]
+#[post("/presentations")]
+async fn create_presentation_handler(
+    req: web::Json<CreatePresentationRequest>,
+    store: web::Data<Store>,
+    limits: web::Data<Limits>,
+    presentations: web::Data<Presentations>,
+    caller: Caller,
+) -> Result<HttpResponse, ApiError> {
+    caller.require(&[Role::Holder])?;
+    let CreatePresentationRequest {
+        presentation,
+        ttl_secs,
+        single_use,
+    } = req.into_inner();
+    check_proof_size(&presentation.proof, &limits)?;
+    let (commitment, _) = resolve_commitment(&presentation, &store)?;
+    fp_from_hex(&commitment).ok_or_else(invalid_commitment)?;
+    let ttl_secs = ttl_secs.unwrap_or(presentations.ttl_secs);
+    if ttl_secs == 0 || ttl_secs > presentations.max_ttl_secs {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            format!(
+                "ttl_secs should be between 1 and {}",
+                presentations.max_ttl_secs
+            ),
+        ));
+    }
+
+    let presentation = store.create_presentation(NewPresentation {
+        proof: presentation.proof,
+        row_title: presentation.row_title,
+        row_content: presentation.row_content,
+        commitment: presentation.commitment,
+        document_id: presentation.document_id,
+        holder_bound: presentation.holder_bound,
+        single_use,
+        ttl_secs,
+    })?;
+    tracing::info!(presentation_id = %presentation.id, "stored presentation");
+
+    let url = format!("/v1/presentations/{}", presentation.id);
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, url.clone()))
+        .json(PresentationResponse {
+            id: presentation.id,
+            url,
+            expires_at: presentation.expires_at,
+            single_use: presentation.single_use,
+        }))
+}
+
+/// Fetches a presentation and verifies its proof. A single use presentation is deleted once
+/// verified, whether its proof is valid or not, and kept when verifying fails with an error.
+#[utoipa::path(
+    get,
+    path = "/v1/presentations/{id}",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, body = PresentationVerificationResponse),
+        (status = 400, description = "`proof_malformed`", body = ApiError),
+        (status = 404, description = "`presentation_not_found`", body = ApiError),
+    )
+)]
+#[get("/presentations/{id}")]
+async fn presentation_handler(
+    id: web::Path<PresentationId>,
+    context: web::Data<ZkdocContext>,
+    store: web::Data<Store>,
+    limits: web::Data<Limits>,
+    caller: Caller,
+) -> Result<web::Json<PresentationVerificationResponse>, ApiError> {
+    caller.require(&[Role::Verifier])?;
+    let Presentation {
+        proof,
+        row_title,
+        row_content,
+        commitment,
+        document_id,
+        holder_bound,
+        single_use,
+        ..
+    } = store
+        .presentation(&id)?
+        .ok_or_else(presentation_not_found)?;
+    let req = ProofVerificationRequest {
+        proof,
+        row_title,
+        row_content,
+        commitment,
+        document_id,
+        holder_bound,
+    };
+    let verifier_store = store.clone();
+    let (req, verification) = web::block(move || {
+        let verification = verify_proof(&req, &context, &verifier_store, &limits);
+        (req, verification)
+    })
+    .await?;
+    let verification = verification?;
+    // a concurrent fetch may have used it up meanwhile
+    if single_use && !store.consume_presentation(&id)? {
+        return Err(presentation_not_found());
+    }
+
+    Ok(web::Json(PresentationVerificationResponse {
+        row_title: req.row_title,
+        row_content: req.row_content,
+        commitment: req.commitment,
+        document_id: req.document_id,
+        holder_bound: req.holder_bound,
+        verification,
+    }))
+}
+
+/// Mounts the routes of the mode, the others are not found.
+fn api_routes(cfg: &mut web::ServiceConfig, mode: Mode, limits: &Limits) {
+    if mode.serves(Role::Issuer) {
+        cfg.service(generate_commitment_handler)
+            .service(register_document_handler)
+            .service(documents_handler)
+            .service(revoke_document_handler);
+    }
+    if mode.serves(Role::Holder) {
+        cfg.service(generate_proof_handler)
+            .service(job_handler)
+            .service(cancel_job_handler)
+            .service(job_events_handler)
+            .service(deliveries_handler)
+            .service(create_presentation_handler);
+    }
+    if mode.serves(Role::Verifier) {
+        cfg.service(verify_proof_handler)
+            .service(presentation_handler)
+            .service(
+                web::resource("/verify-batch")
+                    .app_data(
+                        web::JsonConfig::default()
+                            .limit(limits.batch_json_body)
+                            .error_handler(error::json_error),
+                    )
+                    .route(web::post().to(verify_batch_handler)),
+            );
+    }
+    if mode.serves(Role::Issuer) || mode.serves(Role::Verifier) {
+        cfg.service(document_handler);
+    }
+}
+
+/// Logs events along with the spans they happened in, and a line with the durations of each
+/// span as it closes. The `log` records of actix are forwarded too.
+fn init_logger(config: &Config) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.log.filter))
+        .with_span_events(FmtSpan::CLOSE);
+    match config.log.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+/// Loads the proving parameters, generating and saving them on first start.
+fn load_context(config: &Config) -> std::io::Result<ZkdocContext> {
+    match &config.params_path {
+        Some(path) if path.exists() => ZkdocContext::load(path),
+        Some(path) => {
+            let context = ZkdocContext::new();
+            context.save(path)?;
+            Ok(context)
+        }
+        None => Ok(ZkdocContext::new()),
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("Running on 8080");
-    std::env::set_var("RUST_LOG", "actix_web=info");
-    env_logger::init();
+    let mut args = Args::parse();
+    let command = args.command.take();
+    let config = Config::load(args).unwrap_or_else(|err| {
+        eprintln!("invalid configuration: {err}");
+        process::exit(1);
+    });
+    if let Some(command) = command {
+        if let Err(err) = admin::run(command, &config) {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    init_logger(&config);
+    let metrics = telemetry::install().unwrap_or_else(|err| {
+        eprintln!("failed to install the metrics recorder: {err}");
+        process::exit(1);
+    });
+    let metrics = web::Data::new(metrics);
+    if !config.auth.enabled {
+        tracing::warn!("authentication is disabled, anyone can call every route");
+    }
+
+    let context = load_context(&config).unwrap_or_else(|err| {
+        let path = config.params_path.as_ref().unwrap();
+        eprintln!("failed to load params from {}: {err}", path.display());
+        process::exit(1);
+    });
+    let context = web::Data::new(context);
+    let store = match &config.store_path {
+        Some(path) => Store::open(path),
+        None => {
+            tracing::warn!("documents are kept in memory, set store_path to persist them");
+            Store::open_in_memory()
+        }
+    };
+    let store = web::Data::new(store.unwrap_or_else(|err| {
+        eprintln!("failed to open the store: {err}");
+        process::exit(1);
+    }));
+    let queue = web::Data::new(JobQueue::new(
+        config.proving.workers,
+        config.proving.max_queued_jobs,
+        config.proof_timeout(),
+    ));
+    let cache = web::Data::new(ProofCache::new(config.proof_cache.clone()));
+    let webhooks = web::Data::new(Webhooks::new(config.webhooks.clone(), store.clone()));
+
+    let readiness = web::Data::new(Readiness::default());
+    load_keys(config.mode, context.clone(), readiness.clone());
+    sweep_presentations(store.clone());
+    // jobs saved on shutdown are only kept by a store on disk, and only resumed by servers
+    // proving, others leaving them saved
+    let persistent_store = config.store_path.as_ref().map(|_| store.clone());
+    if let Some(store) = persistent_store.clone() {
+        if config.mode.serves(Role::Holder) {
+            let (queue, cache) = (queue.clone(), cache.clone());
+            shutdown::resume_jobs(store, queue, cache, webhooks.clone(), context.clone());
+        }
+    }
+
+    let rate_limiter = web::Data::new(RateLimiter::new(config.rate_limit.clone()));
+    if !config.rate_limit.enabled {
+        tracing::warn!("rate limiting is disabled");
+    }
 
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header();
+    if let Some(grpc_bind) = config.grpc_bind {
+        let listener = std::net::TcpListener::bind(grpc_bind)?;
+        let service = grpc::ZkdocService {
+            context: context.clone(),
+            queue: queue.clone(),
+            cache: cache.clone(),
+            webhooks: webhooks.clone(),
+            store: store.clone(),
+            rate_limiter: rate_limiter.clone(),
+            limits: config.limits.clone(),
+            auth_enabled: config.auth.enabled,
+            mode: config.mode,
+        };
+        grpc::spawn(listener, service)?;
+        tracing::info!("Serving gRPC on {grpc_bind}");
+    }
+
+    tracing::info!("Running on {} in {} mode", config.bind, config.mode);
+    let app_config = config.clone();
+    let app_readiness = readiness.clone();
+    let app_queue = queue.clone();
+    let mut server = HttpServer::new(move || {
+        let (queue, readiness) = (&app_queue, &app_readiness);
+        let config = &app_config;
+        let mut cors = Cors::default().allow_any_method().allow_any_header();
+        if config.allows_any_origin() {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &config.cors.allowed_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
 
         App::new()
+            .app_data(queue.clone())
+            .app_data(cache.clone())
+            .app_data(webhooks.clone())
+            .app_data(context.clone())
+            .app_data(store.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(readiness.clone())
+            .app_data(web::Data::new(config.limits.clone()))
+            .app_data(web::Data::new(config.presentations.clone()))
+            .app_data(web::Data::new(AuthEnabled(config.auth.enabled)))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(config.limits.json_body)
+                    .error_handler(error::json_error),
+            )
+            .app_data(web::PathConfig::default().error_handler(error::path_error))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error))
+            .wrap_fn(ratelimit::rate_limit)
+            .wrap_fn(auth::authenticate)
             .wrap(cors)
-            .wrap(middleware::Logger::default())
-            .wrap(middleware::Logger::new("[{method} {uri} {status} {response_time}ms {response_length}b]\n{request}\n{response}"))
+            .wrap_fn(error::catch_panic)
+            .wrap_fn(telemetry::trace_request)
             .service(hello)
-            .service(generate_commitment_handler)
-            .service(generate_proof_handler)
-            .service(verify_proof_handler)
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+            .service(ready)
+            .service(openapi::openapi_handler)
+            .service(telemetry::metrics_handler)
+            .service(
+                web::scope("/v1").configure(|cfg| api_routes(cfg, config.mode, &config.limits)),
+            )
+            // unversioned routes of clients predating `/v1`
+            .configure(|cfg| api_routes(cfg, config.mode, &config.limits))
+            .default_service(web::to(error::not_found))
+    });
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+
+    // signals are handled by `shutdown`, which drains the proof jobs before stopping the server
+    let server = server
+        .disable_signals()
+        .shutdown_timeout(shutdown::CONNECTIONS_TIMEOUT)
+        .bind(config.bind)?
+        .run();
+    actix_web::rt::spawn(shutdown::on_signal(
+        server.handle(),
+        queue,
+        persistent_store,
+        readiness,
+        config.drain_timeout(),
+    ));
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use actix_web::http::StatusCode;
+    use actix_web::{web, App};
+    use serde_json::json;
+    use zkdoc_sdk::services::ZkdocContext;
+
+    use super::{api_routes, ready, Readiness};
+    use crate::auth::{self, AuthEnabled};
+    use crate::config::{Limits, Mode};
+    use crate::store::{NewPresentation, Store};
+
+    #[actix_web::test]
+    async fn test_modes() {
+        let readiness = web::Data::new(Readiness::default());
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(readiness.clone())
+                .service(ready)
+                .configure(|cfg| api_routes(cfg, Mode::Verifier, &Limits::default())),
+        )
+        .await;
+        let status = |method: &str, uri: &str| {
+            let req = actix_web::test::TestRequest::default()
+                .method(method.parse().unwrap())
+                .uri(uri)
+                .to_request();
+            let app = &app;
+            async move { actix_web::test::call_service(app, req).await.status() }
+        };
+
+        assert_eq!(
+            status("GET", "/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        readiness.keys_loaded.store(true, Ordering::Release);
+        assert_eq!(status("GET", "/ready").await, StatusCode::OK);
+        readiness.draining.store(true, Ordering::Release);
+        assert_eq!(
+            status("GET", "/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        // mounted routes fail without a key rather than being not found
+        for (method, uri) in [
+            ("POST", "/verify-proof"),
+            ("POST", "/verify-batch"),
+            ("GET", "/documents/5f0b6c1e-8f0e-4f43-9a57-2c1d7c6b9a10"),
+            ("GET", "/presentations/0123456789abcdef01234567"),
+        ] {
+            assert_ne!(status(method, uri).await, StatusCode::NOT_FOUND, "{uri}");
+        }
+        for (method, uri) in [
+            ("POST", "/generate-proof"),
+            ("POST", "/generate-commitment"),
+            ("GET", "/jobs/5f0b6c1e-8f0e-4f43-9a57-2c1d7c6b9a10"),
+            ("POST", "/documents"),
+            ("POST", "/presentations"),
+        ] {
+            assert_eq!(status(method, uri).await, StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_verify_batch() {
+        let limits = Limits {
+            batch_items: 2,
+            ..Limits::default()
+        };
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::open_in_memory().unwrap()))
+                .app_data(web::Data::new(AuthEnabled(false)))
+                .app_data(web::Data::new(limits.clone()))
+                .app_data(web::Data::new(ZkdocContext::new()))
+                .wrap_fn(auth::authenticate)
+                .configure(|cfg| api_routes(cfg, Mode::Verifier, &limits)),
+        )
+        .await;
+        let item = json!({
+            "proof": vec![0; 32],
+            "row_title": "name",
+            "row_content": "alice",
+            "commitment": format!("0x{}", "0".repeat(64)),
+        });
+        let verify = |items: Vec<serde_json::Value>| {
+            let req = actix_web::test::TestRequest::post()
+                .uri("/verify-batch")
+                .set_json(json!({ "items": items }))
+                .to_request();
+            actix_web::test::call_service(&app, req)
+        };
+
+        let response = verify(vec![item.clone(); 3]).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = verify(vec![item.clone(); 2]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(body, json!({ "valid": false, "failed": [0, 1] }));
+    }
+
+    #[actix_web::test]
+    async fn test_presentation_kept_on_error() {
+        let store = web::Data::new(Store::open_in_memory().unwrap());
+        let presentation = store
+            .create_presentation(NewPresentation {
+                proof: vec![0; 32],
+                row_title: "name".to_owned(),
+                row_content: "alice".to_owned(),
+                commitment: Some(format!("0x{}", "0".repeat(64))),
+                document_id: None,
+                holder_bound: false,
+                single_use: true,
+                ttl_secs: 60,
+            })
+            .unwrap();
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(web::Data::new(AuthEnabled(false)))
+                .app_data(web::Data::new(Limits::default()))
+                .app_data(web::Data::new(ZkdocContext::new()))
+                .wrap_fn(auth::authenticate)
+                .configure(|cfg| api_routes(cfg, Mode::Verifier, &Limits::default())),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let req = actix_web::test::TestRequest::get()
+                .uri(&format!("/presentations/{}", presentation.id))
+                .to_request();
+            let response = actix_web::test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+            assert_eq!(body["code"], "proof_malformed");
+        }
+        assert!(store.presentation(&presentation.id).unwrap().is_some());
+    }
 }
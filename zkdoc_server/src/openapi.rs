@@ -0,0 +1,111 @@
+//! OpenAPI description of the `/v1` routes, derived from the request and response types.
+
+use actix_web::{get, HttpResponse};
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+};
+use utoipa::openapi::Components;
+use utoipa::OpenApi;
+
+/// Version of the API, bumped on every schema change. The major version is the route prefix.
+pub const API_VERSION: &str = "1.8.3";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zkdoc_server",
+        description = "REST API for generating and verifying zkdoc proofs"
+    ),
+    paths(
+        crate::generate_commitment_handler,
+        crate::generate_proof_handler,
+        crate::job_handler,
+        crate::cancel_job_handler,
+        crate::job_events_handler,
+        crate::deliveries_handler,
+        crate::verify_proof_handler,
+        crate::verify_batch_handler,
+        crate::register_document_handler,
+        crate::document_handler,
+        crate::documents_handler,
+        crate::revoke_document_handler,
+        crate::create_presentation_handler,
+        crate::presentation_handler,
+    )
+)]
+struct ApiDoc;
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    openapi.info.version = API_VERSION.to_owned();
+    // the package has no license to report
+    openapi.info.license = None;
+    // every documented route takes an API key, in either header
+    let components = openapi.components.get_or_insert_with(Components::new);
+    components.add_security_scheme(
+        "bearer",
+        SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+    );
+    components.add_security_scheme(
+        "api_key",
+        SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+    );
+    openapi.security = Some(vec![
+        SecurityRequirement::new("bearer", Vec::<String>::new()),
+        SecurityRequirement::new("api_key", Vec::<String>::new()),
+    ]);
+    openapi
+}
+
+#[get("/openapi.json")]
+async fn openapi_handler() -> HttpResponse {
+    HttpResponse::Ok().json(openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use serde_json::Value;
+
+    use super::{openapi, API_VERSION};
+
+    /// The schema of [`API_VERSION`], regenerated with
+    /// `ZKDOC_UPDATE_OPENAPI=1 cargo test -p zkdoc_server` once the version is bumped
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    #[test]
+    fn test_openapi_compat() {
+        let openapi = openapi();
+        let major = API_VERSION.split('.').next().unwrap();
+        for path in openapi.paths.paths.keys() {
+            assert!(
+                path.starts_with(&format!("/v{major}/")),
+                "{path} should be under /v{major}"
+            );
+        }
+
+        let schema = openapi.to_pretty_json().unwrap() + "\n";
+        let snapshot = fs::read_to_string(SNAPSHOT).unwrap_or_default();
+        if schema == snapshot {
+            return;
+        }
+
+        let snapshot_version = serde_json::from_str::<Value>(&snapshot)
+            .ok()
+            .and_then(|snapshot| snapshot["info"]["version"].as_str().map(str::to_owned));
+        assert_ne!(
+            snapshot_version.as_deref(),
+            Some(API_VERSION),
+            "the API schema changed, bump API_VERSION and regenerate openapi.json"
+        );
+        if env::var_os("ZKDOC_UPDATE_OPENAPI").is_some() {
+            fs::write(SNAPSHOT, schema).unwrap();
+        } else {
+            panic!(
+                "openapi.json describes version {snapshot_version:?}, regenerate it for \
+                 {API_VERSION} with ZKDOC_UPDATE_OPENAPI=1"
+            );
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Token bucket rate limiting. Every client, an API key or else the peer IP, has a bucket of
+//! `burst` tokens refilled at `per_minute`, and each request takes the cost of its route.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, Error};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use serde_json::json;
+
+use crate::auth::{self, ApiKeyId};
+use crate::config::RateLimit;
+use crate::error::{ApiError, ErrorCode};
+
+/// Buckets kept before the least recently used ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Client {
+    Key(ApiKeyId),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Key of the bucket in [`Buckets::by_use`]
+    used: u64,
+}
+
+/// Buckets of the clients, along with their order of use to find the least recently used one
+#[derive(Default)]
+struct Buckets {
+    by_client: HashMap<Client, Bucket>,
+    by_use: BTreeMap<u64, Client>,
+    uses: u64,
+}
+
+pub struct RateLimiter {
+    config: RateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimit) -> Self {
+        Self {
+            config,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Cost of a route pattern, without its version prefix.
+    fn cost(&self, route: &str) -> u32 {
+        self.config.routes.get(route).copied().unwrap_or(1)
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        f64::from(self.config.per_minute) / 60.0
+    }
+
+    /// Takes `cost` tokens from the bucket of `client`, or returns how long until there are
+    /// enough.
+    fn take(&self, client: Client, cost: u32, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.config.burst);
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            by_client,
+            by_use,
+            uses,
+        } = &mut *buckets;
+        if by_client.len() >= MAX_BUCKETS && !by_client.contains_key(&client) {
+            if let Some((_, lru)) = by_use.pop_first() {
+                by_client.remove(&lru);
+            }
+        }
+
+        *uses += 1;
+        let bucket = by_client.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+            used: *uses,
+        });
+        by_use.remove(&bucket.used);
+        by_use.insert(*uses, client);
+        bucket.used = *uses;
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.tokens_per_sec()).min(burst);
+        bucket.updated = now;
+
+        let cost = f64::from(cost);
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (cost - bucket.tokens) / self.tokens_per_sec(),
+            ))
+        }
+    }
+
+    /// Charges a call of `route` to `client`, failing with `429 rate_limited` once its bucket
+    /// is empty.
+    pub fn check(&self, client: Client, route: &str) -> Result<(), ApiError> {
+        let cost = self.cost(route);
+        if !self.config.enabled || cost == 0 {
+            return Ok(());
+        }
+        self.take(client, cost, Instant::now()).map_err(|wait| {
+            let retry_after = wait.as_secs() + 1;
+            ApiError::new(ErrorCode::RateLimited, "too many requests")
+                .with_details(json!({ "retry_after": retry_after }))
+                .with_retry_after(retry_after)
+        })
+    }
+}
+
+/// Middleware charging every request to its client, answering `429 rate_limited` with a
+/// `Retry-After` header once the bucket is empty. It runs after [`auth::authenticate`] to tell
+/// keys apart.
+pub fn rate_limit<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<EitherBody<B>>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let client = match (auth::request_key_id(&req), req.peer_addr()) {
+        (Some(key_id), _) => Some(Client::Key(key_id)),
+        (None, Some(addr)) => Some(Client::Ip(addr.ip())),
+        (None, None) => None,
+    };
+
+    if let (Some(limiter), Some(client)) = (limiter, client) {
+        let pattern = req.match_pattern().unwrap_or_else(|| req.path().to_owned());
+        let route = pattern.strip_prefix("/v1").unwrap_or(&pattern);
+        let route = if route.is_empty() { "/" } else { route };
+        if let Err(error) = limiter.check(client, route) {
+            let response = req.error_response(error).map_into_right_body();
+            return Box::pin(async move { Ok(response) });
+        }
+    }
+
+    service
+        .call(req)
+        .map(|response| response.map(ServiceResponse::map_into_left_body))
+        .boxed_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    use actix_web::http::{header, Method, StatusCode};
+    use actix_web::{web, App, HttpResponse};
+
+    use super::{rate_limit, Client, RateLimiter, MAX_BUCKETS};
+    use crate::config::RateLimit;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimit {
+            enabled: true,
+            burst: 10,
+            per_minute: 60,
+            routes: BTreeMap::from([("/expensive".to_owned(), 6), ("/free".to_owned(), 0)]),
+        })
+    }
+
+    #[test]
+    fn test_take() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let ip = Client::Ip(IpAddr::from([127, 0, 0, 1]));
+        let other = Client::Ip(IpAddr::from([127, 0, 0, 2]));
+
+        assert_eq!(limiter.cost("/expensive"), 6);
+        assert_eq!(limiter.cost("/cheap"), 1);
+        assert_eq!(limiter.take(ip, 6, now), Ok(()));
+        assert_eq!(limiter.take(ip, 4, now), Ok(()));
+        assert_eq!(limiter.take(ip, 6, now), Err(Duration::from_secs(6)));
+        assert_eq!(limiter.take(other, 6, now), Ok(()));
+
+        // one token a second
+        let later = now + Duration::from_secs(4);
+        assert_eq!(limiter.take(ip, 6, later), Err(Duration::from_secs(2)));
+        assert_eq!(limiter.take(ip, 1, later), Ok(()));
+        let much_later = now + Duration::from_secs(3600);
+        assert_eq!(limiter.take(ip, 10, much_later), Ok(()));
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let client = |i: u32| Client::Ip(IpAddr::from(i.to_be_bytes()));
+
+        assert_eq!(limiter.take(client(0), 10, now), Ok(()));
+        assert_eq!(limiter.take(client(1), 10, now), Ok(()));
+        for i in 2..MAX_BUCKETS as u32 {
+            assert_eq!(limiter.take(client(i), 1, now), Ok(()));
+        }
+        // in use again, so the first client is now the least recently used
+        assert_eq!(limiter.take(client(1), 0, now), Ok(()));
+
+        assert_eq!(limiter.take(client(u32::MAX), 1, now), Ok(()));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_client.len(), MAX_BUCKETS);
+        assert_eq!(buckets.by_use.len(), MAX_BUCKETS);
+        drop(buckets);
+        assert_eq!(limiter.take(client(0), 10, now), Ok(()));
+        assert!(limiter.take(client(1), 1, now).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit() {
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter()))
+                .wrap_fn(rate_limit)
+                .route("/v1/expensive", web::post().to(HttpResponse::Ok))
+                .route("/free", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let call = |uri: &str, method| {
+            let req = actix_web::test::TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .peer_addr("127.0.0.1:1234".parse().unwrap())
+                .to_request();
+            actix_web::test::call_service(&app, req)
+        };
+
+        for _ in 0..20 {
+            let response = call("/free", Method::GET).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = call("/v1/expensive", Method::POST).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = call("/v1/expensive", Method::POST).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = response.headers().get(header::RETRY_AFTER).unwrap();
+        assert!(matches!(retry_after.to_str().unwrap(), "2" | "3"));
+        let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(body["code"], "rate_limited");
+    }
+}
@@ -0,0 +1,231 @@
+//! Graceful shutdown, so that deploys do not kill proofs halfway. On SIGTERM or SIGINT the server
+//! reports unavailable and stops taking proof jobs, running proofs are given until the drain
+//! timeout to finish, then the jobs left unfinished are saved to the store and resumed on the
+//! next start. Holder secrets are not saved, so holder bound jobs fail on restart instead.
+
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use actix_web::dev::ServerHandle;
+use actix_web::rt::{signal, time};
+use actix_web::web;
+use zkdoc_sdk::services::ZkdocContext;
+
+use crate::cache::ProofCache;
+use crate::error::{ApiError, ErrorCode};
+use crate::jobs::{JobId, JobQueue};
+use crate::store::{SavedJob, Store};
+use crate::telemetry::RequestId;
+use crate::webhooks::Webhooks;
+use crate::{submit_proof, GenerateCommitmentAndProofRequest, Readiness};
+
+/// Seconds connections still open once drained are given to close, e.g. job update streams
+pub const CONNECTIONS_TIMEOUT: u64 = 5;
+/// How often the running jobs are counted while draining
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+/// Proofs finished while draining stay served this long, for the clients polling their job
+const FINISHED_GRACE: Duration = Duration::from_secs(5);
+/// How often resuming saved jobs is retried while the queue is full
+const RESUME_RETRY: Duration = Duration::from_secs(1);
+
+/// Waits for a shutdown signal, then drains the proof jobs and stops the server. The jobs left
+/// unfinished are saved to `store`, or dropped when it is not persistent.
+pub async fn on_signal(
+    server: ServerHandle,
+    queue: web::Data<JobQueue>,
+    store: Option<web::Data<Store>>,
+    readiness: web::Data<Readiness>,
+    timeout: Duration,
+) {
+    wait_for_signal().await;
+    tracing::info!("shutting down, draining proof jobs for up to {timeout:?}");
+    readiness.draining.store(true, Ordering::Release);
+    queue.close();
+
+    let was_running = queue.running();
+    let deadline = Instant::now() + timeout;
+    while queue.running() > 0 && Instant::now() < deadline {
+        time::sleep(DRAIN_POLL).await;
+    }
+    let running = queue.running();
+    if running > 0 {
+        tracing::warn!(running, "proof jobs still running after the drain timeout");
+    }
+    if running < was_running {
+        time::sleep(FINISHED_GRACE).await;
+    }
+
+    let jobs = queue.unfinished_requests();
+    match store {
+        _ if jobs.is_empty() => {}
+        Some(store) => match store.save_queued_jobs(&jobs) {
+            Ok(()) => tracing::info!(jobs = jobs.len(), "saved unfinished proof jobs"),
+            Err(err) => tracing::error!("failed to save unfinished proof jobs: {err}"),
+        },
+        None => tracing::warn!(
+            jobs = jobs.len(),
+            "dropping unfinished proof jobs, set store_path to resume them on restart"
+        ),
+    }
+
+    server.stop(true).await;
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use signal::unix::{signal, SignalKind};
+
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("listening for SIGTERM should not fail");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    signal::ctrl_c()
+        .await
+        .expect("listening for Ctrl-C should not fail");
+}
+
+/// Submits again the jobs saved on the last shutdown, under their former ids, from a thread of
+/// its own. Jobs are submitted past a full queue once it has room again, and each saved job is
+/// only deleted once resumed or failed, so that none is lost if the server stops meanwhile. Jobs
+/// saved without their request, or whose request is no longer valid, fail.
+pub fn resume_jobs(
+    store: web::Data<Store>,
+    queue: web::Data<JobQueue>,
+    cache: web::Data<ProofCache>,
+    webhooks: web::Data<Webhooks>,
+    context: web::Data<ZkdocContext>,
+) {
+    let jobs = match store.queued_jobs() {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            tracing::error!("failed to load the saved proof jobs: {err}");
+            return;
+        }
+    };
+    if jobs.is_empty() {
+        return;
+    }
+
+    let spawned = thread::Builder::new()
+        .name("resume-jobs".to_owned())
+        .spawn(move || {
+            for job in jobs {
+                let job_id = job.job_id;
+                let resumed = loop {
+                    match resume_job(&job, &queue, &cache, &webhooks, &context) {
+                        Err(err) if err.code == ErrorCode::QueueFull => thread::sleep(RESUME_RETRY),
+                        // shutting down again, the job stays saved
+                        Err(err) if err.code == ErrorCode::Unavailable => return,
+                        resumed => break resumed,
+                    }
+                };
+                match resumed {
+                    Ok(_) => tracing::info!(%job_id, "resumed proof job"),
+                    Err(err) => {
+                        tracing::warn!(%job_id, "failed to resume proof job: {}", err.message);
+                        queue.insert_failed(job_id, err);
+                    }
+                }
+                if let Err(err) = store.delete_queued_job(job.id) {
+                    tracing::error!(%job_id, "failed to delete the saved proof job: {err}");
+                }
+            }
+        });
+    spawned.expect("spawning the resume thread should not fail");
+}
+
+fn resume_job(
+    job: &SavedJob,
+    queue: &JobQueue,
+    cache: &web::Data<ProofCache>,
+    webhooks: &web::Data<Webhooks>,
+    context: &web::Data<ZkdocContext>,
+) -> Result<JobId, ApiError> {
+    let Some(request) = &job.request else {
+        return Err(ApiError::new(
+            ErrorCode::ProvingFailed,
+            "the server restarted before the proof, submit the request again",
+        ));
+    };
+    let req = serde_json::from_str::<GenerateCommitmentAndProofRequest>(request)
+        .map_err(|err| ApiError::new(ErrorCode::InvalidRequest, err.to_string()))?;
+    submit_proof(
+        req,
+        queue,
+        cache.clone().into_inner(),
+        webhooks.clone().into_inner(),
+        context.clone().into_inner(),
+        job.job_id,
+        RequestId::generate(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use actix_web::web;
+    use serde_json::json;
+    use zkdoc_sdk::services::ZkdocContext;
+
+    use super::resume_jobs;
+    use crate::cache::ProofCache;
+    use crate::config;
+    use crate::error::ErrorCode;
+    use crate::jobs::{JobId, JobQueue, JobStatus};
+    use crate::store::Store;
+    use crate::webhooks::Webhooks;
+
+    #[test]
+    fn test_resume_failing_jobs() {
+        let store = web::Data::new(Store::open_in_memory().unwrap());
+        let queue = web::Data::new(JobQueue::new(1, 4, Duration::from_secs(60)));
+        let invalid_selector = json!({
+            "row_titles": vec!["-"; 10],
+            "row_contents": vec!["-"; 10],
+            "row_selectors": [2, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        });
+        let jobs = [
+            (JobId::new_v4(), None),
+            (JobId::new_v4(), Some("{".to_owned())),
+            (JobId::new_v4(), Some(invalid_selector.to_string())),
+        ];
+        store.save_queued_jobs(&jobs).unwrap();
+
+        resume_jobs(
+            store.clone(),
+            queue.clone(),
+            web::Data::new(ProofCache::new(config::ProofCache::default())),
+            web::Data::new(Webhooks::new(config::Webhooks::default(), store.clone())),
+            web::Data::new(ZkdocContext::new()),
+        );
+        for _ in 0..500 {
+            if store.queued_jobs().unwrap().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(store.queued_jobs().unwrap(), []);
+
+        let codes = jobs.map(|(id, _)| match queue.get(&id).unwrap().status {
+            JobStatus::Failed { error } => error.code,
+            status => panic!("job {id} is {status:?}"),
+        });
+        assert_eq!(
+            codes,
+            [
+                ErrorCode::ProvingFailed,
+                ErrorCode::InvalidRequest,
+                ErrorCode::InvalidSelector
+            ]
+        );
+    }
+}
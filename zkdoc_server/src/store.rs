@@ -0,0 +1,742 @@
+//! Embedded SQLite store of the commitments registered by issuers, so that verifiers can look
+//! documents up by id, of the API keys along with their audit log, of the presentations holders
+//! relay to verifiers and of the webhook deliveries. The schema is migrated on open, following
+//! `PRAGMA user_version`.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand_core::{OsRng, RngCore};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::{ApiKey, ApiKeyId, Role};
+use crate::jobs::JobId;
+
+pub type DocumentId = Uuid;
+/// Random hex, short enough for a link while not guessable
+pub type PresentationId = String;
+
+/// Schema changes, applied in order. Never edit a released migration, append a new one.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE documents (
+        id TEXT PRIMARY KEY,
+        commitment TEXT NOT NULL,
+        issuer_id TEXT NOT NULL,
+        document_type TEXT NOT NULL,
+        issued_at INTEGER NOT NULL,
+        registered_at INTEGER NOT NULL,
+        revoked_at INTEGER
+    );
+    CREATE INDEX documents_by_issuer ON documents (issuer_id, registered_at, id);
+",
+    "
+    CREATE TABLE api_keys (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        role TEXT NOT NULL,
+        issuer_id TEXT,
+        key_hash TEXT NOT NULL UNIQUE,
+        created_at INTEGER NOT NULL,
+        revoked_at INTEGER
+    );
+    CREATE TABLE audit_log (
+        id INTEGER PRIMARY KEY,
+        key_id TEXT NOT NULL REFERENCES api_keys (id),
+        method TEXT NOT NULL,
+        path TEXT NOT NULL,
+        status INTEGER NOT NULL,
+        at INTEGER NOT NULL
+    );
+    CREATE INDEX audit_log_by_key ON audit_log (key_id, id);
+",
+    "
+    CREATE TABLE presentations (
+        id TEXT PRIMARY KEY,
+        proof BLOB NOT NULL,
+        row_title TEXT NOT NULL,
+        row_content TEXT NOT NULL,
+        commitment TEXT,
+        document_id TEXT,
+        holder_bound INTEGER NOT NULL,
+        single_use INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX presentations_by_expiry ON presentations (expires_at);
+",
+    "
+    CREATE TABLE webhook_deliveries (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        status INTEGER,
+        error TEXT,
+        at INTEGER NOT NULL
+    );
+    CREATE INDEX webhook_deliveries_by_job ON webhook_deliveries (job_id, id);
+",
+    "
+    CREATE TABLE queued_jobs (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        request TEXT,
+        saved_at INTEGER NOT NULL
+    );
+",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Document {
+    #[schema(value_type = String, format = Uuid)]
+    pub id: DocumentId,
+    pub commitment: String,
+    pub issuer_id: String,
+    /// Kind of document, e.g. `passport`, free for the issuer to choose
+    pub document_type: String,
+    /// Unix timestamps in milliseconds
+    pub issued_at: u64,
+    pub registered_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+impl Document {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: parse_column(row, "id")?,
+            commitment: row.get("commitment")?,
+            issuer_id: row.get("issuer_id")?,
+            document_type: row.get("document_type")?,
+            issued_at: row.get("issued_at")?,
+            registered_at: row.get("registered_at")?,
+            revoked_at: row.get("revoked_at")?,
+        })
+    }
+}
+
+impl ApiKey {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: parse_column(row, "id")?,
+            name: row.get("name")?,
+            role: parse_column(row, "role")?,
+            issuer_id: row.get("issuer_id")?,
+            created_at: row.get("created_at")?,
+            revoked_at: row.get("revoked_at")?,
+        })
+    }
+}
+
+/// A proof along with the row it discloses, posted by a holder for a verifier to fetch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Presentation {
+    pub id: PresentationId,
+    pub proof: Vec<u8>,
+    pub row_title: String,
+    pub row_content: String,
+    /// Either the commitment or the registered document the proof is verified against
+    pub commitment: Option<String>,
+    pub document_id: Option<DocumentId>,
+    pub holder_bound: bool,
+    /// Whether the presentation is deleted once fetched
+    pub single_use: bool,
+    /// Unix timestamps in milliseconds
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+impl Presentation {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let document_id = row
+            .get::<_, Option<String>>("document_id")?
+            .is_some()
+            .then(|| parse_column(row, "document_id"))
+            .transpose()?;
+        Ok(Self {
+            id: row.get("id")?,
+            proof: row.get("proof")?,
+            row_title: row.get("row_title")?,
+            row_content: row.get("row_content")?,
+            commitment: row.get("commitment")?,
+            document_id,
+            holder_bound: row.get("holder_bound")?,
+            single_use: row.get("single_use")?,
+            created_at: row.get("created_at")?,
+            expires_at: row.get("expires_at")?,
+        })
+    }
+}
+
+/// A request made with an API key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Unix timestamp in milliseconds
+    pub at: u64,
+}
+
+/// An attempt at delivering the webhook of a job.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Delivery {
+    pub url: String,
+    /// 1 for the first attempt
+    pub attempt: u32,
+    /// HTTP status of the answer, unset when the request failed
+    pub status: Option<u16>,
+    /// Why the attempt failed, unset once delivered
+    pub error: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub at: u64,
+}
+
+/// A proof job saved on shutdown, see [`Store::save_queued_jobs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SavedJob {
+    /// Row of the saved job, to delete it once resumed
+    pub id: i64,
+    pub job_id: JobId,
+    /// Serialized request, unset when it was not saved
+    pub request: Option<String>,
+}
+
+/// A document to register, see [`Store::register_document`].
+pub struct NewDocument {
+    pub commitment: String,
+    pub issuer_id: String,
+    pub document_type: String,
+    /// Registration time when unset
+    pub issued_at: Option<u64>,
+}
+
+/// A presentation to store, see [`Store::create_presentation`].
+pub struct NewPresentation {
+    pub proof: Vec<u8>,
+    pub row_title: String,
+    pub row_content: String,
+    pub commitment: Option<String>,
+    pub document_id: Option<DocumentId>,
+    pub holder_bound: bool,
+    pub single_use: bool,
+    /// Seconds the presentation can be fetched for
+    pub ttl_secs: u64,
+}
+
+/// An API key to create, see [`Store::create_api_key`].
+pub struct NewApiKey {
+    pub name: String,
+    pub role: Role,
+    pub issuer_id: Option<String>,
+    /// See [`crate::auth::hash_key`]
+    pub key_hash: String,
+}
+
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if missing.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::migrate(Connection::open(path)?)
+    }
+
+    /// Opens a database that lives as long as the store.
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::migrate(Connection::open_in_memory()?)
+    }
+
+    fn migrate(mut connection: Connection) -> rusqlite::Result<Self> {
+        let transaction = connection.transaction()?;
+        let version: usize = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for migration in MIGRATIONS.iter().skip(version) {
+            transaction.execute_batch(migration)?;
+        }
+        transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+        transaction.commit()?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn register_document(&self, document: NewDocument) -> rusqlite::Result<Document> {
+        let registered_at = now();
+        let document = Document {
+            id: Uuid::new_v4(),
+            commitment: document.commitment,
+            issuer_id: document.issuer_id,
+            document_type: document.document_type,
+            issued_at: document.issued_at.unwrap_or(registered_at),
+            registered_at,
+            revoked_at: None,
+        };
+
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO documents
+                (id, commitment, issuer_id, document_type, issued_at, registered_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                document.id.to_string(),
+                document.commitment,
+                document.issuer_id,
+                document.document_type,
+                document.issued_at,
+                document.registered_at,
+            ],
+        )?;
+
+        Ok(document)
+    }
+
+    pub fn document(&self, id: &DocumentId) -> rusqlite::Result<Option<Document>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT * FROM documents WHERE id = ?1",
+                [id.to_string()],
+                Document::from_row,
+            )
+            .optional()
+    }
+
+    /// Documents of an issuer in registration order, skipping the first `offset`.
+    pub fn documents_by_issuer(
+        &self,
+        issuer_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> rusqlite::Result<Vec<Document>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT * FROM documents WHERE issuer_id = ?1
+            ORDER BY registered_at, id LIMIT ?2 OFFSET ?3",
+        )?;
+        let documents = statement
+            .query_map(params![issuer_id, limit, offset], Document::from_row)?
+            .collect();
+        documents
+    }
+
+    /// Marks a document revoked, keeping the time it was first revoked at.
+    pub fn revoke_document(&self, id: &DocumentId) -> rusqlite::Result<Option<Document>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "UPDATE documents SET revoked_at = coalesce(revoked_at, ?2)
+                WHERE id = ?1 RETURNING *",
+                params![id.to_string(), now()],
+                Document::from_row,
+            )
+            .optional()
+    }
+
+    pub fn create_presentation(
+        &self,
+        presentation: NewPresentation,
+    ) -> rusqlite::Result<Presentation> {
+        let mut id = [0; 12];
+        OsRng.fill_bytes(&mut id);
+        let created_at = now();
+        let presentation = Presentation {
+            id: hex::encode(id),
+            proof: presentation.proof,
+            row_title: presentation.row_title,
+            row_content: presentation.row_content,
+            commitment: presentation.commitment,
+            document_id: presentation.document_id,
+            holder_bound: presentation.holder_bound,
+            single_use: presentation.single_use,
+            created_at,
+            expires_at: created_at + presentation.ttl_secs * 1000,
+        };
+
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO presentations (id, proof, row_title, row_content, commitment,
+                document_id, holder_bound, single_use, created_at, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                presentation.id,
+                presentation.proof,
+                presentation.row_title,
+                presentation.row_content,
+                presentation.commitment,
+                presentation.document_id.map(|id| id.to_string()),
+                presentation.holder_bound,
+                presentation.single_use,
+                presentation.created_at,
+                presentation.expires_at,
+            ],
+        )?;
+
+        Ok(presentation)
+    }
+
+    /// Fetches a presentation unless it expired.
+    pub fn presentation(&self, id: &str) -> rusqlite::Result<Option<Presentation>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT * FROM presentations WHERE id = ?1 AND expires_at > ?2",
+                params![id, now()],
+                Presentation::from_row,
+            )
+            .optional()
+    }
+
+    /// Deletes a single use presentation once answered, returning whether it was still there,
+    /// so that only one of concurrent fetches gets it.
+    pub fn consume_presentation(&self, id: &str) -> rusqlite::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let deleted = connection.execute("DELETE FROM presentations WHERE id = ?1", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Deletes the expired presentations, returning how many there were.
+    pub fn delete_expired_presentations(&self) -> rusqlite::Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM presentations WHERE expires_at <= ?1", [now()])
+    }
+
+    pub fn create_api_key(&self, key: NewApiKey) -> rusqlite::Result<ApiKey> {
+        let api_key = ApiKey {
+            id: Uuid::new_v4(),
+            name: key.name,
+            role: key.role,
+            issuer_id: key.issuer_id,
+            created_at: now(),
+            revoked_at: None,
+        };
+
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO api_keys (id, name, role, issuer_id, key_hash, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                api_key.id.to_string(),
+                api_key.name,
+                api_key.role.name(),
+                api_key.issuer_id,
+                key.key_hash,
+                api_key.created_at,
+            ],
+        )?;
+
+        Ok(api_key)
+    }
+
+    pub fn api_key_by_hash(&self, key_hash: &str) -> rusqlite::Result<Option<ApiKey>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT * FROM api_keys WHERE key_hash = ?1",
+                [key_hash],
+                ApiKey::from_row,
+            )
+            .optional()
+    }
+
+    /// All the keys, revoked ones included, in creation order.
+    pub fn api_keys(&self) -> rusqlite::Result<Vec<ApiKey>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT * FROM api_keys ORDER BY created_at, id")?;
+        let keys = statement.query_map([], ApiKey::from_row)?.collect();
+        keys
+    }
+
+    /// Revokes a key, keeping the time it was first revoked at.
+    pub fn revoke_api_key(&self, id: &ApiKeyId) -> rusqlite::Result<Option<ApiKey>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "UPDATE api_keys SET revoked_at = coalesce(revoked_at, ?2)
+                WHERE id = ?1 RETURNING *",
+                params![id.to_string(), now()],
+                ApiKey::from_row,
+            )
+            .optional()
+    }
+
+    pub fn audit(
+        &self,
+        key_id: &ApiKeyId,
+        method: &str,
+        path: &str,
+        status: u16,
+    ) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO audit_log (key_id, method, path, status, at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![key_id.to_string(), method, path, status, now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn log_delivery(&self, job_id: &JobId, delivery: &Delivery) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO webhook_deliveries (job_id, url, attempt, status, error, at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                job_id.to_string(),
+                delivery.url,
+                delivery.attempt,
+                delivery.status,
+                delivery.error,
+                delivery.at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The delivery attempts of the webhook of a job, in order.
+    pub fn deliveries(&self, job_id: &JobId) -> rusqlite::Result<Vec<Delivery>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare_cached("SELECT * FROM webhook_deliveries WHERE job_id = ?1 ORDER BY id")?;
+        let deliveries = statement
+            .query_map([job_id.to_string()], |row| {
+                Ok(Delivery {
+                    url: row.get("url")?,
+                    attempt: row.get("attempt")?,
+                    status: row.get("status")?,
+                    error: row.get("error")?,
+                    at: row.get("at")?,
+                })
+            })?
+            .collect();
+        deliveries
+    }
+
+    /// Saves the jobs left unfinished on shutdown, to resume them on restart. Jobs without a
+    /// request, such as holder bound ones whose secret is not written to disk, are saved to be
+    /// failed on restart.
+    pub fn save_queued_jobs(&self, jobs: &[(JobId, Option<String>)]) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let saved_at = now();
+        for (job_id, request) in jobs {
+            transaction.execute(
+                "INSERT INTO queued_jobs (job_id, request, saved_at) VALUES (?1, ?2, ?3)",
+                params![job_id.to_string(), request, saved_at],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    /// The saved jobs, in the order they were queued. They are kept until deleted with
+    /// [`Store::delete_queued_job`], so that jobs not resumed yet are not lost.
+    pub fn queued_jobs(&self) -> rusqlite::Result<Vec<SavedJob>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT * FROM queued_jobs ORDER BY id")?;
+        let jobs = statement
+            .query_map([], |row| {
+                Ok(SavedJob {
+                    id: row.get("id")?,
+                    job_id: parse_column(row, "job_id")?,
+                    request: row.get("request")?,
+                })
+            })?
+            .collect();
+        jobs
+    }
+
+    pub fn delete_queued_job(&self, id: i64) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM queued_jobs WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// The last `limit` requests made with a key, most recent first.
+    pub fn audit_log(&self, key_id: &ApiKeyId, limit: u32) -> rusqlite::Result<Vec<AuditEntry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT * FROM audit_log WHERE key_id = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let entries = statement
+            .query_map(params![key_id.to_string(), limit], |row| {
+                Ok(AuditEntry {
+                    method: row.get("method")?,
+                    path: row.get("path")?,
+                    status: row.get("status")?,
+                    at: row.get("at")?,
+                })
+            })?
+            .collect();
+        entries
+    }
+}
+
+/// Reads a text column into any type parsed from strings, such as ids.
+fn parse_column<T>(row: &Row, column: &str) -> rusqlite::Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let index = row.as_ref().column_index(column)?;
+    row.get::<_, String>(index)?.parse().map_err(|err: T::Err| {
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.to_string().into())
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time should be after the epoch")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{NewDocument, NewPresentation, Store};
+
+    fn new_document(issuer_id: &str) -> NewDocument {
+        NewDocument {
+            commitment: "0x01".to_owned(),
+            issuer_id: issuer_id.to_owned(),
+            document_type: "passport".to_owned(),
+            issued_at: None,
+        }
+    }
+
+    #[test]
+    fn test_documents() {
+        let store = Store::open_in_memory().unwrap();
+        let document = store.register_document(new_document("acme")).unwrap();
+        assert_eq!(document.issued_at, document.registered_at);
+        assert_eq!(
+            store.document(&document.id).unwrap(),
+            Some(document.clone())
+        );
+        assert_eq!(store.document(&Uuid::new_v4()).unwrap(), None);
+
+        let revoked = store.revoke_document(&document.id).unwrap().unwrap();
+        assert!(revoked.is_revoked());
+        let revoked_again = store.revoke_document(&document.id).unwrap().unwrap();
+        assert_eq!(revoked_again.revoked_at, revoked.revoked_at);
+        assert_eq!(store.revoke_document(&Uuid::new_v4()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_documents_by_issuer() {
+        let store = Store::open_in_memory().unwrap();
+        let documents = (0..5)
+            .map(|_| store.register_document(new_document("acme")).unwrap())
+            .collect::<Vec<_>>();
+        store.register_document(new_document("other")).unwrap();
+
+        let mut listed = store.documents_by_issuer("acme", 2, 0).unwrap();
+        listed.extend(store.documents_by_issuer("acme", 2, 2).unwrap());
+        listed.extend(store.documents_by_issuer("acme", 2, 4).unwrap());
+        let mut expected = documents;
+        expected.sort_by_key(|document| (document.registered_at, document.id.to_string()));
+        assert_eq!(listed, expected);
+        assert!(store
+            .documents_by_issuer("nobody", 2, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    fn new_presentation(single_use: bool, ttl_secs: u64) -> NewPresentation {
+        NewPresentation {
+            proof: vec![1, 2, 3],
+            row_title: "name".to_owned(),
+            row_content: "Alice".to_owned(),
+            commitment: None,
+            document_id: Some(Uuid::new_v4()),
+            holder_bound: true,
+            single_use,
+            ttl_secs,
+        }
+    }
+
+    #[test]
+    fn test_presentations() {
+        let store = Store::open_in_memory().unwrap();
+        let presentation = store
+            .create_presentation(new_presentation(false, 60))
+            .unwrap();
+        assert_eq!(presentation.id.len(), 24);
+        assert_eq!(presentation.expires_at, presentation.created_at + 60_000);
+        for _ in 0..2 {
+            assert_eq!(
+                store.presentation(&presentation.id).unwrap(),
+                Some(presentation.clone())
+            );
+        }
+
+        let single_use = store
+            .create_presentation(new_presentation(true, 60))
+            .unwrap();
+        assert_ne!(single_use.id, presentation.id);
+        assert_eq!(
+            store.presentation(&single_use.id).unwrap(),
+            Some(single_use.clone())
+        );
+        assert!(store.consume_presentation(&single_use.id).unwrap());
+        assert!(!store.consume_presentation(&single_use.id).unwrap());
+        assert_eq!(store.presentation(&single_use.id).unwrap(), None);
+        assert_eq!(store.presentation("unknown").unwrap(), None);
+
+        let expired = store
+            .create_presentation(new_presentation(false, 0))
+            .unwrap();
+        assert_eq!(store.presentation(&expired.id).unwrap(), None);
+        assert_eq!(store.delete_expired_presentations().unwrap(), 1);
+        assert!(store.presentation(&presentation.id).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_queued_jobs() {
+        let store = Store::open_in_memory().unwrap();
+        assert_eq!(store.queued_jobs().unwrap(), []);
+
+        let jobs = [
+            (Uuid::new_v4(), Some("{\"a\":1}".to_owned())),
+            (Uuid::new_v4(), None),
+        ];
+        store.save_queued_jobs(&jobs).unwrap();
+        let saved = store.queued_jobs().unwrap();
+        let saved_jobs = saved
+            .iter()
+            .map(|job| (job.job_id, job.request.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(saved_jobs, jobs);
+
+        store.delete_queued_job(saved[0].id).unwrap();
+        assert_eq!(store.queued_jobs().unwrap(), &saved[1..]);
+        store.delete_queued_job(saved[1].id).unwrap();
+        assert_eq!(store.queued_jobs().unwrap(), []);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = std::env::temp_dir().join(format!("zkdoc-store-{}.db", std::process::id()));
+        let document = Store::open(&path)
+            .unwrap()
+            .register_document(new_document("acme"))
+            .unwrap();
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.document(&document.id).unwrap(), Some(document));
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Prometheus metrics, recorded by the SDK services and the server through the `metrics` facade
+//! and exported at `/metrics`, and the request spans correlating the logs of a request.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{ready, Ready};
+use std::thread;
+use std::time::Duration;
+
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{get, web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use metrics::{describe_counter, describe_gauge, Unit};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::Instrument;
+use uuid::Uuid;
+use zkdoc_sdk::services::telemetry as sdk;
+
+/// Proof jobs waiting for a proving worker
+pub const QUEUE_DEPTH: &str = "zkdoc_proof_queue_depth";
+/// Proof jobs being proven
+pub const ACTIVE_JOBS: &str = "zkdoc_proof_jobs_active";
+/// Finished proof jobs, labelled by `status`: `done`, `failed` or `cancelled`
+pub const FINISHED_JOBS: &str = "zkdoc_proof_jobs_total";
+/// Proof cache lookups, labelled by `result`: `hit` or `miss`
+pub const PROOF_CACHE: &str = "zkdoc_proof_cache_total";
+/// Total size of the cached proofs
+pub const PROOF_CACHE_BYTES: &str = "zkdoc_proof_cache_bytes";
+/// Webhook delivery attempts, labelled by `result`: `delivered` or `failed`
+pub const WEBHOOK_DELIVERIES: &str = "zkdoc_webhook_deliveries_total";
+
+/// Bucket bounds of the durations, from a commitment (~1ms) to a proof timing out
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0,
+];
+/// Histograms are folded into their buckets this often, whether or not they are scraped
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+fn builder() -> Result<PrometheusBuilder, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_owned()), DURATION_BUCKETS)
+}
+
+fn describe() {
+    sdk::describe();
+    describe_gauge!(QUEUE_DEPTH, "Proof jobs waiting for a proving worker");
+    describe_gauge!(ACTIVE_JOBS, "Proof jobs being proven");
+    describe_counter!(FINISHED_JOBS, "Finished proof jobs, by status");
+    describe_counter!(PROOF_CACHE, "Proof cache lookups, by result");
+    describe_counter!(WEBHOOK_DELIVERIES, "Webhook delivery attempts, by result");
+    describe_gauge!(
+        PROOF_CACHE_BYTES,
+        Unit::Bytes,
+        "Total size of the cached proofs"
+    );
+}
+
+/// Installs the global recorder, returning the handle rendering the metrics.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    let handle = builder()?.install_recorder()?;
+    describe();
+
+    let upkeep = handle.clone();
+    thread::Builder::new()
+        .name("metrics-upkeep".to_owned())
+        .spawn(move || loop {
+            thread::sleep(UPKEEP_INTERVAL);
+            upkeep.run_upkeep();
+        })
+        .expect("spawning the metrics upkeep thread should not fail");
+
+    Ok(handle)
+}
+
+#[get("/metrics")]
+async fn metrics_handler(handle: web::Data<PrometheusHandle>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(handle.render())
+}
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Id of a request, taken from its `X-Request-Id` header or generated, and sent back in the
+/// response header of the same name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// Ids from clients end up in the logs, so only short ids of safe characters are kept.
+    pub fn parse(id: &str) -> Option<Self> {
+        let valid = (1..=64).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        valid.then(|| Self(id.to_owned()))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Infallible>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let id = req.extensions().get::<RequestId>().cloned();
+        ready(Ok(id.unwrap_or_else(Self::generate)))
+    }
+}
+
+/// Middleware running each request in a `request` span with its id, method, path and once
+/// answered its status. The span closing serves as the access log.
+pub fn trace_request<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| RequestId::parse(value.to_str().ok()?))
+        .unwrap_or_else(RequestId::generate);
+    let peer = req
+        .peer_addr()
+        .map_or_else(|| "-".to_owned(), |addr| addr.ip().to_string());
+    let span = tracing::info_span!(
+        "request",
+        %request_id,
+        method = %req.method(),
+        path = req.path(),
+        peer,
+        status = tracing::field::Empty,
+    );
+    req.extensions_mut().insert(request_id.clone());
+    let response = span.in_scope(|| service.call(req));
+
+    async move {
+        let mut response = response.await;
+        let status = match &response {
+            Ok(response) => response.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+        tracing::Span::current().record("status", status.as_u16());
+        if let Ok(response) = &mut response {
+            let value = HeaderValue::from_str(&request_id.0).expect("ids are valid header values");
+            response.headers_mut().insert(REQUEST_ID, value);
+        }
+        response
+    }
+    .instrument(span)
+    .boxed_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpResponse};
+    use zkdoc_sdk::services::telemetry::{PROVING_DURATION, VERIFICATIONS};
+
+    use super::{builder, describe, metrics_handler, trace_request, RequestId, QUEUE_DEPTH};
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let recorder = builder().unwrap().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            describe();
+            metrics::gauge!(QUEUE_DEPTH).set(3.0);
+            metrics::counter!(VERIFICATIONS, "result" => "valid").increment(2);
+            metrics::histogram!(PROVING_DURATION, "circuit" => "plain").record(7.5);
+        });
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(handle))
+                .service(metrics_handler),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/metrics")
+            .to_request();
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+
+        assert!(body.contains("# TYPE zkdoc_proof_queue_depth gauge"));
+        assert!(body.contains("zkdoc_proof_queue_depth 3"));
+        assert!(body.contains(r#"zkdoc_verifications_total{result="valid"} 2"#));
+        assert!(body.contains("# TYPE zkdoc_proving_duration_seconds histogram"));
+        assert!(body.contains(r#"zkdoc_proving_duration_seconds_bucket{circuit="plain",le="5"} 0"#));
+        assert!(
+            body.contains(r#"zkdoc_proving_duration_seconds_bucket{circuit="plain",le="10"} 1"#)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_request_id() {
+        let app = actix_web::test::init_service(App::new().wrap_fn(trace_request).route(
+            "/",
+            web::get().to(|id: RequestId| async move { HttpResponse::Ok().body(id.0) }),
+        ))
+        .await;
+        let call = |id: Option<&str>| {
+            let mut req = actix_web::test::TestRequest::get().uri("/");
+            if let Some(id) = id {
+                req = req.insert_header(("X-Request-Id", id));
+            }
+            actix_web::test::call_service(&app, req.to_request())
+        };
+
+        let response = call(Some("client-id.1")).await;
+        assert_eq!(
+            response.headers().get("x-request-id").unwrap(),
+            "client-id.1"
+        );
+        let body = actix_web::test::read_body(response).await;
+        assert_eq!(body, "client-id.1");
+
+        for id in [None, Some(""), Some("with spaces"), Some(&"x".repeat(65))] {
+            let response = call(id).await;
+            let header = response
+                .headers()
+                .get("x-request-id")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned();
+            assert!(uuid::Uuid::parse_str(&header).is_ok());
+            let body = actix_web::test::read_body(response).await;
+            assert_eq!(body, header);
+        }
+    }
+}
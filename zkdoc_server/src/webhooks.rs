@@ -0,0 +1,516 @@
+//! Webhooks of the proof jobs, so that integrators need not poll. Once a job given a
+//! `callback_url` finishes, the job is posted to that URL as JSON, signed with HMAC-SHA256 so
+//! that receivers can tell it comes from the server. Deliveries are made from a thread of their
+//! own and retried with exponential backoff until the receiver answers with a 2xx status, every
+//! attempt being logged in the store. Callback URLs come from holders, so the server only
+//! connects to public addresses, unless the host is explicitly allowed.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::web;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config;
+use crate::error::{ApiError, ErrorCode};
+use crate::jobs::{Job, JobId};
+use crate::store::{Delivery, Store};
+use crate::telemetry::WEBHOOK_DELIVERIES;
+
+/// Header of the `sha256=<hex>` signature of `<timestamp>.<body>`
+pub const SIGNATURE: &str = "x-zkdoc-signature";
+/// Header of the Unix timestamp in seconds the payload was signed at
+pub const TIMESTAMP: &str = "x-zkdoc-timestamp";
+
+/// Signature sent in the [`SIGNATURE`] header, covering the timestamp so that receivers can
+/// reject replayed payloads.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// A payload waiting for its next attempt.
+struct Pending {
+    job_id: JobId,
+    url: String,
+    body: Vec<u8>,
+    attempt: u32,
+    due: Instant,
+}
+
+/// Whether an address may be reached by webhooks, excluding loopback, private, link-local and
+/// other special purpose ranges, e.g. `169.254.169.254` of cloud metadata services.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // benchmarking
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            // NAT64, embedding an IPv4 address in the last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., c, d] = ip.octets().map(u32::from);
+                let embedded = (u32::from(segments[6]) << 16) | (c << 8) | d;
+                return is_public(IpAddr::V4(Ipv4Addr::from(embedded)));
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // unique local
+                || (segments[0] & 0xfe00) == 0xfc00
+                // link-local
+                || (segments[0] & 0xffc0) == 0xfe80
+                // documentation
+                || segments[..2] == [0x2001, 0xdb8])
+        }
+    }
+}
+
+/// Resolves the hosts of the callback URLs to their public addresses only, so that a name
+/// resolving to an internal address, even after the URL was checked, is never connected to.
+#[derive(Clone)]
+struct PublicResolver {
+    /// Hosts connected to whatever they resolve to
+    allowed_hosts: Vec<String>,
+}
+
+impl PublicResolver {
+    fn allows(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+}
+
+impl ureq::Resolver for PublicResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let addresses = netloc.to_socket_addrs()?;
+        let host = netloc.rsplit_once(':').map_or(netloc, |(host, _)| host);
+        if self.allows(host) {
+            return Ok(addresses.collect());
+        }
+
+        let public = addresses
+            .filter(|address| is_public(address.ip()))
+            .collect::<Vec<_>>();
+        if public.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{host} resolves to no public address"),
+            ));
+        }
+        Ok(public)
+    }
+}
+
+pub struct Webhooks {
+    /// Unset when webhooks are disabled
+    sender: Option<mpsc::Sender<Pending>>,
+    resolver: PublicResolver,
+}
+
+impl Webhooks {
+    /// Spawns the delivery thread, unless webhooks are disabled for lack of a secret.
+    pub fn new(config: config::Webhooks, store: web::Data<Store>) -> Self {
+        let resolver = PublicResolver {
+            allowed_hosts: config.allowed_hosts,
+        };
+        let Some(secret) = config.secret else {
+            return Self {
+                sender: None,
+                resolver,
+            };
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let deliverer = Deliverer {
+            secret,
+            max_attempts: config.max_attempts,
+            backoff: Duration::from_secs(config.backoff_secs),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(config.timeout_secs))
+                .redirects(0)
+                .resolver(resolver.clone())
+                .build(),
+            store,
+        };
+        let spawned = thread::Builder::new()
+            .name("webhooks".to_owned())
+            .spawn(move || deliverer.run(receiver));
+        spawned.expect("spawning the webhooks thread should not fail");
+
+        Self {
+            sender: Some(sender),
+            resolver,
+        }
+    }
+
+    /// Checks the callback URL of a job before it is submitted, rejecting internal addresses
+    /// and `localhost` right away. Host names are not resolved here, as this runs on the
+    /// request threads, but on every delivery attempt, which never connects to internal
+    /// addresses.
+    pub fn check_url(&self, url: &str) -> Result<(), ApiError> {
+        if self.sender.is_none() {
+            return Err(ApiError::new(
+                ErrorCode::InvalidRequest,
+                "webhooks are disabled on this server",
+            ));
+        }
+        let parsed = ureq::post(url).request_url().ok();
+        let Some(parsed) = parsed.filter(|parsed| ["http", "https"].contains(&parsed.scheme()))
+        else {
+            return Err(ApiError::new(
+                ErrorCode::InvalidRequest,
+                "callback_url should be an http or https URL",
+            ));
+        };
+        let host = parsed.host();
+        let address = host.trim_start_matches('[').trim_end_matches(']');
+        let name = host.trim_end_matches('.').to_ascii_lowercase();
+        let internal = match address.parse::<IpAddr>() {
+            Ok(ip) => !is_public(ip),
+            Err(_) => name == "localhost" || name.ends_with(".localhost"),
+        };
+        if internal && !self.resolver.allows(host) {
+            return Err(ApiError::new(
+                ErrorCode::InvalidRequest,
+                format!("callback_url should reach a public address, not {host}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Enqueues the delivery of a finished job to `url`.
+    pub fn notify(&self, url: String, job: &Job) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let pending = Pending {
+            job_id: job.id,
+            url,
+            body: serde_json::to_vec(job).expect("jobs should serialize"),
+            attempt: 1,
+            due: Instant::now(),
+        };
+        // called while the job queue is locked, so a stopped thread is only logged
+        if sender.send(pending).is_err() {
+            tracing::error!(job_id = %job.id, "webhooks thread stopped, dropping the webhook");
+        }
+    }
+}
+
+struct Deliverer {
+    secret: String,
+    max_attempts: u32,
+    backoff: Duration,
+    agent: ureq::Agent,
+    store: web::Data<Store>,
+}
+
+impl Deliverer {
+    /// Delivers the payloads as they come, and the retries once they are due.
+    fn run(self, receiver: mpsc::Receiver<Pending>) {
+        let mut pending = Vec::<Pending>::new();
+        loop {
+            let next_due = pending.iter().map(|pending| pending.due).min();
+            let received = match next_due {
+                Some(due) => receiver.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(new) => pending.push(new),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => match next_due {
+                    // the retries are still made once nothing new comes
+                    Some(due) => thread::sleep(due.saturating_duration_since(Instant::now())),
+                    None => return,
+                },
+            }
+
+            let now = Instant::now();
+            let (due, waiting) = pending.into_iter().partition(|pending| pending.due <= now);
+            pending = waiting;
+            for due in due {
+                pending.extend(self.deliver(due));
+            }
+        }
+    }
+
+    /// Makes an attempt, returning the next one on failure unless it was the last.
+    fn deliver(&self, mut pending: Pending) -> Option<Pending> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time should be after the epoch");
+        let signature = sign(&self.secret, timestamp.as_secs(), &pending.body);
+        let result = self
+            .agent
+            .post(&pending.url)
+            .set("content-type", "application/json")
+            .set(TIMESTAMP, &timestamp.as_secs().to_string())
+            .set(SIGNATURE, &signature)
+            .send_bytes(&pending.body);
+        let (status, error) = match result {
+            Ok(response) if (200..300).contains(&response.status()) => {
+                (Some(response.status()), None)
+            }
+            Ok(response) | Err(ureq::Error::Status(_, response)) => (
+                Some(response.status()),
+                Some(format!("answered with status {}", response.status())),
+            ),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        let delivery = Delivery {
+            url: pending.url.clone(),
+            attempt: pending.attempt,
+            status,
+            error,
+            at: timestamp.as_millis() as u64,
+        };
+        if let Err(err) = self.store.log_delivery(&pending.job_id, &delivery) {
+            tracing::error!("failed to log the webhook delivery: {err}");
+        }
+        let job_id = pending.job_id;
+        let Some(error) = delivery.error else {
+            metrics::counter!(WEBHOOK_DELIVERIES, "result" => "delivered").increment(1);
+            tracing::info!(%job_id, attempt = pending.attempt, "delivered webhook");
+            return None;
+        };
+        metrics::counter!(WEBHOOK_DELIVERIES, "result" => "failed").increment(1);
+        if pending.attempt >= self.max_attempts {
+            tracing::warn!(%job_id, attempt = pending.attempt, "giving up on webhook: {error}");
+            return None;
+        }
+
+        let backoff = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(pending.attempt - 1));
+        tracing::info!(
+            %job_id,
+            attempt = pending.attempt,
+            "retrying webhook in {backoff:?}: {error}"
+        );
+        pending.attempt += 1;
+        pending.due = Instant::now() + backoff;
+        Some(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use actix_web::web;
+
+    use super::{is_public, sign, Webhooks, SIGNATURE, TIMESTAMP};
+    use crate::config;
+    use crate::jobs::{JobId, JobQueue};
+    use crate::store::Store;
+
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> &str {
+            let (_, value) = self
+                .headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .unwrap();
+            value
+        }
+    }
+
+    /// Stands in for the receiver of the webhooks, answering with the given statuses in turn.
+    fn receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&mut stream);
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        headers.push((name.to_owned(), value.to_owned()));
+                    }
+                }
+                let received = Received {
+                    headers,
+                    body: Vec::new(),
+                };
+                let length = received.header("content-length").parse().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                )
+                .unwrap();
+                sender.send(Received { body, ..received }).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn webhooks(store: &web::Data<Store>) -> Webhooks {
+        Webhooks::new(
+            config::Webhooks {
+                secret: Some("shh".to_owned()),
+                max_attempts: 2,
+                backoff_secs: 1,
+                timeout_secs: 5,
+                // the test receiver listens on loopback
+                allowed_hosts: vec!["127.0.0.1".to_owned()],
+            },
+            store.clone(),
+        )
+    }
+
+    #[test]
+    fn test_is_public() {
+        for public in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"] {
+            assert!(is_public(public.parse().unwrap()), "{public}");
+        }
+        for internal in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(internal.parse().unwrap()), "{internal}");
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign("shh", 1700000000, b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), 7 + 64);
+        assert_eq!(signature, sign("shh", 1700000000, b"{}"));
+        assert_ne!(signature, sign("shh", 1700000001, b"{}"));
+        assert_ne!(signature, sign("other", 1700000000, b"{}"));
+    }
+
+    #[test]
+    fn test_delivery() {
+        let store = web::Data::new(Store::open_in_memory().unwrap());
+        let webhooks = webhooks(&store);
+        assert!(webhooks.check_url("https://93.184.215.14/hook").is_ok());
+        // resolved when delivering only
+        assert!(webhooks.check_url("https://receiver.invalid/hook").is_ok());
+        assert!(webhooks.check_url("ftp://example.com").is_err());
+        assert!(webhooks.check_url("not a url").is_err());
+        for internal in [
+            "http://localhost:8080/hook",
+            "http://api.localhost./hook",
+            "http://127.0.0.2/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/hook",
+            "http://[::1]/hook",
+            "http://[::ffff:192.168.0.1]/hook",
+        ] {
+            assert!(webhooks.check_url(internal).is_err(), "{internal}");
+        }
+
+        let (url, received) = receiver(vec![500, 200]);
+        let queue = JobQueue::new(1, 1, Duration::from_secs(5));
+        let job_id = queue.insert_cached(JobId::new_v4(), vec![1, 2, 3]);
+        webhooks.notify(url.clone(), &queue.get(&job_id).unwrap());
+
+        let failed = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        let delivered = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        let job: serde_json::Value = serde_json::from_slice(&delivered.body).unwrap();
+        assert_eq!(job["id"], job_id.to_string());
+        assert_eq!(job["status"], "done");
+        assert_eq!(delivered.body, failed.body);
+        let timestamp = delivered.header(TIMESTAMP).parse().unwrap();
+        assert_eq!(
+            delivered.header(SIGNATURE),
+            sign("shh", timestamp, &delivered.body)
+        );
+
+        // logged once the answer is read
+        thread::sleep(Duration::from_millis(100));
+        let deliveries = store.deliveries(&job_id).unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].url, url);
+        assert_eq!(deliveries[0].status, Some(500));
+        assert!(deliveries[0].error.is_some());
+        assert_eq!(
+            (deliveries[1].attempt, deliveries[1].status),
+            (2, Some(200))
+        );
+        assert_eq!(deliveries[1].error, None);
+        // the second attempt waited for the backoff
+        assert!(deliveries[1].at - deliveries[0].at >= 1000);
+    }
+
+    #[test]
+    fn test_give_up() {
+        let store = web::Data::new(Store::open_in_memory().unwrap());
+        let webhooks = webhooks(&store);
+        let (url, received) = receiver(vec![503, 503, 200]);
+        let queue = JobQueue::new(1, 1, Duration::from_secs(5));
+        let job_id = queue.insert_cached(JobId::new_v4(), vec![1]);
+        webhooks.notify(url, &queue.get(&job_id).unwrap());
+
+        for _ in 0..2 {
+            received.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        // no third attempt
+        assert!(received.recv_timeout(Duration::from_secs(3)).is_err());
+        let deliveries = store.deliveries(&job_id).unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert!(deliveries.iter().all(|delivery| delivery.error.is_some()));
+
+        let disabled = Webhooks::new(config::Webhooks::default(), store);
+        assert!(disabled.check_url("https://example.com/hook").is_err());
+    }
+}